@@ -62,6 +62,17 @@ impl AABB{
         }
         self.x
     }
+    // false for boxes that reach infinity along some axis, like an infinite plane's
+    pub fn is_bounded(&self)->bool{
+        [self.x, self.y, self.z].iter().all(|ax| ax.min.is_finite() && ax.max.is_finite())
+    }
+    pub fn centroid(&self)->Vec3{
+        Vec3::new(0.5*(self.x.min+self.x.max), 0.5*(self.y.min+self.y.max), 0.5*(self.z.min+self.z.max))
+    }
+    pub fn surface_area(&self)->f64{
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        2.0*(dx*dy+dy*dz+dz*dx)
+    }
     pub fn longest_axis(&self)->i32{
         if self.x().size() > self.y().size(){
             return if self.x().size() > self.z().size(){
@@ -110,6 +121,21 @@ impl AABB{
         }
         true
     }
+    // slab test with the reciprocal direction precomputed, for BVH traversal
+    pub fn hit_inv(&self, origin: Vec3, inv_dir: Vec3, ray_t: Interval)->bool{
+        let mut t_min = ray_t.min();
+        let mut t_max = ray_t.max();
+        for (axis, ax) in [self.x, self.y, self.z].iter().enumerate(){
+            let t0 = (ax.min() - origin[axis])*inv_dir[axis];
+            let t1 = (ax.max() - origin[axis])*inv_dir[axis];
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            if t_max <= t_min{
+                return false
+            }
+        }
+        true
+    }
     pub fn pad_to_minimums(&mut self){
         let delta: f64 = 0.0001;
         if self.x.size() < delta{
@@ -1,123 +1,470 @@
 use crate::aabb::AABB as AABB;
 use crate::hittable::Hittable as Hittable;
+use crate::vec3::Vec3 as Vec3;
 use crate::Interval;
 use crate::Ray;
 use crate::Hit_record;
 
 use std::sync::Arc;
 use std::cmp::Ordering;
+use std::fmt;
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SplitMethod{
+    // halve the primitives at the median centroid of the longest axis
+    Median,
+    // binned surface area heuristic
+    Sah,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BvhOptions{
+    pub split: SplitMethod,
+    pub max_leaf_size: usize,
+    // relative cost of visiting a node and of intersecting one primitive
+    pub traversal_cost: f64,
+    pub intersection_cost: f64,
+    pub bins: usize,
+}
+
+impl SplitMethod{
+    pub const NAMES: &'static str = "sah, median";
+
+    pub fn from_name(name: &str)->Option<Self>{
+        match name{
+            "sah"=>Some(SplitMethod::Sah),
+            "median"=>Some(SplitMethod::Median),
+            _=>None,
+        }
+    }
+}
+
+impl Default for BvhOptions{
+    fn default()->Self{
+        Self{
+            split: SplitMethod::Sah,
+            max_leaf_size: 4,
+            traversal_cost: 1.0,
+            intersection_cost: 2.0,
+            bins: 16,
+        }
+    }
+}
+
+impl BvhOptions{
+    pub const MAX_BINS: usize = 64;
+
+    pub fn check(&self)->Result<(), String>{
+        if self.max_leaf_size < 1{
+            return Err("max_leaf_size must be at least 1".to_string())
+        }
+        if !(self.traversal_cost >= 0.0 && self.traversal_cost.is_finite()){
+            return Err(format!("traversal_cost must be a non-negative number, got {}", self.traversal_cost))
+        }
+        if !(self.intersection_cost > 0.0 && self.intersection_cost.is_finite()){
+            return Err(format!("intersection_cost must be a positive number, got {}", self.intersection_cost))
+        }
+        if self.bins < 2 || self.bins > Self::MAX_BINS{
+            return Err(format!("bins must be between 2 and {}, got {}", Self::MAX_BINS, self.bins))
+        }
+        Ok(())
+    }
+}
+
+// nodes are stored depth first, so the first child of an interior node is
+// the next node and only the second child needs an offset
 pub struct BVH{
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+    nodes: Vec<LinearNode>,
+    objects: Vec<Arc<dyn Hittable>>,
+    // objects with infinite boxes would swallow every node above them, they
+    // are tested on their own before the tree
+    unbounded: Vec<Arc<dyn Hittable>>,
+    bbox: AABB,
+    stats: BvhStats,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BvhStats{
+    pub primitives: usize,
+    pub unbounded: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    pub max_depth: usize,
+    pub build_time: Duration,
+}
+
+struct LinearNode{
+    bbox: AABB,
+    // first object for a leaf, second child for an interior node
+    offset: u32,
+    // 0 marks an interior node
+    count: u16,
+    axis: u8,
+}
+
+enum BuildNode{
+    Leaf{ bbox: AABB, first: usize, count: usize },
+    Interior{ bbox: AABB, axis: usize, children: Box<[BuildNode; 2]> },
+}
+
+// deeper than this the builder falls back to median splits, which bounds the
+// traversal stack at MAX_SAH_DEPTH plus the log2 of the primitive count
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+// subtrees with fewer primitives than this are built on the current thread
+const PARALLEL_THRESHOLD: usize = 4096;
+// bounds and bins of bigger nodes are gathered in parallel chunks of this size
+const CHUNK_SIZE: usize = 16384;
+
+// what the builder needs to know about an object, computed once up front
+struct BuildPrim{
+    bbox: AABB,
+    centroid: Vec3,
+    object: Arc<dyn Hittable>,
+}
+
+#[derive(Clone, Copy)]
+struct Bounds{
+    bbox: AABB,
+    centroid_min: Vec3,
+    centroid_max: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Bin{
     bbox: AABB,
+    count: usize,
 }
 
+
 impl BVH{
-    pub fn new(mut objects: Vec<Arc<dyn Hittable>>)-> Self{
-        let mut bbox: AABB = AABB::empty();
-        for obj in &mut *objects{
-            bbox = AABB::newb(bbox, obj.bounding_box());
+    pub fn new(objects: Vec<Arc<dyn Hittable>>)->Self{
+        Self::with_options(objects, &BvhOptions::default())
+    }
+    pub fn with_options(objects: Vec<Arc<dyn Hittable>>, options: &BvhOptions)->Self{
+        let start = Instant::now();
+        let (objects, unbounded): (Vec<_>, Vec<_>) = objects.into_iter().partition(|o| o.bounding_box().is_bounded());
+        let mut prims: Vec<BuildPrim> = objects.into_par_iter().map(|object|{
+            let bbox = object.bounding_box();
+            BuildPrim{bbox, centroid: bbox.centroid(), object}
+        }).collect();
+        let mut scratch = Vec::new();
+        let root = Self::build(&mut prims, 0, 0, options, &mut scratch);
+
+        let mut bvh = BVH{
+            nodes: Vec::with_capacity(2*prims.len()),
+            objects: prims.into_iter().map(|p| p.object).collect(),
+            bbox: unbounded.iter().fold(root.bbox(), |b, o| AABB::newb(b, o.bounding_box())),
+            unbounded,
+            stats: BvhStats{primitives: 0, unbounded: 0, nodes: 0, leaves: 0, max_depth: 0, build_time: Duration::ZERO},
+        };
+        if !bvh.objects.is_empty(){
+            bvh.flatten(&root, 0);
+        }
+        bvh.nodes.shrink_to_fit();
+        bvh.stats.primitives = bvh.objects.len();
+        bvh.stats.unbounded = bvh.unbounded.len();
+        bvh.stats.nodes = bvh.nodes.len();
+        bvh.stats.build_time = start.elapsed();
+        bvh
+    }
+    pub fn stats(&self)->BvhStats{
+        self.stats
+    }
+    fn flatten(&mut self, node: &BuildNode, depth: usize)->usize{
+        let index = self.nodes.len();
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+        match node{
+            BuildNode::Leaf{bbox, first, count}=>{
+                self.nodes.push(LinearNode{bbox: *bbox, offset: *first as u32, count: *count as u16, axis: 0});
+                self.stats.leaves += 1;
+            }
+            BuildNode::Interior{bbox, axis, children}=>{
+                self.nodes.push(LinearNode{bbox: *bbox, offset: 0, count: 0, axis: *axis as u8});
+                self.flatten(&children[0], depth+1);
+                let second = self.flatten(&children[1], depth+1);
+                self.nodes[index].offset = second as u32;
+            }
         }
+        index
+    }
+    // prims[0] is objects[first] once the build has finished partitioning.
+    // children only ever see their own half of the slice, so both halves can
+    // be built at the same time. scratch holds the bins and is reused by
+    // every node a thread builds
+    fn build(prims: &mut [BuildPrim], first: usize, depth: usize, options: &BvhOptions, scratch: &mut Vec<Bin>)->BuildNode{
+        let Bounds{bbox, centroid_min, centroid_max} = Bounds::of(prims);
 
-        let axis: i32 = bbox.longest_axis();
+        let n = prims.len();
+        if n <= 1{
+            return BuildNode::Leaf{bbox, first, count: n}
+        }
 
-        let comparator: fn(&Arc<dyn Hittable>, &Arc<dyn Hittable>) -> Ordering = if axis == 0{
-            box_x_compare
-        }else if axis == 1{
-            box_y_compare
+        let extent = centroid_max-centroid_min;
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z(){
+            0
+        }else if extent.y() > extent.z(){
+            1
         }else{
-            box_z_compare
+            2
         };
 
-        let object_span = objects.len();
-
-        if object_span==1{
-            let obj = objects[0].clone();
-            BVH {
-                left: obj.clone(),
-                right: obj,
-                bbox: objects[0].bounding_box(),
+        let split = if depth >= MAX_SAH_DEPTH { SplitMethod::Median } else { options.split };
+        let leaf_size = options.max_leaf_size.min(u16::MAX as usize);
+        let (mid, split_axis) = match split{
+            SplitMethod::Median=>{
+                if n <= leaf_size{
+                    return BuildNode::Leaf{bbox, first, count: n}
+                }
+                (Self::partition_median(prims, axis), axis)
             }
-        }else if object_span == 2{
-            let left = objects[0].clone();
-            let right = objects[1].clone();
-            let bbox = AABB::newb(left.bounding_box(), right.bounding_box());
-            BVH{
-                left: left,
-                right: right,
-                bbox: bbox,
+            SplitMethod::Sah=>{
+                // small nodes gain nothing from more bins than primitives
+                let bins = options.bins.min(n);
+                match Self::sah_split(prims, &bbox, centroid_min, extent, bins, options, scratch){
+                    Some((split_axis, split_bin, cost))=>{
+                        if n <= leaf_size && options.intersection_cost*n as f64 <= cost{
+                            return BuildNode::Leaf{bbox, first, count: n}
+                        }
+                        let scale = bins as f64/extent[split_axis];
+                        let mut mid = 0;
+                        for i in 0..n{
+                            if Self::bin_index(prims[i].centroid[split_axis], centroid_min[split_axis], scale, bins) < split_bin{
+                                prims.swap(i, mid);
+                                mid += 1;
+                            }
+                        }
+                        (mid, split_axis)
+                    }
+                    // every centroid in the same spot, no split can separate them
+                    None=>{
+                        if n <= leaf_size{
+                            return BuildNode::Leaf{bbox, first, count: n}
+                        }
+                        (n/2, axis)
+                    }
+                }
             }
+        };
+
+        let (left, right) = prims.split_at_mut(mid);
+        let children = if n >= PARALLEL_THRESHOLD{
+            let (l, r) = rayon::join(
+                || Self::build(left, first, depth+1, options, scratch),
+                || Self::build(right, first+mid, depth+1, options, &mut Vec::new()),
+            );
+            [l, r]
         }else{
-            objects.sort_by(comparator);
+            [
+                Self::build(left, first, depth+1, options, scratch),
+                Self::build(right, first+mid, depth+1, options, scratch),
+            ]
+        };
+        BuildNode::Interior{bbox, axis: split_axis, children: Box::new(children)}
+    }
+    fn partition_median(prims: &mut [BuildPrim], axis: usize)->usize{
+        let mid = prims.len()/2;
+        prims.select_nth_unstable_by(mid, |a, b| a.centroid[axis].partial_cmp(&b.centroid[axis]).unwrap_or(Ordering::Equal));
+        mid
+    }
+    fn bin_index(c: f64, min: f64, scale: f64, bins: usize)->usize{
+        (((c-min)*scale) as usize).min(bins-1)
+    }
+    // bins[axis*count..][..count] is the row for each axis
+    fn fill_bins(prims: &[BuildPrim], centroid_min: Vec3, scale: Vec3, count: usize, bins: &mut [Bin]){
+        for p in prims{
+            for axis in 0..3{
+                let b = &mut bins[axis*count+Self::bin_index(p.centroid[axis], centroid_min[axis], scale[axis], count)];
+                b.bbox = AABB::newb(b.bbox, p.bbox);
+                b.count += 1;
+            }
+        }
+    }
+    // cheapest split over all three axes as (axis, first bin on the right, cost)
+    fn sah_split(prims: &[BuildPrim], bbox: &AABB, centroid_min: Vec3, extent: Vec3, count: usize, options: &BvhOptions, scratch: &mut Vec<Bin>)->Option<(usize, usize, f64)>{
+        let inv_area = 1.0/bbox.surface_area();
+        // a flat axis puts everything in the first bin and offers no split
+        let scale = |e: f64| if e > 0.0 { count as f64/e } else { 0.0 };
+        let scale = Vec3::new(scale(extent.x()), scale(extent.y()), scale(extent.z()));
 
-            let mid = object_span / 2;
-            let left = Arc::new(BVH::new(objects[..mid].to_vec()));
-            let right = Arc::new(BVH::new(objects[mid..].to_vec()));
+        // three rows of bins and one for the right-hand sweep
+        scratch.clear();
+        scratch.resize(4*count, Bin::empty());
+        let (bins, right) = scratch.split_at_mut(3*count);
+        if prims.len() > CHUNK_SIZE{
+            let merged = prims.par_chunks(CHUNK_SIZE)
+                .map(|chunk|{
+                    let mut bins = vec![Bin::empty(); 3*count];
+                    Self::fill_bins(chunk, centroid_min, scale, count, &mut bins);
+                    bins
+                })
+                .reduce_with(|mut a, b|{
+                    for (a, b) in a.iter_mut().zip(b){
+                        *a = a.merge(b);
+                    }
+                    a
+                });
+            if let Some(merged) = merged{
+                bins.copy_from_slice(&merged);
+            }
+        }else{
+            Self::fill_bins(prims, centroid_min, scale, count, bins);
+        }
 
-            let bbox = AABB::newb(left.bounding_box(), right.bounding_box());
+        let mut best: Option<(usize, usize, f64)> = None;
+        for (axis, row) in bins.chunks(count).enumerate(){
+            // sweep from the right to get the cost of every right-hand side
+            let mut acc = Bin::empty();
+            for i in (1..count).rev(){
+                acc = acc.merge(row[i]);
+                right[i] = acc;
+            }
 
-            BVH{
-                left: left,
-                right: right,
-                bbox: bbox,
+            let mut acc = Bin::empty();
+            for i in 1..count{
+                acc = acc.merge(row[i-1]);
+                if acc.count == 0 || right[i].count == 0{
+                    continue
+                }
+                let cost = options.traversal_cost + options.intersection_cost*inv_area
+                    *(acc.count as f64*acc.bbox.surface_area() + right[i].count as f64*right[i].bbox.surface_area());
+                if best.is_none_or(|(_, _, c)| cost < c){
+                    best = Some((axis, i, cost));
+                }
             }
         }
+        best
     }
-    // pub fn form(&mut self, objects: &Vec<Arc<dyn Hittable + Sync + Send>>, start: usize, end: usize)->BVH{
-    //     let axis: i32 = rand::thread_rng().gen_range(0..2);
-
-    //     let comparator: fn(&Arc<dyn Hittable+Send+Sync>, &Arc<dyn Hittable+Send+Sync>)->Ordering = if axis == 0{box_x_compare}
-    //     else if axis == 1{box_y_compare}else{box_z_compare};
-        
-    //     let object_span: usize = end - start;
+}
 
-    //     if object_span == 1{ 
-    //         self.left = objects[start].clone();
-    //         self.right = objects[start].clone();
-    //     }
-    //     else if object_span == 2{
-    //         self.left = objects[start].clone();
-    //         self.right = objects[start+1].clone();
-    //     }else{
-    //         objects.sort_by(|a, b| comparator(a, b));
-            
-    //         let mid: usize = start+object_span/2;
-    //         self.left = Arc::new(BVH::form(self.left, objects, start, mid));
-    //         self.right = Arc::new(BVH::form(self.left, objects, mid, end));
-    //     }
+impl BuildNode{
+    fn bbox(&self)->AABB{
+        match self{
+            BuildNode::Leaf{bbox, ..}=>*bbox,
+            BuildNode::Interior{bbox, ..}=>*bbox,
+        }
+    }
+}
 
-    //     self.bbox = AABB::newb(self.left.bounding_box(), self.right.bounding_box());
-    // }   
-    pub fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis_index: i32)->Ordering{
-        let a_axis_interval: Interval = a.bounding_box().axis_interval(axis_index);
-        let b_axis_interval: Interval = b.bounding_box().axis_interval(axis_index);
-        a_axis_interval.min().partial_cmp(&b_axis_interval.min()).unwrap_or(Ordering::Equal)
+impl Bounds{
+    fn empty()->Self{
+        let inf = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        Self{bbox: AABB::empty(), centroid_min: inf, centroid_max: -inf}
+    }
+    fn merge(self, other: Self)->Self{
+        Self{
+            bbox: AABB::newb(self.bbox, other.bbox),
+            centroid_min: self.centroid_min.min(other.centroid_min),
+            centroid_max: self.centroid_max.max(other.centroid_max),
+        }
     }
-}   
+    fn of(prims: &[BuildPrim])->Self{
+        if prims.len() > CHUNK_SIZE{
+            return prims.par_chunks(CHUNK_SIZE).map(Self::of).reduce(Self::empty, Self::merge)
+        }
+        let mut bounds = Self::empty();
+        for p in prims{
+            bounds.bbox = AABB::newb(bounds.bbox, p.bbox);
+            bounds.centroid_min = bounds.centroid_min.min(p.centroid);
+            bounds.centroid_max = bounds.centroid_max.max(p.centroid);
+        }
+        bounds
+    }
+}
 
-fn box_x_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>)->Ordering{
-    BVH::box_compare(a,b,0)
+impl Bin{
+    fn empty()->Self{
+        Self{bbox: AABB::empty(), count: 0}
+    }
+    fn merge(self, other: Self)->Self{
+        Self{bbox: AABB::newb(self.bbox, other.bbox), count: self.count+other.count}
+    }
 }
-fn box_y_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>)->Ordering{
-    BVH::box_compare(a,b,1)
+
+impl BvhStats{
+    // totals over several trees, such as the meshes of one model
+    pub fn merge(self, other: Self)->Self{
+        Self{
+            primitives: self.primitives+other.primitives,
+            unbounded: self.unbounded+other.unbounded,
+            nodes: self.nodes+other.nodes,
+            leaves: self.leaves+other.leaves,
+            max_depth: self.max_depth.max(other.max_depth),
+            build_time: self.build_time+other.build_time,
+        }
+    }
 }
-fn box_z_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>)->Ordering{
-    BVH::box_compare(a,b,2)
+
+impl fmt::Display for BvhStats{
+    fn fmt(&self, f: &mut fmt::Formatter)->fmt::Result{
+        write!(f, "{} primitives, {} nodes ({} leaves, depth {}), built in {:.1} ms",
+            self.primitives, self.nodes, self.leaves, self.max_depth, self.build_time.as_secs_f64()*1000.0)?;
+        if self.unbounded > 0{
+            write!(f, ", {} unbounded outside the tree", self.unbounded)?;
+        }
+        Ok(())
+    }
 }
 
 impl Hittable for BVH{
-    fn hit(&self, r: &Ray, mut ray_t: Interval, rec: &mut Hit_record)->bool{
-        if !self.bbox.hit(*r, &mut ray_t){
-            return false 
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let mut hit_anything = false;
+        let mut closest = ray_t.max();
+        // a hit here also shortens the ray through the tree
+        for obj in &self.unbounded{
+            if obj.hit(r, Interval::new(ray_t.min(), closest), rec){
+                hit_anything = true;
+                closest = rec.t();
+            }
         }
+        if self.nodes.is_empty(){
+            return hit_anything
+        }
+        let origin = r.origin();
+        let dir = r.direction();
+        let inv_dir = Vec3::new(1.0/dir.x(), 1.0/dir.y(), 1.0/dir.z());
+        let dir_is_neg = [dir.x() < 0.0, dir.y() < 0.0, dir.z() < 0.0];
 
-        let hit_left: bool = self.left.hit(r, ray_t, rec);
-        let hit_right: bool = self.right.hit(r,Interval::new(ray_t.min(), if hit_left{rec.t()}else{ray_t.max()}), rec);
+        let mut stack = [0u32; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current: usize = 0;
 
-        hit_left || hit_right
+        loop{
+            let node = &self.nodes[current];
+            // the far child is skipped here once a hit is closer than its box
+            if node.bbox.hit_inv(origin, inv_dir, Interval::new(ray_t.min(), closest)){
+                if node.count > 0{
+                    let first = node.offset as usize;
+                    for obj in &self.objects[first..first+node.count as usize]{
+                        if obj.hit(r, Interval::new(ray_t.min(), closest), rec){
+                            hit_anything = true;
+                            closest = rec.t();
+                        }
+                    }
+                }else{
+                    // visit the child on the near side of the split first
+                    if dir_is_neg[node.axis as usize]{
+                        stack[stack_len] = current as u32+1;
+                        current = node.offset as usize;
+                    }else{
+                        stack[stack_len] = node.offset;
+                        current += 1;
+                    }
+                    stack_len += 1;
+                    continue
+                }
+            }
+            if stack_len == 0{
+                break
+            }
+            stack_len -= 1;
+            current = stack[stack_len] as usize;
+        }
+        hit_anything
     }
     fn bounding_box(&self)->AABB{
         self.bbox
     }
-}
\ No newline at end of file
+}
@@ -3,7 +3,8 @@ use crate::vec3::Vec3 as Vec3;
 use crate::ray::Ray as Ray;
 use crate::hittable::Hit_record as Hit_record;
 use crate::interval::Interval as Interval;
-use crate::color::write_color as write_color;
+use crate::framebuffer::Framebuffer as Framebuffer;
+use crate::color::Display as Display;
 use crate::material::Lambertian as Lambertian;
 use crate::material::Material as Material;
 use crate::pdf::PDF as PDF;
@@ -11,15 +12,139 @@ use crate::pdf::HittablePDF as HittablePDF;
 use crate::pdf::MixturePDF as MixturePDF;
 use crate::material::ScatterRecord as ScatterRecord;
 
-use rand::Rng;
+use crate::sampler;
+use crate::sampler::SamplerKind as SamplerKind;
 use std::sync::Arc;
 use rayon::prelude::*;
 
+// per-pixel sample budget, a pixel stops once the 95% confidence interval of
+// its mean luminance is within `threshold` of the mean
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveSampling{
+    pub min_spp: u32,
+    pub max_spp: u32,
+    pub threshold: f64,
+}
+
+impl AdaptiveSampling{
+    pub const DEFAULT_MIN_SPP: u32 = 16;
+
+    pub fn check(&self)->Result<(), String>{
+        if self.min_spp < 1{
+            return Err("min_spp must be at least 1".to_string())
+        }
+        if self.max_spp < self.min_spp{
+            return Err(format!("max_spp ({}) is less than min_spp ({})", self.max_spp, self.min_spp))
+        }
+        if !(self.threshold > 0.0 && self.threshold.is_finite()){
+            return Err(format!("threshold must be a positive number, got {}", self.threshold))
+        }
+        Ok(())
+    }
+}
+
+// how the shutter's transmission ramps up and down while it is open
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ShutterCurve{
+    // fully open for the whole exposure
+    Box,
+    // opens over the first `ramp` of the exposure and closes over the last,
+    // ramp is a fraction of the open time up to 0.5
+    Trapezoid{ ramp: f64 },
+}
+
+// exposure window in scene time. a rolling shutter starts each row later
+// than the one above it, `rolling` is the delay from the top row to the
+// bottom one. the defaults give ray times uniform over 0..1, which is what
+// Sphere::newt moves over
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Shutter{
+    pub open: f64,
+    pub close: f64,
+    pub curve: ShutterCurve,
+    pub rolling: f64,
+}
+
+impl ShutterCurve{
+    pub const NAMES: &'static str = "box, trapezoid";
+    pub const DEFAULT_RAMP: f64 = 0.25;
+
+    pub fn from_name(name: &str, ramp: f64)->Option<Self>{
+        match name{
+            "box"=>Some(ShutterCurve::Box),
+            "trapezoid"=>Some(ShutterCurve::Trapezoid{ ramp }),
+            _=>None,
+        }
+    }
+    // maps u in 0..1 to a fraction of the open time, distributed like the
+    // curve, by inverting its cdf
+    pub fn sample(&self, u: f64)->f64{
+        match *self{
+            ShutterCurve::Box=>u,
+            ShutterCurve::Trapezoid{ ramp } if ramp > 0.0=>{
+                // the curve has height 1 and area 1-ramp
+                let area = 1.0-ramp;
+                let t = u*area;
+                if t < 0.5*ramp{
+                    (2.0*ramp*t).sqrt()
+                }else if t < area-0.5*ramp{
+                    t+0.5*ramp
+                }else{
+                    1.0-(2.0*ramp*(area-t)).max(0.0).sqrt()
+                }
+            }
+            ShutterCurve::Trapezoid{..}=>u,
+        }
+    }
+}
+
+impl Default for Shutter{
+    fn default()->Self{
+        Self{
+            open: 0.0,
+            close: 1.0,
+            curve: ShutterCurve::Box,
+            rolling: 0.0,
+        }
+    }
+}
+
+impl Shutter{
+    // a film camera's shutter angle, with a frame lasting one unit of scene
+    // time. 360 degrees keeps the shutter open for the whole frame and 180
+    // for half of it
+    pub fn from_angle(angle: f64, open: f64)->Self{
+        Self{
+            open,
+            close: open+angle/360.0,
+            ..Self::default()
+        }
+    }
+    pub fn check(&self)->Result<(), String>{
+        if !(self.open.is_finite() && self.close.is_finite()){
+            return Err("shutter open and close times must be finite".to_string())
+        }
+        if self.close < self.open{
+            return Err(format!("shutter closes ({}) before it opens ({})", self.close, self.open))
+        }
+        if let ShutterCurve::Trapezoid{ ramp } = self.curve && !(0.0..=0.5).contains(&ramp){
+            return Err(format!("shutter ramp must be between 0 and 0.5, got {}", ramp))
+        }
+        if !(self.rolling >= 0.0 && self.rolling.is_finite()){
+            return Err(format!("rolling shutter delay must be a non-negative number, got {}", self.rolling))
+        }
+        Ok(())
+    }
+    // y is the sample's position down the image, 0 at the top and 1 at the bottom
+    pub fn time(&self, u: f64, y: f64)->f64{
+        self.open+self.rolling*y+(self.close-self.open)*self.curve.sample(u)
+    }
+}
+
 #[derive(Clone)]
 pub struct Camera{
     aspect_ratio: f64,
     pixel_samples_scale: f64,
-    recip_sqrt_spp: f64,
     vfov: f64,
     defocus_angle: f64,
     focus_dist: f64,
@@ -27,7 +152,6 @@ pub struct Camera{
     image_height: u32,
     samples_per_pixel: u32,
     max_depth: u32,
-    sqrt_spp: i32,
     center: Vec3,
     pixel00_loc: Vec3,
     pixel_delta_u: Vec3,
@@ -41,6 +165,13 @@ pub struct Camera{
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
     background: Vec3,
+    seed: u64,
+    sampler: SamplerKind,
+    adaptive: Option<AdaptiveSampling>,
+    film: Framebuffer,
+    sample_counts: Vec<u32>,
+    display: Display,
+    shutter: Shutter,
 }
 
 impl Camera{
@@ -49,7 +180,6 @@ impl Camera{
         Camera{
             aspect_ratio: aspect_ratio,
             pixel_samples_scale: 0.0,
-            recip_sqrt_spp: 0.0,
             vfov: vfov,
             defocus_angle: defocus_angle,
             focus_dist: focus_dist,
@@ -57,7 +187,6 @@ impl Camera{
             image_height: 1,
             samples_per_pixel: samples_per_pixel,
             max_depth: max_depth,
-            sqrt_spp: 1,
             center: Vec3::enew(),
             pixel00_loc: Vec3::enew(),
             pixel_delta_u: Vec3::enew(),
@@ -71,19 +200,76 @@ impl Camera{
             defocus_disk_u: Vec3::enew(),
             defocus_disk_v: Vec3::enew(),
             background: background,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive: None,
+            film: Framebuffer::new(0,0),
+            sample_counts: Vec::new(),
+            display: Display::default(),
+            shutter: Shutter::default(),
         }
     }
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64){
+        self.aspect_ratio = aspect_ratio
+    }
+    pub fn set_image_width(&mut self, image_width: u32){
+        self.image_width = image_width
+    }
+    pub fn set_samples_per_pixel(&mut self, samples_per_pixel: u32){
+        self.samples_per_pixel = samples_per_pixel
+    }
+    pub fn set_max_depth(&mut self, max_depth: u32){
+        self.max_depth = max_depth
+    }
+    pub fn set_seed(&mut self, seed: u64){
+        self.seed = seed
+    }
+    pub fn set_sampler(&mut self, sampler: SamplerKind){
+        self.sampler = sampler
+    }
+    pub fn sampler(&self)->SamplerKind{
+        self.sampler
+    }
+    pub fn set_adaptive(&mut self, adaptive: Option<AdaptiveSampling>){
+        self.adaptive = adaptive
+    }
+    pub fn adaptive(&self)->Option<AdaptiveSampling>{
+        self.adaptive
+    }
+    pub fn samples_per_pixel(&self)->u32{
+        self.samples_per_pixel
+    }
+    pub fn set_display(&mut self, display: Display){
+        self.display = display
+    }
+    pub fn display(&self)->Display{
+        self.display
+    }
+    pub fn set_shutter(&mut self, shutter: Shutter){
+        self.shutter = shutter
+    }
+    pub fn shutter(&self)->Shutter{
+        self.shutter
+    }
+    pub fn seed(&self)->u64{
+        self.seed
+    }
+    pub fn image_width(&self)->u32{
+        self.image_width
+    }
+    pub fn image_height(&self)->u32{
+        (self.image_width as f64 / self.aspect_ratio) as u32
+    }
     fn degrees_to_radians(degrees: f64)->f64{
         let pi: f64 = 3.1415926535897932385;
         degrees*pi/180.0
     }
     fn initialize(&mut self){
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as u32;
+        self.image_height = self.image_height().max(1);
         self.center = self.lookfrom;
         
-        self.sqrt_spp = u32::isqrt(self.samples_per_pixel) as i32;
-        self.pixel_samples_scale = 1.0/(self.sqrt_spp*self.sqrt_spp) as f64;
-        self.recip_sqrt_spp = 1.0/self.sqrt_spp as f64;
+        self.samples_per_pixel = self.samples_per_pixel.max(1);
+        self.pixel_samples_scale = 1.0/self.samples_per_pixel as f64;
         
         let theta: f64 = Self::degrees_to_radians(self.vfov);
         let h: f64 = (theta/2.0).tan();
@@ -111,6 +297,7 @@ impl Camera{
         if depth <= 0{
             return Vec3::enew()
         }
+        sampler::start_bounce(self.max_depth-depth);
 
         let dummy_mat = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
         let mut rec: Hit_record = Hit_record::new(dummy_mat);
@@ -143,39 +330,52 @@ impl Camera{
         
         color_from_emission + color_from_scatter
     }
-    pub fn render(&mut self, world: &dyn Hittable, lights: Arc<dyn Hittable>){
+    // linear radiance from the last render
+    pub fn film(&self)->&Framebuffer{
+        &self.film
+    }
+    pub fn render(&mut self, world: &dyn Hittable, lights: Arc<dyn Hittable>)->&Framebuffer{
         self.initialize();
-        println!("P3\n{0} {1}\n255", self.image_width, self.image_height);
 
-        let mut pixels: Vec<String> = vec![String::new(); (self.image_width*self.image_height) as usize];
+        // moved out while rendering so the per-row camera clones stay cheap
+        self.film = Framebuffer::new(0,0);
+        self.sample_counts = Vec::new();
+        let mut fb = Framebuffer::new(self.image_width as usize, self.image_height as usize);
+        let mut counts = vec![0u32; fb.pixels().len()];
+        let max_spp = match self.adaptive{
+            Some(a)=>a.max_spp,
+            None=>self.samples_per_pixel,
+        };
 
-        pixels
+        fb.pixels_mut()
             .par_chunks_mut(self.image_width as usize)
+            .zip(counts.par_chunks_mut(self.image_width as usize))
             .enumerate()
-            .for_each(|(j,row)|{
+            .for_each(|(j,(row,row_counts))|{
                 let local_camera: Camera = self.clone();
+                sampler::install(self.sampler.create(max_spp, self.seed));
                 for (i,pixel) in row.iter_mut().enumerate(){
-                    let mut pixel_color: Vec3 = Vec3::enew();
-                    for s_i in 0..self.sqrt_spp{
-                        for s_j in 0..self.sqrt_spp{
-                            let r: Ray = local_camera.get_ray(i as u32, j as u32, s_j as u32, s_i as u32);
-                            pixel_color += local_camera.ray_color(&r, local_camera.max_depth, &*world, lights.clone());
+                    let (color, count) = match local_camera.adaptive{
+                        Some(a)=>local_camera.render_pixel_adaptive(i as u32, j as u32, &a, world, &lights),
+                        None=>{
+                            let mut pixel_color: Vec3 = Vec3::enew();
+                            for s in 0..self.samples_per_pixel{
+                                pixel_color += local_camera.sample_pixel(i as u32, j as u32, s, world, &lights);
+                            }
+                            (local_camera.pixel_samples_scale*pixel_color, self.samples_per_pixel)
                         }
-                    }
-                    
-                    let scaled: Vec3 = local_camera.pixel_samples_scale*pixel_color;
-                    *pixel = write_color(scaled);
+                    };
+                    *pixel = [color.x() as f32, color.y() as f32, color.z() as f32];
+                    row_counts[i] = count;
                 }
             });
 
-            for row in pixels.chunks(self.image_width as usize){
-                for pixel in row{
-                    print!("{}",pixel);
-                }
+            eprintln!("Pixels rendered: {}", fb.pixels().len());
+            if self.adaptive.is_some(){
+                let total: u64 = counts.iter().map(|&c| c as u64).sum();
+                eprintln!("Average samples per pixel: {:.1} (max {})", total as f64/counts.len() as f64, max_spp);
             }
 
-            eprintln!("Pixels rendered: {}", pixels.iter().filter(|s| !s.is_empty()).count());
-
 
         // for i in 0..self.image_height{
         //     eprint!("\rScanlines remaining: {} ", self.image_height - i);
@@ -190,34 +390,134 @@ impl Camera{
         //     }
         // }
         eprintln!("Done.");
+        self.film = fb;
+        self.sample_counts = counts;
+        &self.film
+    }
+    // samples taken per pixel in the last render, row major like the film
+    pub fn sample_counts(&self)->&[u32]{
+        &self.sample_counts
+    }
+    fn sample_pixel(&self, i: u32, j: u32, s: u32, world: &dyn Hittable, lights: &Arc<dyn Hittable>)->Vec3{
+        sampler::start_pixel_sample(i, j, s);
+        let r: Ray = self.get_ray(i, j);
+        self.ray_color(&r, self.max_depth, world, lights.clone())
+    }
+    // batches of min_spp samples until the pixel converges or runs out of budget,
+    // only this pixel's samples decide so the result doesn't depend on threads
+    fn render_pixel_adaptive(&self, i: u32, j: u32, a: &AdaptiveSampling, world: &dyn Hittable, lights: &Arc<dyn Hittable>)->(Vec3, u32){
+        let batch = a.min_spp.max(1);
+        let mut sum = Vec3::enew();
+        let mut mean = 0.0;
+        let mut m2 = 0.0;
+        let mut n: u32 = 0;
+        while n < a.max_spp{
+            let sample = self.sample_pixel(i, j, n, world, lights);
+            sum += sample;
+            n += 1;
+
+            // Welford's running variance of the sample luminance
+            let l = 0.2126*sample.x()+0.7152*sample.y()+0.0722*sample.z();
+            let delta = l-mean;
+            mean += delta/n as f64;
+            m2 += delta*(l-mean);
+
+            if n >= a.min_spp && n.is_multiple_of(batch) && n > 1{
+                let error = 1.96*(m2/((n-1) as f64*n as f64)).sqrt();
+                if error <= a.threshold*mean.max(1e-3){
+                    break
+                }
+            }
+        }
+        (sum/n as f64, n)
     }
     fn sample_square()->Vec3{
-        Vec3::new(Self::random_double()-0.5,Self::random_double()-0.5,0.0)
+        let (px, py) = sampler::get_2d();
+        Vec3::new(px-0.5,py-0.5,0.0)
     }
-    fn get_ray(&self, i: u32, j: u32, s_i: u32, s_j: u32)->Ray{
-        let offset: Vec3 = self.sample_square_stratified(s_i, s_j);
+    // pixel, lens and time always take the first five sampler dimensions
+    fn get_ray(&self, i: u32, j: u32)->Ray{
+        let offset: Vec3 = Self::sample_square();
         let pixel_sample: Vec3 = self.pixel00_loc +((i as f64 +offset.x())*self.pixel_delta_u)+((j as f64 +offset.y())*self.pixel_delta_v);
+        let lens_sample: Vec3 = self.defocus_disk_sample();
         let ray_origin: Vec3 = if self.defocus_angle <= 0.0{
             self.center 
         }else{
-            self.defocus_disk_sample()
+            lens_sample
         };
         let ray_direction: Vec3 = pixel_sample - ray_origin;
-        let ray_time: f64 = Vec3::random_double();
+        let y = (j as f64+0.5+offset.y())/self.image_height as f64;
+        let ray_time: f64 = self.shutter.time(sampler::get_1d(), y);
 
         Ray::newt(ray_origin, ray_direction, ray_time)
     }
-    fn random_double()->f64{
-        rand::thread_rng().gen_range(0.0..1.0)
-    }
     fn defocus_disk_sample(&self)->Vec3{
         let p: Vec3 = Vec3::random_in_unit_disk();
         self.center+(p.x()*self.defocus_disk_u)+(p.y()*self.defocus_disk_v)
     }
-    fn sample_square_stratified(&self, s_i: u32, s_j: u32)->Vec3{
-        let px = ((s_i as f64 + Self::random_double())*self.recip_sqrt_spp)-0.5;
-        let py = ((s_j as f64 + Self::random_double())*self.recip_sqrt_spp)-0.5;
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::scene::Scene as Scene;
+    use std::path::Path;
 
-        Vec3::new(px,py,0.0)
+    // diffuse, metal, glass, a noise texture, fog and a sampled light, so
+    // every kind of random decision shows up on some path
+    const SCENE: &str = r#"{
+        "camera": {"image_width": 24, "aspect_ratio": 1.5, "samples_per_pixel": 8, "max_depth": 8,
+            "vfov": 40, "lookfrom": [0, 1, 5], "lookat": [0, 0.5, 0], "defocus_angle": 1, "focus_dist": 5},
+        "textures": {"marble": {"type": "noise", "scale": 4}},
+        "materials": {
+            "floor": {"type": "lambertian", "albedo": "marble"},
+            "metal": {"type": "metal", "albedo": [0.8, 0.7, 0.6], "fuzz": 0.2},
+            "glass": {"type": "dielectric", "refraction_index": 1.5},
+            "light": {"type": "diffuse_light", "emit": [6, 6, 6]}
+        },
+        "objects": [
+            {"type": "quad", "q": [-3, 0, -3], "u": [6, 0, 0], "v": [0, 0, 6], "material": "floor"},
+            {"type": "sphere", "center": [-0.8, 0.5, 0], "radius": 0.5, "material": "metal"},
+            {"type": "sphere", "center": [0.8, 0.5, 0], "center2": [0.8, 0.7, 0], "radius": 0.5, "material": "glass"},
+            {"type": "constant_medium", "boundary": {"type": "sphere", "center": [0, 0.4, 1], "radius": 0.4},
+                "density": 2, "albedo": [0.9, 0.9, 0.9]},
+            {"type": "quad", "q": [-1, 3, -1], "u": [2, 0, 0], "v": [0, 0, 2], "material": "light"}
+        ],
+        "lights": [{"type": "quad", "q": [-1, 3, -1], "u": [2, 0, 0], "v": [0, 0, 2]}]
+    }"#;
+
+    fn render(threads: usize, sampler: SamplerKind, seed: u64, adaptive: bool)->Vec<[f32; 3]>{
+        let mut scene = Scene::parse(SCENE, Path::new("")).unwrap();
+        scene.camera.set_sampler(sampler);
+        scene.camera.set_seed(seed);
+        if adaptive{
+            scene.camera.set_adaptive(Some(AdaptiveSampling{min_spp: 4, max_spp: 32, threshold: 0.05}));
+        }
+        let world = scene.world;
+        let lights: Arc<dyn Hittable> = Arc::new(scene.lights);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| scene.camera.render(&world, lights).pixels().to_vec())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn same_seed_gives_the_same_image_on_any_thread_count(){
+        for sampler in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise]{
+            let one = render(1, sampler, 7, false);
+            assert!(one.iter().flatten().any(|&c| c > 0.0), "{:?} rendered black", sampler);
+            for threads in [2, 5]{
+                assert!(one == render(threads, sampler, 7, false), "{:?} changed with {} threads", sampler, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_is_deterministic_too(){
+        let one = render(1, SamplerKind::Sobol, 3, true);
+        assert!(one == render(4, SamplerKind::Sobol, 3, true));
+    }
+
+    #[test]
+    fn another_seed_gives_another_image(){
+        assert!(render(2, SamplerKind::Independent, 1, false) != render(2, SamplerKind::Independent, 2, false));
+    }
+}
@@ -0,0 +1,312 @@
+use crate::output::Format as Format;
+use crate::output::OutputSettings as OutputSettings;
+use crate::output::ExrPixelType as ExrPixelType;
+use crate::output::ExrCompression as ExrCompression;
+use crate::color::ToneMap as ToneMap;
+use crate::sampler::SamplerKind as SamplerKind;
+use crate::bvh::BvhOptions as BvhOptions;
+use crate::bvh::SplitMethod as SplitMethod;
+use crate::camera::Shutter as Shutter;
+use crate::camera::ShutterCurve as ShutterCurve;
+
+// command line options, anything set here overrides the scene's camera
+pub struct Options{
+    pub scene: String,
+    pub width: Option<u32>,
+    pub aspect: Option<f64>,
+    pub spp: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerKind>,
+    pub adaptive: Option<f64>,
+    pub min_spp: Option<u32>,
+    pub max_spp: Option<u32>,
+    pub heatmap: Option<String>,
+    pub bvh: Option<BvhOptions>,
+    pub output: Option<String>,
+    pub output_settings: OutputSettings,
+    pub exposure: Option<f64>,
+    pub tone_map: Option<ToneMap>,
+    // open and close times
+    pub shutter: Option<(f64, f64)>,
+    pub shutter_curve: Option<ShutterCurve>,
+    pub rolling_shutter: Option<f64>,
+    pub help: bool,
+}
+
+pub const BUILTIN_SCENES: [&str; 2] = ["cornell", "final"];
+
+pub fn usage()->String{
+    format!("usage: rust-raytracer [options] [scene]
+
+  scene                built-in scene ({}) or path to a .json scene file [default: cornell]
+
+options:
+  --width <pixels>     image width
+  --aspect <ratio>     aspect ratio as a number or w:h, e.g. 1.5 or 16:9
+  --spp <n>            samples per pixel
+  --max-depth <n>      maximum ray bounce depth
+  --threads <n>        threads for building and rendering [default: all cores]
+  --seed <n>           render seed, the same seed always gives the same image
+  --sampler <name>     {} [default: sobol]
+  --adaptive <t>       adaptive sampling, a pixel stops once its 95% confidence
+                       interval is within t of its mean, e.g. 0.01
+  --min-spp <n>        samples before a pixel may stop [default: 16]
+  --max-spp <n>        per-pixel sample limit [default: --spp]
+  --heatmap <path>     also write the per-pixel sample counts as an image
+  --bvh <split>        build the scene's BVH with {} [default: sah]
+  --bvh-leaf-size <n>  most primitives per BVH leaf [default: 4]
+  --bvh-traversal-cost <c>
+  --bvh-intersection-cost <c>
+                       SAH cost of visiting a node and of testing a primitive
+                       [defaults: 1 and 2]
+  --output <path>      write the image to a file instead of a ppm on stdout,
+                       the format follows the extension ({})
+  --exposure <stops>   exposure adjustment for .ppm and .png output
+  --tonemap <name>     {}
+  --white-point <w>    white point for reinhard_extended, implies it if no --tonemap
+  --shutter <open>:<close>
+                       shutter open and close times [default: 0:1]
+  --shutter-angle <deg>
+                       shutter open for deg/360 of a frame from time 0, e.g. 180
+  --shutter-curve <name>
+                       {} [default: box]
+  --shutter-ramp <r>   fraction of the open time spent opening and again
+                       closing, implies trapezoid if no --shutter-curve [default: 0.25]
+  --rolling-shutter <t>
+                       delay from the top row's exposure to the bottom row's
+  --exr-type <t>       half or float samples for .exr output [default: half]
+  --exr-compression <c>
+                       none or zip for .exr output [default: zip]
+  -h, --help           print this message", BUILTIN_SCENES.join(", "), SamplerKind::NAMES, SplitMethod::NAMES, Format::extensions(), ToneMap::NAMES, ShutterCurve::NAMES)
+}
+
+impl Options{
+    pub fn parse(args: &[String])->Result<Self, String>{
+        let mut opts = Options{
+            scene: String::new(),
+            width: None,
+            aspect: None,
+            spp: None,
+            max_depth: None,
+            threads: None,
+            seed: None,
+            sampler: None,
+            adaptive: None,
+            min_spp: None,
+            max_spp: None,
+            heatmap: None,
+            bvh: None,
+            output: None,
+            output_settings: OutputSettings::default(),
+            exposure: None,
+            tone_map: None,
+            shutter: None,
+            shutter_curve: None,
+            rolling_shutter: None,
+            help: false,
+        };
+
+        let mut exr_flag = false;
+        let mut tone_map_name: Option<String> = None;
+        let mut white_point: Option<f64> = None;
+        let mut shutter_angle: Option<f64> = None;
+        let mut shutter_curve_name: Option<String> = None;
+        let mut shutter_ramp: Option<f64> = None;
+        let mut i = 0;
+        while i < args.len(){
+            let arg = args[i].as_str();
+            // accept both "--flag value" and "--flag=value"
+            let (flag, inline) = match arg.split_once('='){
+                Some((f, v)) if f.starts_with("--")=>(f, Some(v.to_string())),
+                _=>(arg, None),
+            };
+            let mut value = || -> Result<String, String>{
+                if let Some(v) = &inline{
+                    return Ok(v.clone())
+                }
+                i += 1;
+                args.get(i).cloned().ok_or_else(|| format!("{} needs a value", flag))
+            };
+
+            match flag{
+                "-h" | "--help"=>opts.help = true,
+                "--width"=>opts.width = Some(parse_positive(flag, &value()?)?),
+                "--aspect"=>opts.aspect = Some(parse_aspect(&value()?)?),
+                "--spp"=>opts.spp = Some(parse_positive(flag, &value()?)?),
+                "--max-depth"=>opts.max_depth = Some(parse_positive(flag, &value()?)?),
+                "--threads"=>opts.threads = Some(parse_positive(flag, &value()?)? as usize),
+                "--seed"=>{
+                    let v = value()?;
+                    opts.seed = Some(v.parse().map_err(|_| format!("--seed expects a non-negative integer, got '{}'", v))?);
+                }
+                "--sampler"=>{
+                    let v = value()?;
+                    opts.sampler = Some(SamplerKind::from_name(&v)
+                        .ok_or_else(|| format!("--sampler expects one of {}, got '{}'", SamplerKind::NAMES, v))?);
+                }
+                "--adaptive"=>{
+                    let v = value()?;
+                    opts.adaptive = Some(v.parse::<f64>().ok().filter(|t| t.is_finite() && *t > 0.0)
+                        .ok_or_else(|| format!("--adaptive expects a positive threshold, got '{}'", v))?);
+                }
+                "--min-spp"=>opts.min_spp = Some(parse_positive(flag, &value()?)?),
+                "--max-spp"=>opts.max_spp = Some(parse_positive(flag, &value()?)?),
+                "--heatmap"=>{
+                    let v = value()?;
+                    if Format::from_path(&v).is_none(){
+                        return Err(format!("--heatmap must end in one of {}, got '{}'", Format::extensions(), v))
+                    }
+                    opts.heatmap = Some(v);
+                }
+                "--bvh"=>{
+                    let v = value()?;
+                    opts.bvh.get_or_insert_with(BvhOptions::default).split = SplitMethod::from_name(&v)
+                        .ok_or_else(|| format!("--bvh expects one of {}, got '{}'", SplitMethod::NAMES, v))?;
+                }
+                "--bvh-leaf-size"=>{
+                    opts.bvh.get_or_insert_with(BvhOptions::default).max_leaf_size = parse_positive(flag, &value()?)? as usize;
+                }
+                "--bvh-traversal-cost" | "--bvh-intersection-cost"=>{
+                    let v = value()?;
+                    let cost = v.parse::<f64>().ok().filter(|c| c.is_finite() && *c >= 0.0)
+                        .ok_or_else(|| format!("{} expects a non-negative number, got '{}'", flag, v))?;
+                    let bvh = opts.bvh.get_or_insert_with(BvhOptions::default);
+                    if flag == "--bvh-traversal-cost"{
+                        bvh.traversal_cost = cost;
+                    }else{
+                        bvh.intersection_cost = cost;
+                    }
+                }
+                "--output" | "-o"=>{
+                    let v = value()?;
+                    if Format::from_path(&v).is_none(){
+                        return Err(format!("--output must end in one of {}, got '{}'", Format::extensions(), v))
+                    }
+                    opts.output = Some(v);
+                }
+                "--exposure"=>{
+                    let v = value()?;
+                    opts.exposure = Some(v.parse::<f64>().ok().filter(|e| e.is_finite())
+                        .ok_or_else(|| format!("--exposure expects a number of stops, got '{}'", v))?);
+                }
+                "--tonemap"=>tone_map_name = Some(value()?),
+                "--white-point"=>{
+                    let v = value()?;
+                    white_point = Some(v.parse::<f64>().ok().filter(|w| w.is_finite() && *w > 0.0)
+                        .ok_or_else(|| format!("--white-point expects a positive number, got '{}'", v))?);
+                }
+                "--shutter"=>{
+                    let v = value()?;
+                    opts.shutter = Some(v.split_once(':').and_then(|(o, c)| Some((o.parse::<f64>().ok()?, c.parse::<f64>().ok()?)))
+                        .filter(|(o, c)| o.is_finite() && c.is_finite() && c >= o)
+                        .ok_or_else(|| format!("--shutter expects open:close times with close >= open, e.g. 0:0.5, got '{}'", v))?);
+                }
+                "--shutter-angle"=>{
+                    let v = value()?;
+                    shutter_angle = Some(v.parse::<f64>().ok().filter(|a| a.is_finite() && *a >= 0.0)
+                        .ok_or_else(|| format!("--shutter-angle expects a non-negative number of degrees, got '{}'", v))?);
+                }
+                "--shutter-curve"=>shutter_curve_name = Some(value()?),
+                "--shutter-ramp"=>{
+                    let v = value()?;
+                    shutter_ramp = Some(v.parse::<f64>().ok().filter(|r| (0.0..=0.5).contains(r))
+                        .ok_or_else(|| format!("--shutter-ramp expects a number between 0 and 0.5, got '{}'", v))?);
+                }
+                "--rolling-shutter"=>{
+                    let v = value()?;
+                    opts.rolling_shutter = Some(v.parse::<f64>().ok().filter(|t| t.is_finite() && *t >= 0.0)
+                        .ok_or_else(|| format!("--rolling-shutter expects a non-negative time, got '{}'", v))?);
+                }
+                "--exr-type"=>{
+                    opts.output_settings.exr_pixel_type = match value()?.as_str(){
+                        "half"=>ExrPixelType::Half,
+                        "float"=>ExrPixelType::Float,
+                        v=>return Err(format!("--exr-type expects half or float, got '{}'", v)),
+                    };
+                    exr_flag = true;
+                }
+                "--exr-compression"=>{
+                    opts.output_settings.exr_compression = match value()?.as_str(){
+                        "none"=>ExrCompression::None,
+                        "zip"=>ExrCompression::Zip,
+                        v=>return Err(format!("--exr-compression expects none or zip, got '{}'", v)),
+                    };
+                    exr_flag = true;
+                }
+                _ if flag.starts_with('-') && flag.len() > 1=>return Err(format!("unknown option '{}'", flag)),
+                _=>{
+                    if !opts.scene.is_empty(){
+                        return Err(format!("only one scene may be given, got '{}' and '{}'", opts.scene, arg))
+                    }
+                    opts.scene = arg.to_string();
+                }
+            }
+            i += 1;
+        }
+
+        if exr_flag && opts.output.as_deref().and_then(Format::from_path) != Some(Format::Exr){
+            return Err("--exr-type and --exr-compression need an --output ending in .exr".to_string())
+        }
+        opts.tone_map = match (tone_map_name, white_point){
+            (Some(name), white)=>{
+                let tm = ToneMap::from_name(&name, white.unwrap_or(1.0))
+                    .ok_or_else(|| format!("--tonemap expects one of {}, got '{}'", ToneMap::NAMES, name))?;
+                if white.is_some() && !matches!(tm, ToneMap::ReinhardExtended{..}){
+                    return Err("--white-point only applies to --tonemap reinhard_extended".to_string())
+                }
+                Some(tm)
+            }
+            (None, Some(white))=>Some(ToneMap::ReinhardExtended{ white }),
+            (None, None)=>None,
+        };
+        if let Some(angle) = shutter_angle{
+            if opts.shutter.is_some(){
+                return Err("--shutter and --shutter-angle can't be used together".to_string())
+            }
+            let shutter = Shutter::from_angle(angle, 0.0);
+            opts.shutter = Some((shutter.open, shutter.close));
+        }
+        opts.shutter_curve = match (shutter_curve_name, shutter_ramp){
+            (Some(name), ramp)=>{
+                let curve = ShutterCurve::from_name(&name, ramp.unwrap_or(ShutterCurve::DEFAULT_RAMP))
+                    .ok_or_else(|| format!("--shutter-curve expects one of {}, got '{}'", ShutterCurve::NAMES, name))?;
+                if ramp.is_some() && !matches!(curve, ShutterCurve::Trapezoid{..}){
+                    return Err("--shutter-ramp only applies to --shutter-curve trapezoid".to_string())
+                }
+                Some(curve)
+            }
+            (None, Some(ramp))=>Some(ShutterCurve::Trapezoid{ ramp }),
+            (None, None)=>None,
+        };
+        if let Some(bvh) = &opts.bvh{
+            bvh.check()?;
+        }
+        if opts.scene.is_empty(){
+            opts.scene = "cornell".to_string();
+        }
+        Ok(opts)
+    }
+}
+
+fn parse_positive(flag: &str, v: &str)->Result<u32, String>{
+    match v.parse::<u32>(){
+        Ok(n) if n > 0=>Ok(n),
+        _=>Err(format!("{} expects a positive integer, got '{}'", flag, v)),
+    }
+}
+
+fn parse_aspect(v: &str)->Result<f64, String>{
+    let ratio = match v.split_once(':'){
+        Some((w, h))=>match (w.parse::<f64>(), h.parse::<f64>()){
+            (Ok(w), Ok(h)) if h > 0.0=>w/h,
+            _=>f64::NAN,
+        },
+        None=>v.parse::<f64>().unwrap_or(f64::NAN),
+    };
+    if !ratio.is_finite() || ratio <= 0.0{
+        return Err(format!("--aspect expects a positive ratio such as 1.5 or 16:9, got '{}'", v))
+    }
+    Ok(ratio)
+}
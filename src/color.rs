@@ -1,14 +1,129 @@
 use crate::vec3::Vec3 as Vec3;
 use crate::interval::Interval as Interval;
 
-fn linear_to_gamma(linear_component: f64)->f64{
-    if linear_component > 0.0{
-        return linear_component.sqrt()
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMap{
+    Clamp,
+    Reinhard,
+    // radiance at `white` maps to 1.0
+    ReinhardExtended{ white: f64 },
+    Aces,
+    Hable,
+}
+
+// display transform for 8-bit output, the float formats stay linear
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Display{
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+}
+
+impl ToneMap{
+    pub const NAMES: &'static str = "clamp, reinhard, reinhard_extended, aces, hable";
+
+    pub fn from_name(name: &str, white: f64)->Option<Self>{
+        match name.replace('-', "_").as_str(){
+            "clamp"=>Some(ToneMap::Clamp),
+            "reinhard"=>Some(ToneMap::Reinhard),
+            "reinhard_extended"=>Some(ToneMap::ReinhardExtended{ white }),
+            "aces"=>Some(ToneMap::Aces),
+            "hable"=>Some(ToneMap::Hable),
+            _=>None,
+        }
+    }
+    pub fn apply(&self, c: Vec3)->Vec3{
+        match *self{
+            ToneMap::Clamp=>c,
+            ToneMap::Reinhard=>scale_luminance(c, |l| l/(1.0+l)),
+            ToneMap::ReinhardExtended{ white }=>{
+                let w2 = white*white;
+                scale_luminance(c, |l| l*(1.0+l/w2)/(1.0+l))
+            }
+            ToneMap::Aces=>Vec3::new(aces(c.x()), aces(c.y()), aces(c.z())),
+            ToneMap::Hable=>{
+                let white_scale = 1.0/hable(11.2);
+                let bias = 2.0;
+                Vec3::new(hable(bias*c.x())*white_scale, hable(bias*c.y())*white_scale, hable(bias*c.z())*white_scale)
+            }
+        }
+    }
+}
+
+impl Default for Display{
+    fn default()->Self{
+        Self{
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+        }
+    }
+}
+
+impl Display{
+    // exposed and tone mapped linear color, still before the sRGB encoding
+    pub fn map(&self, c: Vec3)->Vec3{
+        self.tone_map.apply(c*2f64.powf(self.exposure))
+    }
+}
+
+fn luminance(c: Vec3)->f64{
+    0.2126*c.x()+0.7152*c.y()+0.0722*c.z()
+}
+
+fn scale_luminance(c: Vec3, f: impl Fn(f64)->f64)->Vec3{
+    let l = luminance(c);
+    if l <= 0.0{
+        return Vec3::enew()
+    }
+    c*(f(l)/l)
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: f64)->f64{
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    (x*(a*x+b))/(x*(c*x+d)+e)
+}
+
+// Uncharted 2 filmic curve
+fn hable(x: f64)->f64{
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x*(a*x+c*b)+d*e)/(x*(a*x+b)+d*f))-e/f
+}
+
+fn srgb_to_linear(encoded: f64)->f64{
+    if encoded <= 0.04045{
+        return encoded/12.92
     }
-    0.0
+    ((encoded+0.055)/1.055).powf(2.4)
 }
 
-pub fn write_color(pixel_color: Vec3)->String{
+// black, purple, orange, yellow, white ramp for heatmaps, t in [0,1],
+// returned linear so the sRGB encoding on output gives back the ramp
+pub fn heatmap_color(t: f64)->Vec3{
+    const RAMP: [[f64; 3]; 5] = [
+        [0.0, 0.0, 0.02],
+        [0.34, 0.06, 0.43],
+        [0.87, 0.32, 0.23],
+        [0.99, 0.75, 0.15],
+        [1.0, 1.0, 0.9],
+    ];
+    let x = t.clamp(0.0, 1.0)*(RAMP.len()-1) as f64;
+    let k = (x as usize).min(RAMP.len()-2);
+    let f = x-k as f64;
+    let c = |ch: usize| srgb_to_linear(RAMP[k][ch]*(1.0-f)+RAMP[k+1][ch]*f);
+    Vec3::new(c(0), c(1), c(2))
+}
+
+fn linear_to_srgb(linear_component: f64)->f64{
+    if linear_component <= 0.0{
+        return 0.0
+    }
+    if linear_component <= 0.0031308{
+        return 12.92*linear_component
+    }
+    1.055*linear_component.powf(1.0/2.4)-0.055
+}
+
+pub fn to_rgb8(pixel_color: Vec3, display: &Display)->[u8; 3]{
     let mut r: f64 = pixel_color.x();
     let mut g: f64 = pixel_color.y();
     let mut b: f64 = pixel_color.z();
@@ -23,15 +138,16 @@ pub fn write_color(pixel_color: Vec3)->String{
         b = 0.0;
     }
 
+    let mapped = display.map(Vec3::new(r, g, b));
 
-    r = linear_to_gamma(r);
-    g = linear_to_gamma(g);
-    b = linear_to_gamma(b);
+    r = linear_to_srgb(mapped.x());
+    g = linear_to_srgb(mapped.y());
+    b = linear_to_srgb(mapped.z());
 
     let intensity: Interval = Interval::new(0.000, 0.999);
-    let rbyte: i32 = (256.0 * intensity.clamp(r))as i32 ;
-    let gbyte: i32 = (256.0 * intensity.clamp(g)) as i32;
-    let bbyte: i32 = (256.0 * intensity.clamp(b)) as i32;
-    
-    format!("{rbyte} {gbyte} {bbyte}\n")
-}
\ No newline at end of file
+    let rbyte: u8 = (256.0 * intensity.clamp(r)) as u8;
+    let gbyte: u8 = (256.0 * intensity.clamp(g)) as u8;
+    let bbyte: u8 = (256.0 * intensity.clamp(b)) as u8;
+
+    [rbyte, gbyte, bbyte]
+}
@@ -0,0 +1,149 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::hittable::Span as Span;
+use crate::material::Material as Material;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::aabb::AABB as AABB;
+
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CsgOp{
+    Union,
+    Intersection,
+    // left with right cut out of it
+    Difference,
+}
+
+// two closed objects combined into one solid. the surface is wherever a
+// point moves in or out of the result, so it works with refraction and
+// nodes can nest
+pub struct Csg{
+    op: CsgOp,
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    // replaces the materials of both sides, like Instance
+    material: Option<Arc<dyn Material>>,
+    bbox: AABB,
+}
+
+impl CsgOp{
+    pub const NAMES: &'static str = "union, intersection, difference";
+
+    pub fn from_name(name: &str)->Option<Self>{
+        match name{
+            "union"=>Some(CsgOp::Union),
+            "intersection"=>Some(CsgOp::Intersection),
+            "difference"=>Some(CsgOp::Difference),
+            _=>None,
+        }
+    }
+    fn inside(&self, in_left: bool, in_right: bool)->bool{
+        match self{
+            CsgOp::Union=>in_left || in_right,
+            CsgOp::Intersection=>in_left && in_right,
+            CsgOp::Difference=>in_left && !in_right,
+        }
+    }
+}
+
+impl Csg{
+    pub fn new(op: CsgOp, left: Arc<dyn Hittable>, right: Arc<dyn Hittable>)->Self{
+        let (a, b) = (left.bounding_box(), right.bounding_box());
+        let bbox = match op{
+            CsgOp::Union=>AABB::newb(a, b),
+            CsgOp::Intersection=>{
+                let overlap = |x: Interval, y: Interval| Interval::new(x.min.max(y.min), x.max.min(y.max));
+                let (x, y, z) = (overlap(a.x(), b.x()), overlap(a.y(), b.y()), overlap(a.z(), b.z()));
+                if x.size() < 0.0 || y.size() < 0.0 || z.size() < 0.0{
+                    AABB::empty()
+                }else{
+                    AABB::new(x, y, z)
+                }
+            }
+            CsgOp::Difference=>a,
+        };
+        Self{op, left, right, material: None, bbox}
+    }
+    pub fn newm(op: CsgOp, left: Arc<dyn Hittable>, right: Arc<dyn Hittable>, material: Arc<dyn Material>)->Self{
+        let mut ret = Self::new(op, left, right);
+        ret.material = Some(material);
+        ret
+    }
+    pub fn op(&self)->CsgOp{
+        self.op
+    }
+    // sweeps the boundaries of both sides in order, a span of the result
+    // opens and closes wherever the operation's answer changes
+    fn combine(&self, left: &[Span], right: &[Span], spans: &mut Vec<Span>){
+        let mut events: Vec<(&Hit_record, bool)> = Vec::with_capacity(2*(left.len()+right.len()));
+        for s in left{
+            events.push((&s.enter, true));
+            events.push((&s.exit, true));
+        }
+        for s in right{
+            events.push((&s.enter, false));
+            events.push((&s.exit, false));
+        }
+        events.sort_by(|a, b| a.0.t().total_cmp(&b.0.t()));
+
+        let (mut in_left, mut in_right) = (false, false);
+        let mut entry: Option<Hit_record> = None;
+        for (rec, is_left) in events{
+            let was_inside = self.op.inside(in_left, in_right);
+            if is_left{
+                in_left = rec.front_face();
+            }else{
+                in_right = rec.front_face();
+            }
+            let inside = self.op.inside(in_left, in_right);
+            if inside == was_inside{
+                continue
+            }
+            // the normal already faces back along the ray, only which way
+            // the result is crossed needs fixing, e.g. for the cut surface
+            // of a difference
+            let mut rec = rec.clone();
+            rec.front_face = inside;
+            if let Some(mat) = &self.material{
+                rec.mat = mat.clone();
+            }
+            if inside{
+                entry = Some(rec);
+            }else if let Some(enter) = entry.take(){
+                spans.push(Span{enter, exit: rec});
+            }
+        }
+    }
+}
+
+impl Hittable for Csg{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let mut spans = Vec::new();
+        self.intervals(r, &mut spans);
+        for span in spans{
+            for boundary in [span.enter, span.exit]{
+                if ray_t.surrounds(boundary.t()){
+                    *rec = boundary;
+                    return true
+                }
+            }
+        }
+        false
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn intervals(&self, r: &Ray, spans: &mut Vec<Span>){
+        let mut left = Vec::new();
+        self.left.intervals(r, &mut left);
+        // nothing of the left side to keep or cut from
+        if left.is_empty() && self.op != CsgOp::Union{
+            return
+        }
+        let mut right = Vec::new();
+        self.right.intervals(r, &mut right);
+        self.combine(&left, &right, spans);
+    }
+}
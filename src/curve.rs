@@ -0,0 +1,330 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::onb::ONB as ONB;
+
+use std::f64::consts::SQRT_2;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CurveBasis{
+    // every segment takes four points and shares its end with the next one
+    Bezier,
+    // a uniform cubic b-spline, smooth through the joins but not passing
+    // through its points
+    BSpline,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CurveMode{
+    // a flat strip, facing the ray unless it has a normal of its own
+    Ribbon,
+    // a strip facing the ray shaded as if it were round
+    Tube,
+}
+
+// one cubic bezier segment, shared by the pieces it is split into
+struct Segment{
+    cp: [Vec3; 4],
+    width: [f64; 2],
+    mode: CurveMode,
+    normal: Option<Vec3>,
+    // where the segment lies along the whole strand, for texture coordinates
+    strand_u: [f64; 2],
+    mat: Arc<dyn Material>,
+}
+
+// a stretch u0 to u1 of a segment. hits are found by splitting the curve
+// until each piece is nearly straight, then testing the ray against a line
+// of the piece's width
+pub struct Curve{
+    segment: Arc<Segment>,
+    u0: f64,
+    u1: f64,
+    bbox: AABB,
+}
+
+// the nearest hit so far while splitting a piece, in ray space where the
+// ray starts at the origin and runs along z
+struct Search{
+    z_min: f64,
+    z_max: f64,
+    // how much narrower a ribbon with a normal looks from the ray
+    thin: f64,
+    // z, u and v of the hit
+    found: Option<(f64, f64, f64)>,
+}
+
+// pieces each segment is split into, so the BVH gets boxes close to the curve
+const SPLIT: usize = 4;
+// deepest the splitting goes inside a piece
+const MAX_DEPTH: i32 = 10;
+
+impl CurveBasis{
+    pub const NAMES: &'static str = "bezier, bspline";
+
+    pub fn from_name(name: &str)->Option<Self>{
+        match name{
+            "bezier"=>Some(CurveBasis::Bezier),
+            "bspline"=>Some(CurveBasis::BSpline),
+            _=>None,
+        }
+    }
+    // the smallest number of points a strand needs, and whether a count is
+    // one that makes whole segments
+    pub fn valid_count(&self, n: usize)->bool{
+        match self{
+            CurveBasis::Bezier=>n >= 4 && (n-1).is_multiple_of(3),
+            CurveBasis::BSpline=>n >= 4,
+        }
+    }
+    // the bezier control points of every segment through points
+    fn segments(&self, points: &[Vec3])->Vec<[Vec3; 4]>{
+        match self{
+            CurveBasis::Bezier=>points.windows(4).step_by(3).map(|w| [w[0], w[1], w[2], w[3]]).collect(),
+            CurveBasis::BSpline=>points.windows(4).map(|w|{
+                [
+                    (w[0]+4.0*w[1]+w[2])/6.0,
+                    (4.0*w[1]+2.0*w[2])/6.0,
+                    (2.0*w[1]+4.0*w[2])/6.0,
+                    (w[1]+4.0*w[2]+w[3])/6.0,
+                ]
+            }).collect(),
+        }
+    }
+}
+
+impl CurveMode{
+    pub const NAMES: &'static str = "ribbon, tube";
+
+    pub fn from_name(name: &str)->Option<Self>{
+        match name{
+            "ribbon"=>Some(CurveMode::Ribbon),
+            "tube"=>Some(CurveMode::Tube),
+            _=>None,
+        }
+    }
+}
+
+impl Curve{
+    // a whole bezier segment in one piece
+    pub fn new(cp: [Vec3; 4], width: [f64; 2], mode: CurveMode, mat: Arc<dyn Material>)->Self{
+        let segment = Arc::new(Segment{cp, width, mode, normal: None, strand_u: [0.0, 1.0], mat});
+        Self::piece(segment, 0.0, 1.0)
+    }
+    // the pieces of a strand through points, with its width going from
+    // width[0] at the start to width[1] at the end. a ribbon with a normal
+    // lies across it instead of turning to face each ray
+    pub fn strand(points: &[Vec3], basis: CurveBasis, width: [f64; 2], mode: CurveMode, normal: Option<Vec3>, mat: Arc<dyn Material>)->Vec<Arc<dyn Hittable>>{
+        assert!(basis.valid_count(points.len()), "strand has the wrong number of points for its basis");
+        let cps = basis.segments(points);
+        let n = cps.len() as f64;
+        let mut pieces: Vec<Arc<dyn Hittable>> = Vec::with_capacity(cps.len()*SPLIT);
+        for (i, cp) in cps.into_iter().enumerate(){
+            let strand_u = [i as f64/n, (i+1) as f64/n];
+            let segment = Arc::new(Segment{
+                cp,
+                width: strand_u.map(|u| lerp(u, width[0], width[1])),
+                mode,
+                normal: normal.map(|n| n.unit_vector()),
+                strand_u,
+                mat: mat.clone(),
+            });
+            for k in 0..SPLIT{
+                let (u0, u1) = (k as f64/SPLIT as f64, (k+1) as f64/SPLIT as f64);
+                pieces.push(Arc::new(Self::piece(segment.clone(), u0, u1)));
+            }
+        }
+        pieces
+    }
+    fn piece(segment: Arc<Segment>, u0: f64, u1: f64)->Self{
+        // a bezier curve stays inside the hull of its control points
+        let cp = sub_curve(&segment.cp, u0, u1);
+        let lo = cp.iter().fold(cp[0], |a, &b| a.min(b));
+        let hi = cp.iter().fold(cp[0], |a, &b| a.max(b));
+        let half = 0.5*segment.width_at(u0).max(segment.width_at(u1));
+        let pad = Vec3::new(half, half, half);
+        Self{segment, u0, u1, bbox: AABB::newi(lo-pad, hi+pad)}
+    }
+    // splits cp, the piece from u0 to u1 in ray space, until it is flat
+    // enough and keeps the nearest hit in search
+    fn hit_recursive(&self, cp: &[Vec3; 4], u0: f64, u1: f64, depth: i32, search: &mut Search){
+        let half = 0.5*self.segment.width_at(u0).max(self.segment.width_at(u1));
+        let lo = cp.iter().fold(cp[0], |a, &b| a.min(b));
+        let hi = cp.iter().fold(cp[0], |a, &b| a.max(b));
+        // the ray runs along z through the origin
+        if lo.x()-half > 0.0 || hi.x()+half < 0.0 || lo.y()-half > 0.0 || hi.y()+half < 0.0
+            || hi.z()+half < search.z_min || lo.z()-half > search.z_max{
+            return
+        }
+
+        if depth > 0{
+            let um = 0.5*(u0+u1);
+            let (a, b) = split_half(cp);
+            self.hit_recursive(&a, u0, um, depth-1, search);
+            self.hit_recursive(&b, um, u1, depth-1, search);
+            return
+        }
+
+        // the ray must pass between the lines through the ends that are
+        // perpendicular to the curve, so neighbouring pieces don't both hit
+        let edge = (cp[1].y()-cp[0].y())*-cp[0].y()+cp[0].x()*(cp[0].x()-cp[1].x());
+        if edge < 0.0{
+            return
+        }
+        let edge = (cp[2].y()-cp[3].y())*-cp[3].y()+cp[3].x()*(cp[3].x()-cp[2].x());
+        if edge < 0.0{
+            return
+        }
+
+        // the closest point to the ray on the line from end to end
+        let (sx, sy) = (cp[3].x()-cp[0].x(), cp[3].y()-cp[0].y());
+        let denom = sx*sx+sy*sy;
+        if denom == 0.0{
+            return
+        }
+        let mut w = ((-cp[0].x()*sx-cp[0].y()*sy)/denom).clamp(0.0, 1.0);
+        // the line's parameter is off where the curve speeds up or slows
+        // down, so move it to the nearest point on the curve itself
+        for _ in 0..2{
+            let (pc, d) = (eval_bezier(cp, w), derivative_bezier(cp, w));
+            let speed = d.x()*d.x()+d.y()*d.y();
+            if speed > 0.0{
+                w = (w-(pc.x()*d.x()+pc.y()*d.y())/speed).clamp(0.0, 1.0);
+            }
+        }
+        let u = lerp(w, u0, u1);
+        let width = self.segment.width_at(u)*search.thin;
+
+        let pc = eval_bezier(cp, w);
+        let dist2 = pc.x()*pc.x()+pc.y()*pc.y();
+        if dist2 > 0.25*width*width || pc.z() <= search.z_min || pc.z() >= search.z_max{
+            return
+        }
+
+        // v runs across the curve, 0.5 on its middle
+        let d = derivative_bezier(cp, w);
+        let side = d.x()*-pc.y()+pc.x()*d.y();
+        let offset = dist2.sqrt()/width;
+        let v = if side > 0.0 { 0.5+offset } else { 0.5-offset };
+        search.z_max = pc.z();
+        search.found = Some((pc.z(), u, v));
+    }
+}
+
+impl Segment{
+    fn width_at(&self, u: f64)->f64{
+        lerp(u, self.width[0], self.width[1])
+    }
+}
+
+fn lerp(t: f64, a: f64, b: f64)->f64{
+    (1.0-t)*a+t*b
+}
+
+fn eval_bezier(cp: &[Vec3; 4], u: f64)->Vec3{
+    let s = 1.0-u;
+    s*s*s*cp[0]+3.0*s*s*u*cp[1]+3.0*s*u*u*cp[2]+u*u*u*cp[3]
+}
+
+fn derivative_bezier(cp: &[Vec3; 4], u: f64)->Vec3{
+    let s = 1.0-u;
+    3.0*(s*s*(cp[1]-cp[0])+2.0*s*u*(cp[2]-cp[1])+u*u*(cp[3]-cp[2]))
+}
+
+// de casteljau at the middle
+fn split_half(cp: &[Vec3; 4])->([Vec3; 4], [Vec3; 4]){
+    let mid = |a: Vec3, b: Vec3| 0.5*(a+b);
+    let (a, b, c) = (mid(cp[0], cp[1]), mid(cp[1], cp[2]), mid(cp[2], cp[3]));
+    let (d, e) = (mid(a, b), mid(b, c));
+    let f = mid(d, e);
+    ([cp[0], a, d, f], [f, e, c, cp[3]])
+}
+
+// the control points of the stretch from u0 to u1, from the curve's blossom
+fn sub_curve(cp: &[Vec3; 4], u0: f64, u1: f64)->[Vec3; 4]{
+    let blossom = |a: f64, b: f64, c: f64|{
+        let mix = |t: f64, p: Vec3, q: Vec3| (1.0-t)*p+t*q;
+        let (p0, p1, p2) = (mix(a, cp[0], cp[1]), mix(a, cp[1], cp[2]), mix(a, cp[2], cp[3]));
+        let (q0, q1) = (mix(b, p0, p1), mix(b, p1, p2));
+        mix(c, q0, q1)
+    };
+    [blossom(u0, u0, u0), blossom(u0, u0, u1), blossom(u0, u1, u1), blossom(u1, u1, u1)]
+}
+
+impl Hittable for Curve{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let len = r.direction().length();
+        let dir = r.direction()/len;
+        let frame = ONB::new(dir);
+        let world = sub_curve(&self.segment.cp, self.u0, self.u1);
+        let cp = world.map(|p| frame.local(p-r.origin()));
+
+        // enough splits that the pieces are within a few percent of the
+        // width of straight
+        let mut l0: f64 = 0.0;
+        for i in 0..2{
+            let second = cp[i]-2.0*cp[i+1]+cp[i+2];
+            l0 = l0.max(second.x().abs()).max(second.y().abs()).max(second.z().abs());
+        }
+        let eps = 0.05*self.segment.width[0].max(self.segment.width[1]);
+        let depth = if l0 > 0.0 && eps > 0.0{
+            ((SQRT_2*6.0*l0/(8.0*eps)).log2().floor()/2.0).clamp(0.0, MAX_DEPTH as f64) as i32
+        }else{
+            0
+        };
+
+        let thin = match (self.segment.mode, self.segment.normal){
+            (CurveMode::Ribbon, Some(n))=>n.dot(dir).abs(),
+            _=>1.0,
+        };
+        let mut search = Search{z_min: ray_t.min()*len, z_max: ray_t.max()*len, thin, found: None};
+        self.hit_recursive(&cp, self.u0, self.u1, depth, &mut search);
+        let Some((z, u, mut v)) = search.found else{
+            return false
+        };
+
+        let t = z/len;
+        let p = r.at(t);
+        let mut tangent = derivative_bezier(&self.segment.cp, u);
+        if tangent.near_zero(){
+            tangent = self.segment.cp[3]-self.segment.cp[0];
+        }
+        let tangent = tangent.unit_vector();
+        // toward the ray across the curve, and the side v grows toward
+        let facing = (-dir-tangent.dot(-dir)*tangent).unit_vector();
+        let across = Vec3::cross(&facing, tangent);
+        // v from the split follows the ray's frame, turn it to follow across
+        // so materials can tell which side of the fiber was hit
+        let d = frame.local(tangent);
+        if frame.transform(Vec3::new(-d.y(), d.x(), 0.0)).dot(across) < 0.0{
+            v = 1.0-v;
+        }
+
+        let normal = match (self.segment.mode, self.segment.normal){
+            (CurveMode::Ribbon, Some(n))=>n,
+            (CurveMode::Ribbon, None)=>facing,
+            (CurveMode::Tube, _)=>{
+                let h = (2.0*v-1.0).clamp(-1.0, 1.0);
+                ((1.0-h*h).sqrt()*facing+h*across).unit_vector()
+            }
+        };
+
+        rec.setT(t);
+        rec.setP(p);
+        rec.mat = self.segment.mat.clone();
+        rec.set_face_normal(r, &normal);
+        rec.tangent = tangent;
+        rec.u = lerp(u, self.segment.strand_u[0], self.segment.strand_u[1]);
+        rec.v = v;
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+}
+
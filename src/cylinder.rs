@@ -0,0 +1,259 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::hittable::area_pdf_value as area_pdf_value;
+use crate::disk::Disk as Disk;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::onb::ONB as ONB;
+
+use crate::sampler;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// runs from base to base+axis. an open cylinder is just the tube, a capped
+// one is closed with a disk at each end
+pub struct Cylinder{
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    frame: ONB,
+    area: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+// the closest of the candidate hits along a ray, in the shape's own frame
+struct LocalHit{
+    t: f64,
+    local: Vec3,
+    normal: Vec3,
+    u: f64,
+    v: f64,
+}
+
+impl Cylinder{
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, capped: bool, mat: Arc<dyn Material>)->Self{
+        let frame = ONB::new(axis);
+        let radius = radius.max(0.0);
+        let height = axis.length();
+        let side = 2.0*PI*radius*height;
+        Self{
+            base,
+            radius,
+            height,
+            capped,
+            frame,
+            area: if capped { side+2.0*PI*radius*radius } else { side },
+            mat,
+            bbox: AABB::newb(Disk::disk_bbox(base, frame.w(), radius), Disk::disk_bbox(base+axis, frame.w(), radius)),
+        }
+    }
+    fn hit_local(&self, o: Vec3, d: Vec3, ray_t: Interval)->Option<LocalHit>{
+        let mut best: Option<LocalHit> = None;
+        let mut closest = ray_t.max();
+
+        let a = d.x()*d.x()+d.y()*d.y();
+        let h = o.x()*d.x()+o.y()*d.y();
+        let c = o.x()*o.x()+o.y()*o.y()-self.radius*self.radius;
+        let discriminant = h*h-a*c;
+        if a > 0.0 && discriminant >= 0.0{
+            let sqrtd = discriminant.sqrt();
+            for t in [(-h-sqrtd)/a, (-h+sqrtd)/a]{
+                let z = o.z()+t*d.z();
+                if ray_t.surrounds(t) && t < closest && (0.0..=self.height).contains(&z){
+                    let local = o+t*d;
+                    closest = t;
+                    best = Some(LocalHit{
+                        t,
+                        local,
+                        normal: Vec3::new(local.x()/self.radius, local.y()/self.radius, 0.0),
+                        u: (local.y().atan2(local.x())+PI)/(2.0*PI),
+                        v: z/self.height,
+                    });
+                    break
+                }
+            }
+        }
+        if self.capped && d.z() != 0.0{
+            for (z, nz) in [(0.0, -1.0), (self.height, 1.0)]{
+                let t = (z-o.z())/d.z();
+                let local = o+t*d;
+                if ray_t.surrounds(t) && t < closest && local.x()*local.x()+local.y()*local.y() <= self.radius*self.radius{
+                    closest = t;
+                    let mut hit = LocalHit{t, local, normal: Vec3::new(0.0, 0.0, nz), u: 0.0, v: 0.0};
+                    Disk::get_disk_uv(local, self.radius, &mut hit.u, &mut hit.v);
+                    best = Some(hit);
+                }
+            }
+        }
+        best
+    }
+}
+
+impl Hittable for Cylinder{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let o = self.frame.local(r.origin()-self.base);
+        let d = self.frame.local(r.direction());
+        let Some(hit) = self.hit_local(o, d, ray_t) else{
+            return false
+        };
+
+        rec.setT(hit.t);
+        rec.setP(self.base+self.frame.transform(hit.local));
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &self.frame.transform(hit.normal));
+        rec.u = hit.u;
+        rec.v = hit.v;
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        area_pdf_value(self, origin, direction, self.mat.clone(), |_| 1.0/self.area)
+    }
+    // picks the tube or a cap by area, then reuses the first number within it
+    fn random(&self, origin: Vec3)->Vec3{
+        let (mut r1, r2) = sampler::get_2d();
+        let side = 2.0*PI*self.radius*self.height/self.area;
+        let phi = 2.0*PI*r2;
+        let local = if r1 < side{
+            r1 /= side;
+            Vec3::new(self.radius*phi.cos(), self.radius*phi.sin(), r1*self.height)
+        }else{
+            r1 = (r1-side)/(1.0-side);
+            let (z, r1) = if r1 < 0.5 { (0.0, 2.0*r1) } else { (self.height, 2.0*r1-1.0) };
+            let rho = self.radius*r1.sqrt();
+            Vec3::new(rho*phi.cos(), rho*phi.sin(), z)
+        };
+        self.base+self.frame.transform(local)-origin
+    }
+}
+
+// pointed at base+axis with a round base of the given radius, capped closes
+// the base with a disk
+pub struct Cone{
+    base: Vec3,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    frame: ONB,
+    area: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Cone{
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, capped: bool, mat: Arc<dyn Material>)->Self{
+        let frame = ONB::new(axis);
+        let radius = radius.max(0.0);
+        let height = axis.length();
+        let side = PI*radius*(radius*radius+height*height).sqrt();
+        let apex = base+axis;
+        Self{
+            base,
+            radius,
+            height,
+            capped,
+            frame,
+            area: if capped { side+PI*radius*radius } else { side },
+            mat,
+            bbox: AABB::newb(Disk::disk_bbox(base, frame.w(), radius), AABB::newi(apex, apex)),
+        }
+    }
+    fn hit_local(&self, o: Vec3, d: Vec3, ray_t: Interval)->Option<LocalHit>{
+        let mut best: Option<LocalHit> = None;
+        let mut closest = ray_t.max();
+
+        // x^2+y^2 = k^2 (height-z)^2
+        let k = self.radius/self.height;
+        let k2 = k*k;
+        let oz = self.height-o.z();
+        let a = d.x()*d.x()+d.y()*d.y()-k2*d.z()*d.z();
+        let h = o.x()*d.x()+o.y()*d.y()+k2*oz*d.z();
+        let c = o.x()*o.x()+o.y()*o.y()-k2*oz*oz;
+        let mut roots = Vec::with_capacity(2);
+        if a.abs() > 1e-12{
+            let discriminant = h*h-a*c;
+            if discriminant >= 0.0{
+                let sqrtd = discriminant.sqrt();
+                let (t0, t1) = ((-h-sqrtd)/a, (-h+sqrtd)/a);
+                roots.push(t0.min(t1));
+                roots.push(t0.max(t1));
+            }
+        }else if h != 0.0{
+            // parallel to the slope, it only crosses the cone once
+            roots.push(-c/(2.0*h));
+        }
+        for t in roots{
+            let local = o+t*d;
+            // the quadric has a second nappe above the apex
+            if ray_t.surrounds(t) && t < closest && (0.0..=self.height).contains(&local.z()){
+                closest = t;
+                let normal = Vec3::new(local.x(), local.y(), k2*(self.height-local.z())).unit_vector();
+                best = Some(LocalHit{
+                    t,
+                    local,
+                    normal,
+                    u: (local.y().atan2(local.x())+PI)/(2.0*PI),
+                    v: local.z()/self.height,
+                });
+                break
+            }
+        }
+        if self.capped && d.z() != 0.0{
+            let t = -o.z()/d.z();
+            let local = o+t*d;
+            if ray_t.surrounds(t) && t < closest && local.x()*local.x()+local.y()*local.y() <= self.radius*self.radius{
+                let mut hit = LocalHit{t, local, normal: Vec3::new(0.0, 0.0, -1.0), u: 0.0, v: 0.0};
+                Disk::get_disk_uv(local, self.radius, &mut hit.u, &mut hit.v);
+                best = Some(hit);
+            }
+        }
+        best
+    }
+}
+
+impl Hittable for Cone{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let o = self.frame.local(r.origin()-self.base);
+        let d = self.frame.local(r.direction());
+        let Some(hit) = self.hit_local(o, d, ray_t) else{
+            return false
+        };
+
+        rec.setT(hit.t);
+        rec.setP(self.base+self.frame.transform(hit.local));
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &self.frame.transform(hit.normal));
+        rec.u = hit.u;
+        rec.v = hit.v;
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        area_pdf_value(self, origin, direction, self.mat.clone(), |_| 1.0/self.area)
+    }
+    // the side's area grows linearly away from the apex, so the distance
+    // from the apex goes as the square root
+    fn random(&self, origin: Vec3)->Vec3{
+        let (mut r1, r2) = sampler::get_2d();
+        let side = if self.capped { 1.0-PI*self.radius*self.radius/self.area } else { 1.0 };
+        let phi = 2.0*PI*r2;
+        let local = if r1 < side{
+            r1 /= side;
+            let s = r1.sqrt();
+            Vec3::new(s*self.radius*phi.cos(), s*self.radius*phi.sin(), (1.0-s)*self.height)
+        }else{
+            let rho = self.radius*((r1-side)/(1.0-side)).sqrt();
+            Vec3::new(rho*phi.cos(), rho*phi.sin(), 0.0)
+        };
+        self.base+self.frame.transform(local)-origin
+    }
+}
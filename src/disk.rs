@@ -0,0 +1,92 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::hittable::area_pdf_value as area_pdf_value;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::onb::ONB as ONB;
+
+use crate::sampler;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// flat and round, facing along normal
+pub struct Disk{
+    center: Vec3,
+    radius: f64,
+    frame: ONB,
+    area: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Disk{
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, mat: Arc<dyn Material>)->Self{
+        let frame = ONB::new(normal);
+        let radius = radius.max(0.0);
+        Self{
+            center,
+            radius,
+            frame,
+            area: PI*radius*radius,
+            mat,
+            bbox: Self::disk_bbox(center, frame.w(), radius),
+        }
+    }
+    // a circle's extent along each axis shrinks as the axis lines up with
+    // its normal
+    pub fn disk_bbox(center: Vec3, normal: Vec3, radius: f64)->AABB{
+        let mut e = Vec3::enew();
+        for i in 0..3{
+            e[i] = radius*(1.0-normal[i]*normal[i]).max(0.0).sqrt();
+        }
+        AABB::newi(center-e, center+e)
+    }
+    // u goes around the disk and v out from the center
+    pub fn get_disk_uv(local: Vec3, radius: f64, u: &mut f64, v: &mut f64){
+        *u = (local.y().atan2(local.x())+PI)/(2.0*PI);
+        *v = (local.x()*local.x()+local.y()*local.y()).sqrt()/radius;
+    }
+}
+
+impl Hittable for Disk{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let normal = self.frame.w();
+        let denom = normal.dot(r.direction());
+        if denom.abs() < 1e-8{
+            return false
+        }
+
+        let t = normal.dot(self.center-r.origin())/denom;
+        if !ray_t.contains(t){
+            return false
+        }
+        let p = r.at(t);
+        let local = self.frame.local(p-self.center);
+        if local.x()*local.x()+local.y()*local.y() > self.radius*self.radius{
+            return false
+        }
+
+        rec.setT(t);
+        rec.setP(p);
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &normal);
+        Self::get_disk_uv(local, self.radius, &mut rec.u, &mut rec.v);
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        area_pdf_value(self, origin, direction, self.mat.clone(), |_| 1.0/self.area)
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        let (r1, r2) = sampler::get_2d();
+        let rho = self.radius*r1.sqrt();
+        let phi = 2.0*PI*r2;
+        let p = self.center+self.frame.transform(Vec3::new(rho*phi.cos(), rho*phi.sin(), 0.0));
+        p-origin
+    }
+}
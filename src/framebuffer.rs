@@ -0,0 +1,60 @@
+use crate::vec3::Vec3 as Vec3;
+use crate::color::to_rgb8 as to_rgb8;
+use crate::color::Display as Display;
+use crate::color::heatmap_color as heatmap_color;
+
+// linear rgb pixels, row 0 is the top of the image
+#[derive(Clone)]
+pub struct Framebuffer{
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Framebuffer{
+    pub fn new(width: usize, height: usize)->Self{
+        Self{
+            width,
+            height,
+            pixels: vec![[0.0; 3]; width*height],
+        }
+    }
+    // sample counts shown on a log scale between min and max
+    pub fn heatmap(width: usize, height: usize, counts: &[u32], min: u32, max: u32)->Self{
+        let mut fb = Self::new(width, height);
+        let lo = (min.max(1) as f64).ln();
+        let hi = (max.max(1) as f64).ln();
+        for (p, &c) in fb.pixels.iter_mut().zip(counts){
+            let t = if hi > lo { ((c.max(1) as f64).ln()-lo)/(hi-lo) } else { 1.0 };
+            let color = heatmap_color(t);
+            *p = [color.x() as f32, color.y() as f32, color.z() as f32];
+        }
+        fb
+    }
+    pub fn width(&self)->usize{
+        self.width
+    }
+    pub fn height(&self)->usize{
+        self.height
+    }
+    pub fn pixels(&self)->&[[f32; 3]]{
+        &self.pixels
+    }
+    pub fn pixels_mut(&mut self)->&mut [[f32; 3]]{
+        &mut self.pixels
+    }
+    pub fn get(&self, x: usize, y: usize)->[f32; 3]{
+        self.pixels[y*self.width+x]
+    }
+    pub fn set(&mut self, x: usize, y: usize, color: Vec3){
+        self.pixels[y*self.width+x] = [color.x() as f32, color.y() as f32, color.z() as f32];
+    }
+    // display-ready 8-bit rgb, three bytes per pixel
+    pub fn to_rgb8(&self, display: &Display)->Vec<u8>{
+        let mut bytes = Vec::with_capacity(self.pixels.len()*3);
+        for p in &self.pixels{
+            bytes.extend_from_slice(&to_rgb8(Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64), display));
+        }
+        bytes
+    }
+}
@@ -0,0 +1,315 @@
+use crate::material::Material as Material;
+use crate::material::ScatterRecord as ScatterRecord;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::ray::Ray as Ray;
+use crate::onb::ONB as ONB;
+
+use crate::sampler;
+use std::f64::consts::{LN_2, PI};
+
+// scattering from a dielectric fiber after d'Eon and Chiang et al., the
+// Marschner lobes R, TT and TRT plus one for everything after. it samples its
+// own directions, so the camera follows them without mixing in the lights
+pub struct Hair{
+    sigma_a: Vec3,
+    eta: f64,
+    // longitudinal variances per lobe, from beta_m
+    v: [f64; P_MAX+1],
+    // azimuthal logistic scale, from beta_n
+    s: f64,
+    // sin and cos of 2^k times the scale tilt, for shifting the lobes
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+// lobes past TRT are summed into one
+const P_MAX: usize = 3;
+
+impl Hair{
+    pub const DEFAULT_BETA_M: f64 = 0.3;
+    pub const DEFAULT_BETA_N: f64 = 0.3;
+    pub const DEFAULT_ALPHA: f64 = 2.0;
+    pub const DEFAULT_ETA: f64 = 1.55;
+
+    // sigma_a is the absorption inside the fiber per unit of its diameter,
+    // beta_m and beta_n the longitudinal and azimuthal roughness in [0, 1]
+    // and alpha the tilt of the cuticle scales in degrees
+    pub fn new(sigma_a: Vec3, beta_m: f64, beta_n: f64, alpha: f64, eta: f64)->Self{
+        let mut v = [0.0; P_MAX+1];
+        v[0] = (0.726*beta_m+0.812*beta_m*beta_m+3.7*beta_m.powi(20)).powi(2);
+        v[1] = 0.25*v[0];
+        v[2] = 4.0*v[0];
+        v[3] = v[2];
+        let s = (PI/8.0).sqrt()*(0.265*beta_n+1.194*beta_n*beta_n+5.372*beta_n.powi(22));
+
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = alpha.to_radians().sin();
+        cos_2k_alpha[0] = safe_sqrt(1.0-sin_2k_alpha[0]*sin_2k_alpha[0]);
+        for i in 1..3{
+            sin_2k_alpha[i] = 2.0*cos_2k_alpha[i-1]*sin_2k_alpha[i-1];
+            cos_2k_alpha[i] = cos_2k_alpha[i-1]*cos_2k_alpha[i-1]-sin_2k_alpha[i-1]*sin_2k_alpha[i-1];
+        }
+        Self{sigma_a, eta, v, s, sin_2k_alpha, cos_2k_alpha}
+    }
+    // absorption from the concentrations of the two pigments in real hair,
+    // about 0.1 for blonde up to 8 for black
+    pub fn sigma_a_from_melanin(eumelanin: f64, pheomelanin: f64)->Vec3{
+        eumelanin*Vec3::new(0.419, 0.697, 1.37)+pheomelanin*Vec3::new(0.187, 0.4, 1.05)
+    }
+    // absorption that gives roughly color after many bounces in a mass of
+    // hair
+    pub fn sigma_a_from_color(color: Vec3, beta_n: f64)->Vec3{
+        let b = beta_n;
+        let k = 5.969-0.215*b+2.532*b*b-10.73*b.powi(3)+5.574*b.powi(4)+0.245*b.powi(5);
+        let mut sigma_a = Vec3::enew();
+        for i in 0..3{
+            sigma_a[i] = (color[i].max(1e-4).ln()/k).powi(2);
+        }
+        sigma_a
+    }
+    // the incoming angle turned by each lobe's tilt
+    fn tilt(&self, p: usize, sin_theta: f64, cos_theta: f64)->(f64, f64){
+        let (sin_op, cos_op) = match p{
+            0=>(sin_theta*self.cos_2k_alpha[1]-cos_theta*self.sin_2k_alpha[1],
+                cos_theta*self.cos_2k_alpha[1]+sin_theta*self.sin_2k_alpha[1]),
+            1=>(sin_theta*self.cos_2k_alpha[0]+cos_theta*self.sin_2k_alpha[0],
+                cos_theta*self.cos_2k_alpha[0]-sin_theta*self.sin_2k_alpha[0]),
+            2=>(sin_theta*self.cos_2k_alpha[2]+cos_theta*self.sin_2k_alpha[2],
+                cos_theta*self.cos_2k_alpha[2]-sin_theta*self.sin_2k_alpha[2]),
+            _=>(sin_theta, cos_theta),
+        };
+        (sin_op, cos_op.abs())
+    }
+    // the attenuation of each lobe, and gamma_t, for light leaving at
+    // sin_theta_o from offset h across the fiber
+    fn attenuation(&self, sin_theta_o: f64, h: f64)->([Vec3; P_MAX+1], f64){
+        let cos_theta_o = safe_sqrt(1.0-sin_theta_o*sin_theta_o);
+        let sin_theta_t = sin_theta_o/self.eta;
+        let cos_theta_t = safe_sqrt(1.0-sin_theta_t*sin_theta_t);
+        // the modified index for the projection onto the normal plane
+        let etap = safe_sqrt(self.eta*self.eta-sin_theta_o*sin_theta_o)/cos_theta_o.max(1e-8);
+        let sin_gamma_t = (h/etap).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0-sin_gamma_t*sin_gamma_t);
+        let gamma_t = sin_gamma_t.asin();
+
+        let path = 2.0*cos_gamma_t/cos_theta_t.max(1e-8);
+        let mut t = Vec3::enew();
+        for i in 0..3{
+            t[i] = (-self.sigma_a[i]*path).exp();
+        }
+        let cos_gamma_o = safe_sqrt(1.0-h*h);
+        let f = fresnel_dielectric(cos_theta_o*cos_gamma_o, self.eta);
+
+        let mut ap = [Vec3::enew(); P_MAX+1];
+        ap[0] = Vec3::new(f, f, f);
+        ap[1] = (1.0-f)*(1.0-f)*t;
+        ap[2] = f*(ap[1]*t);
+        let mut rest = Vec3::enew();
+        for i in 0..3{
+            let tf = t[i]*f;
+            rest[i] = if tf < 1.0 { ap[2][i]*tf/(1.0-tf) } else { 0.0 };
+        }
+        ap[3] = rest;
+        (ap, gamma_t)
+    }
+    // the scattered radiance per unit incoming radiance for wo and wi in the
+    // fiber's frame, times |cos| of wi against the normal
+    fn eval(&self, wo: Vec3, wi: Vec3, h: f64)->Vec3{
+        let sin_theta_o = wo.x();
+        let cos_theta_o = safe_sqrt(1.0-sin_theta_o*sin_theta_o);
+        let phi_o = wo.z().atan2(wo.y());
+        let sin_theta_i = wi.x();
+        let cos_theta_i = safe_sqrt(1.0-sin_theta_i*sin_theta_i);
+        let phi_i = wi.z().atan2(wi.y());
+
+        let (ap, gamma_t) = self.attenuation(sin_theta_o, h);
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+        let phi = phi_i-phi_o;
+        let mut sum = Vec3::enew();
+        for (p, a) in ap.iter().enumerate().take(P_MAX){
+            let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            let weight = longitudinal(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                *azimuthal(phi, p, self.s, gamma_o, gamma_t);
+            sum += weight**a;
+        }
+        let weight = longitudinal(cos_theta_i, cos_theta_o, sin_theta_i, sin_theta_o, self.v[P_MAX])/(2.0*PI);
+        sum += weight*ap[P_MAX];
+        sum
+    }
+    // the chance of choosing each lobe when sampling, by its share of the
+    // attenuation
+    fn lobe_pdf(&self, sin_theta_o: f64, h: f64)->([f64; P_MAX+1], f64){
+        let (ap, gamma_t) = self.attenuation(sin_theta_o, h);
+        let lum = ap.map(|a| 0.2126*a.x()+0.7152*a.y()+0.0722*a.z());
+        let total: f64 = lum.iter().sum();
+        if total <= 0.0{
+            return ([1.0, 0.0, 0.0, 0.0], gamma_t)
+        }
+        (lum.map(|l| l/total), gamma_t)
+    }
+    // a direction for light arriving at the fiber, and its solid angle pdf
+    fn sample(&self, wo: Vec3, h: f64)->(Vec3, f64){
+        let sin_theta_o = wo.x();
+        let cos_theta_o = safe_sqrt(1.0-sin_theta_o*sin_theta_o);
+        let phi_o = wo.z().atan2(wo.y());
+        let (lobe_pdf, gamma_t) = self.lobe_pdf(sin_theta_o, h);
+        let gamma_o = h.clamp(-1.0, 1.0).asin();
+
+        let (mut u0, u1) = sampler::get_2d();
+        let (u2, u3) = sampler::get_2d();
+        let mut p = P_MAX;
+        for (i, &pdf) in lobe_pdf.iter().enumerate().take(P_MAX){
+            if u0 < pdf{
+                p = i;
+                break
+            }
+            u0 -= pdf;
+        }
+
+        // the longitudinal angle around the tilted mirror direction
+        let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let u2 = u2.max(1e-5);
+        let cos_theta = 1.0+self.v[p]*(u2+(1.0-u2)*(-2.0/self.v[p]).exp()).ln();
+        let sin_theta = safe_sqrt(1.0-cos_theta*cos_theta);
+        let cos_phi = (2.0*PI*u3).cos();
+        let sin_theta_i = (-cos_theta*sin_op+sin_theta*cos_phi*cos_op).clamp(-1.0, 1.0);
+        let cos_theta_i = safe_sqrt(1.0-sin_theta_i*sin_theta_i);
+
+        let dphi = if p < P_MAX{
+            phi(p, gamma_o, gamma_t)+sample_trimmed_logistic(u1, self.s, -PI, PI)
+        }else{
+            2.0*PI*u1
+        };
+        let phi_i = phi_o+dphi;
+        let wi = Vec3::new(sin_theta_i, cos_theta_i*phi_i.cos(), cos_theta_i*phi_i.sin());
+
+        let mut pdf = 0.0;
+        for (p, &lobe) in lobe_pdf.iter().enumerate().take(P_MAX){
+            let (sin_op, cos_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            pdf += longitudinal(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])*lobe
+                *azimuthal(dphi, p, self.s, gamma_o, gamma_t);
+        }
+        pdf += longitudinal(cos_theta_i, cos_theta_o, sin_theta_i, sin_theta_o, self.v[P_MAX])*lobe_pdf[P_MAX]/(2.0*PI);
+        (wi, pdf)
+    }
+}
+
+impl Material for Hair{
+    fn scatter(&self, r_in: Ray, rec: Hit_record, srec: &mut ScatterRecord)->bool{
+        let wo_world = -r_in.direction().unit_vector();
+        // the fiber's frame has x along it and z toward the viewer, with the
+        // offset h running along y. shapes without a tangent get one across
+        // their normal
+        let tangent = if rec.tangent.near_zero(){ ONB::new(rec.normal()).u() }else{ rec.tangent };
+        let z = wo_world-tangent.dot(wo_world)*tangent;
+        if z.near_zero(){
+            return false
+        }
+        let z = z.unit_vector();
+        let y = Vec3::cross(&z, tangent);
+        let local = |w: Vec3| Vec3::new(w.dot(tangent), w.dot(y), w.dot(z));
+
+        let h = (2.0*rec.v()-1.0).clamp(-1.0, 1.0);
+        let wo = local(wo_world);
+        let (wi, pdf) = self.sample(wo, h);
+        if pdf <= 0.0{
+            return false
+        }
+
+        srec.attenuation = self.eval(wo, wi, h)/pdf;
+        srec.pdf_ptr = None;
+        srec.skip_pdf = true;
+        srec.skip_pdf_ray = Ray::newt(rec.p(), wi.x()*tangent+wi.y()*y+wi.z()*z, r_in.time());
+        true
+    }
+}
+
+fn safe_sqrt(x: f64)->f64{
+    x.max(0.0).sqrt()
+}
+
+// the modified bessel function of the first kind, order zero
+fn bessel_i0(x: f64)->f64{
+    let mut sum = 0.0;
+    let mut term = 1.0;
+    let x2 = x*x/4.0;
+    for i in 0..10{
+        if i > 0{
+            term *= x2/(i*i) as f64;
+        }
+        sum += term;
+    }
+    sum
+}
+
+fn log_bessel_i0(x: f64)->f64{
+    if x > 12.0{
+        x+0.5*(-(2.0*PI).ln()+(1.0/x).ln()+1.0/(8.0*x))
+    }else{
+        bessel_i0(x).ln()
+    }
+}
+
+// the distribution of the angle light leaves at along the fiber
+fn longitudinal(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64)->f64{
+    let a = cos_theta_i*cos_theta_o/v;
+    let b = sin_theta_i*sin_theta_o/v;
+    if v <= 0.1{
+        (log_bessel_i0(a)-b-1.0/v+LN_2+(1.0/(2.0*v)).ln()).exp()
+    }else{
+        (-b).exp()*bessel_i0(a)/((1.0/v).sinh()*2.0*v)
+    }
+}
+
+// where lobe p leaves around the fiber, relative to where the light came in
+fn phi(p: usize, gamma_o: f64, gamma_t: f64)->f64{
+    2.0*p as f64*gamma_t-2.0*gamma_o+p as f64*PI
+}
+
+fn logistic(x: f64, s: f64)->f64{
+    let x = x.abs();
+    (-x/s).exp()/(s*(1.0+(-x/s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64)->f64{
+    1.0/(1.0+(-x/s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64)->f64{
+    logistic(x, s)/(logistic_cdf(b, s)-logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64)->f64{
+    let k = logistic_cdf(b, s)-logistic_cdf(a, s);
+    let x = -s*(1.0/(u*k+logistic_cdf(a, s))-1.0).ln();
+    x.clamp(a, b)
+}
+
+// the distribution of lobe p around the fiber
+fn azimuthal(phi_in: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64)->f64{
+    let mut dphi = phi_in-phi(p, gamma_o, gamma_t);
+    while dphi > PI{
+        dphi -= 2.0*PI;
+    }
+    while dphi < -PI{
+        dphi += 2.0*PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+// reflectance of a smooth dielectric for light arriving at cos_theta
+fn fresnel_dielectric(cos_theta: f64, eta: f64)->f64{
+    let cos_i = cos_theta.clamp(-1.0, 1.0);
+    let (cos_i, eta) = if cos_i < 0.0 { (-cos_i, 1.0/eta) } else { (cos_i, eta) };
+    let sin_t = safe_sqrt(1.0-cos_i*cos_i)/eta;
+    if sin_t >= 1.0{
+        return 1.0
+    }
+    let cos_t = safe_sqrt(1.0-sin_t*sin_t);
+    let parallel = (eta*cos_i-cos_t)/(eta*cos_i+cos_t);
+    let perpendicular = (cos_i-eta*cos_t)/(cos_i+eta*cos_t);
+    0.5*(parallel*parallel+perpendicular*perpendicular)
+}
+
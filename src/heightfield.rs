@@ -0,0 +1,217 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::image_tex::RtwImage as RtwImage;
+use crate::perlin::Perlin as Perlin;
+
+use std::sync::Arc;
+
+// a regular grid of heights over the xz plane, starting at corner and
+// covering size.x by size.z. each cell is split into two triangles and rays
+// walk the cells they cross in order, so the first hit found is the nearest
+pub struct Heightfield{
+    corner: Vec3,
+    size: Vec3,
+    // samples along x and z, heights[j*nx+i] is above corner+(i dx, 0, j dz)
+    nx: usize,
+    nz: usize,
+    heights: Vec<f64>,
+    dx: f64,
+    dz: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Heightfield{
+    // heights are in scene units above the corner
+    pub fn new(heights: Vec<f64>, nx: usize, nz: usize, corner: Vec3, size: Vec3, mat: Arc<dyn Material>)->Self{
+        assert!(nx >= 2 && nz >= 2, "heightfield needs at least 2x2 samples");
+        assert!(heights.len() == nx*nz, "heightfield needs nx*nz heights");
+        let (lo, hi) = heights.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &h| (lo.min(h), hi.max(h)));
+        let mut bbox = AABB::newi(corner+Vec3::new(0.0, lo, 0.0), corner+Vec3::new(size.x(), hi, size.z()));
+        bbox.pad_to_minimums();
+        Self{
+            corner,
+            size,
+            nx,
+            nz,
+            heights,
+            dx: size.x()/(nx-1) as f64,
+            dz: size.z()/(nz-1) as f64,
+            mat,
+            bbox,
+        }
+    }
+    // one sample per pixel with black at 0 and white at size.y. image rows
+    // run along z, so an image texture of the same file lines up with the
+    // heights it made
+    pub fn from_image(image: &RtwImage, corner: Vec3, size: Vec3, mat: Arc<dyn Material>)->Self{
+        let (nx, nz) = (image.width(), image.height());
+        let mut heights = Vec::with_capacity(nx*nz);
+        for j in 0..nz{
+            for i in 0..nx{
+                let [r, g, b] = image.pixel_data(i, j);
+                heights.push(size.y()*(r as f64+g as f64+b as f64)/(3.0*255.0));
+            }
+        }
+        Self::new(heights, nx, nz, corner, size, mat)
+    }
+    // a grid of samples.0 by samples.1 heights of turbulence at frequency
+    // cycles per unit, scaled by size.y
+    pub fn from_noise(noise: &Perlin, frequency: f64, octaves: i32, samples: (usize, usize), corner: Vec3, size: Vec3, mat: Arc<dyn Material>)->Self{
+        let (nx, nz) = samples;
+        let mut heights = Vec::with_capacity(nx*nz);
+        for j in 0..nz{
+            for i in 0..nx{
+                let x = size.x()*i as f64/(nx-1) as f64;
+                let z = size.z()*j as f64/(nz-1) as f64;
+                heights.push(size.y()*noise.turb(frequency*Vec3::new(x, 0.0, z), octaves));
+            }
+        }
+        Self::new(heights, nx, nz, corner, size, mat)
+    }
+    fn vertex(&self, i: usize, j: usize)->Vec3{
+        self.corner+Vec3::new(i as f64*self.dx, self.heights[j*self.nx+i], j as f64*self.dz)
+    }
+    // the normal of the surface through the neighbouring samples, one sided
+    // along the edges
+    fn vertex_normal(&self, i: usize, j: usize)->Vec3{
+        let h = |i: usize, j: usize| self.heights[j*self.nx+i];
+        let (i0, i1) = (i.saturating_sub(1), (i+1).min(self.nx-1));
+        let (j0, j1) = (j.saturating_sub(1), (j+1).min(self.nz-1));
+        let slope_x = (h(i1, j)-h(i0, j))/((i1-i0) as f64*self.dx);
+        let slope_z = (h(i, j1)-h(i, j0))/((j1-j0) as f64*self.dz);
+        Vec3::new(-slope_x, 1.0, -slope_z).unit_vector()
+    }
+    // the two triangles of cell (i, j), nearest hit first
+    fn hit_cell(&self, i: usize, j: usize, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let corners = [(i, j), (i+1, j), (i+1, j+1), (i, j+1)];
+        let triangles = [[corners[0], corners[1], corners[2]], [corners[0], corners[2], corners[3]]];
+        let mut best: Option<(f64, usize, f64, f64)> = None;
+        for (k, tri) in triangles.iter().enumerate(){
+            let [p0, p1, p2] = tri.map(|(i, j)| self.vertex(i, j));
+            let limit = best.map_or(ray_t.max(), |b| b.0);
+            if let Some((t, b1, b2)) = hit_triangle(p0, p1, p2, r, Interval::new(ray_t.min(), limit)){
+                best = Some((t, k, b1, b2));
+            }
+        }
+        let Some((t, k, b1, b2)) = best else{
+            return false
+        };
+
+        let tri = triangles[k];
+        let [p0, p1, p2] = tri.map(|(i, j)| self.vertex(i, j));
+        let b0 = 1.0-b1-b2;
+        let p = b0*p0+b1*p1+b2*p2;
+        // the triangles wind so their normals point up
+        let normal = Vec3::cross(&(p2-p0), p1-p0).unit_vector();
+        rec.setT(t);
+        rec.setP(p);
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &normal);
+
+        let [n0, n1, n2] = tri.map(|(i, j)| self.vertex_normal(i, j));
+        let ns = (b0*n0+b1*n1+b2*n2).unit_vector();
+        rec.normal = if rec.front_face{ ns }else{ -ns };
+        rec.u = ((p.x()-self.corner.x())/self.size.x()).clamp(0.0, 1.0);
+        rec.v = 1.0-((p.z()-self.corner.z())/self.size.z()).clamp(0.0, 1.0);
+        true
+    }
+}
+
+// Moller-Trumbore, giving t and the weights of p1 and p2
+fn hit_triangle(p0: Vec3, p1: Vec3, p2: Vec3, r: &Ray, ray_t: Interval)->Option<(f64, f64, f64)>{
+    let e1 = p1-p0;
+    let e2 = p2-p0;
+    let pvec = Vec3::cross(&r.direction(), e2);
+    let det = e1.dot(pvec);
+    if det.abs() < 1e-12{
+        return None
+    }
+    let inv_det = 1.0/det;
+    let tvec = r.origin()-p0;
+    let b1 = tvec.dot(pvec)*inv_det;
+    if !(0.0..=1.0).contains(&b1){
+        return None
+    }
+    let qvec = Vec3::cross(&tvec, e1);
+    let b2 = r.direction().dot(qvec)*inv_det;
+    if b2 < 0.0 || b1+b2 > 1.0{
+        return None
+    }
+    let t = e2.dot(qvec)*inv_det;
+    if !ray_t.surrounds(t){
+        return None
+    }
+    Some((t, b1, b2))
+}
+
+impl Hittable for Heightfield{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let mut span = ray_t;
+        if !self.bbox.hit(*r, &mut span){
+            return false
+        }
+        let (o, d) = (r.origin(), r.direction());
+        let (cells_x, cells_z) = (self.nx-1, self.nz-1);
+
+        // 2D DDA over the cells, starting where the ray enters the box
+        let start = r.at(span.min());
+        let cell = |x: f64, origin: f64, step: f64, count: usize| (((x-origin)/step).floor().max(0.0) as usize).min(count-1);
+        let mut i = cell(start.x(), self.corner.x(), self.dx, cells_x);
+        let mut j = cell(start.z(), self.corner.z(), self.dz, cells_z);
+        // the t where the ray leaves cell k along one axis, and how much t
+        // each further cell takes
+        let next = |k: usize, o: f64, d: f64, origin: f64, step: f64|->(f64, f64){
+            if d > 0.0{
+                ((origin+(k+1) as f64*step-o)/d, step/d)
+            }else if d < 0.0{
+                ((origin+k as f64*step-o)/d, -step/d)
+            }else{
+                (f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (mut next_x, delta_x) = next(i, o.x(), d.x(), self.corner.x(), self.dx);
+        let (mut next_z, delta_z) = next(j, o.z(), d.z(), self.corner.z(), self.dz);
+
+        let mut t = span.min();
+        loop{
+            let t_exit = next_x.min(next_z).min(span.max());
+            // skip cells the ray passes entirely above or below
+            let (y0, y1) = (o.y()+t*d.y(), o.y()+t_exit*d.y());
+            let h = [self.heights[j*self.nx+i], self.heights[j*self.nx+i+1],
+                self.heights[(j+1)*self.nx+i], self.heights[(j+1)*self.nx+i+1]];
+            let (lo, hi) = (h.iter().fold(f64::INFINITY, |a, &b| a.min(b)), h.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b)));
+            if y0.min(y1) <= self.corner.y()+hi && y0.max(y1) >= self.corner.y()+lo
+                && self.hit_cell(i, j, r, ray_t, rec){
+                return true
+            }
+
+            if t_exit >= span.max(){
+                return false
+            }
+            if next_x < next_z{
+                if (d.x() > 0.0 && i+1 >= cells_x) || (d.x() < 0.0 && i == 0){
+                    return false
+                }
+                i = if d.x() > 0.0 { i+1 } else { i-1 };
+                t = next_x;
+                next_x += delta_x;
+            }else{
+                if (d.z() > 0.0 && j+1 >= cells_z) || (d.z() < 0.0 && j == 0){
+                    return false
+                }
+                j = if d.z() > 0.0 { j+1 } else { j-1 };
+                t = next_z;
+                next_z += delta_z;
+            }
+        }
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+}
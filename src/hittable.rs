@@ -2,7 +2,9 @@ use crate::ray::Ray as Ray;
 use crate::vec3::Vec3 as Vec3;
 use crate::interval::Interval as Interval;
 use crate::material::Material as Material;
+use crate::material::EmptyMat as EmptyMat;
 use crate::aabb::AABB as AABB;
+use crate::matrix::Mat4 as Mat4;
 
 use std::sync::Arc;
 
@@ -16,11 +18,49 @@ pub trait Hittable: Send + Sync{
     fn random(&self, origin: Vec3)->Vec3{
         Vec3::new(1.0,0.0,0.0)
     }
+    // the stretches of the whole line through r that lie inside the object,
+    // in order, for constructive solid geometry. only meaningful for closed
+    // objects. by default it walks every hit along the line and pairs the
+    // ones entering the object with the ones leaving it
+    fn intervals(&self, r: &Ray, spans: &mut Vec<Span>){
+        let mut entry: Option<Hit_record> = None;
+        let mut t_min = f64::NEG_INFINITY;
+        let mut rec = Hit_record::new(Arc::new(EmptyMat{}));
+        for _ in 0..MAX_INTERVAL_HITS{
+            if !self.hit(r, Interval::new(t_min, f64::INFINITY), &mut rec){
+                break
+            }
+            // a second hit at the same spot, like on the shared edge of two
+            // faces, is skipped by the pairing below
+            if rec.front_face(){
+                if entry.is_none(){
+                    entry = Some(rec.clone());
+                }
+            }else if let Some(enter) = entry.take(){
+                spans.push(Span{enter, exit: rec.clone()});
+            }
+            t_min = rec.t()+1e-9*rec.t().abs().max(1.0);
+        }
+    }
+}
+
+// hits walked per object when finding its intervals
+const MAX_INTERVAL_HITS: usize = 256;
+
+// one stretch of a ray inside a solid. both records face back along the ray,
+// enter has front_face set and exit doesn't
+#[derive(Clone)]
+pub struct Span{
+    pub enter: Hit_record,
+    pub exit: Hit_record,
 }
 
 pub struct Hit_record{
     p: Vec3,
     pub normal: Vec3,
+    // the direction a fiber runs at the hit, for materials like hair. shapes
+    // without one leave it zero
+    pub tangent: Vec3,
     t: f64,
     pub u: f64,
     pub v: f64,
@@ -41,11 +81,37 @@ pub struct RotateY{
     bbox: AABB,
 }
 
+// any affine transform in one node, instead of a chain of wrappers
+pub struct Transform{
+    object: Arc<dyn Hittable>,
+    matrix: Mat4,
+    inverse: Mat4,
+    bbox: AABB,
+}
+
+// solid angle pdf of sampling a point on object's surface with the given
+// area density. every surface point along the direction could have been the
+// sample, so curved shapes sum over all their hits
+pub fn area_pdf_value(object: &dyn Hittable, origin: Vec3, direction: Vec3, mat: Arc<dyn Material>, density: impl Fn(Vec3)->f64)->f64{
+    let r = Ray::new(origin, direction);
+    let mut rec = Hit_record::new(mat);
+    let mut t_min = 0.001;
+    let mut pdf = 0.0;
+    while object.hit(&r, Interval::new(t_min, f64::INFINITY), &mut rec){
+        let distance_squared = rec.t()*rec.t()*direction.length_squared();
+        let cosine = direction.dot(rec.normal()).abs()/direction.length();
+        pdf += density(rec.p())*distance_squared/cosine;
+        t_min = rec.t()+0.001;
+    }
+    pdf
+}
+
 impl Hit_record{
     pub fn new(mat: Arc<dyn Material>)->Hit_record{
         Hit_record{
             p: Vec3::enew(),
             normal: Vec3::enew(),
+            tangent: Vec3::enew(),
             t: 0.0,
             u: 0.0,
             v: 0.0,
@@ -79,7 +145,9 @@ impl Hit_record{
     }
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3){
         self.front_face = r.direction().dot(*outward_normal) < 0.0;
-        
+        // a shape with a tangent sets it afterwards
+        self.tangent = Vec3::enew();
+
         self.normal = if self.front_face{
             *outward_normal
         }else{
@@ -138,6 +206,72 @@ impl RotateY{
     }
 }
 
+impl Transform{
+    // matrix must be affine and invertible, see Mat4::is_affine and Mat4::inverse
+    pub fn new(object: Arc<dyn Hittable>, matrix: Mat4)->Self{
+        assert!(matrix.is_affine(), "transform must be affine");
+        let inverse = matrix.inverse().expect("transform is not invertible");
+        let bbox = matrix.bbox(&object.bounding_box());
+        Self{
+            object,
+            matrix,
+            inverse,
+            bbox,
+        }
+    }
+    // applies matrix after this transform, still a single node
+    pub fn then(&self, matrix: Mat4)->Self{
+        Self::new(self.object.clone(), matrix*self.matrix)
+    }
+    pub fn object(&self)->&Arc<dyn Hittable>{
+        &self.object
+    }
+    pub fn matrix(&self)->Mat4{
+        self.matrix
+    }
+    pub fn inverse(&self)->Mat4{
+        self.inverse
+    }
+    // the work behind hit and pdf_value, shared with transforms that change
+    // over time and build their matrices per ray
+    pub fn hit_object(object: &dyn Hittable, matrix: &Mat4, inverse: &Mat4, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        // the direction isn't normalized, so t means the same in both spaces
+        let local = Ray::newt(inverse.point(r.origin()), inverse.vector(r.direction()), r.time());
+        if !object.hit(&local, ray_t, rec){
+            return false
+        }
+
+        rec.setP(matrix.point(rec.p()));
+        // the inverse transpose keeps the sign of dot(direction, normal), so
+        // front_face from the object still holds
+        rec.normal = inverse.normal(rec.normal()).unit_vector();
+        rec.tangent = matrix.vector(rec.tangent);
+        if !rec.tangent.near_zero(){
+            rec.tangent = rec.tangent.unit_vector();
+        }
+        true
+    }
+    // the object's intervals along the same line, brought back to world space
+    pub fn object_intervals(object: &dyn Hittable, matrix: &Mat4, inverse: &Mat4, r: &Ray, spans: &mut Vec<Span>){
+        let local = Ray::newt(inverse.point(r.origin()), inverse.vector(r.direction()), r.time());
+        let first = spans.len();
+        object.intervals(&local, spans);
+        for span in &mut spans[first..]{
+            for rec in [&mut span.enter, &mut span.exit]{
+                rec.setP(matrix.point(rec.p()));
+                rec.normal = inverse.normal(rec.normal()).unit_vector();
+            }
+        }
+    }
+    pub fn object_pdf_value(object: &dyn Hittable, inverse: &Mat4, origin: Vec3, direction: Vec3)->f64{
+        // the object's pdf is per solid angle in its own space, scale it by
+        // the jacobian of w -> normalize(A w), which is |det A| / |A w|^3
+        let local = inverse.vector(direction.unit_vector());
+        let len = local.length();
+        object.pdf_value(inverse.point(origin), local)*inverse.determinant().abs()/(len*len*len)
+    }
+}
+
 impl Hittable for Translate{
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
         let offset_r: Ray = Ray::newt(r.origin()-self.offset,r.direction(),r.time());
@@ -172,6 +306,8 @@ impl Hittable for RotateY{
 
         rec.normal = Vec3::new((self.cos_theta*rec.normal().x())+(self.sin_theta*rec.normal().z()),
                     rec.normal().y(), (-self.sin_theta*rec.normal().x())+(self.cos_theta*rec.normal().z()));
+        rec.tangent = Vec3::new((self.cos_theta*rec.tangent.x())+(self.sin_theta*rec.tangent.z()),
+                    rec.tangent.y(), (-self.sin_theta*rec.tangent.x())+(self.cos_theta*rec.tangent.z()));
 
         true
     }
@@ -185,6 +321,7 @@ impl Clone for Hit_record{
         Hit_record{
             p: self.p,
             normal: self.normal,
+            tangent: self.tangent,
             t: self.t,
             u: self.u,
             v: self.v,
@@ -192,4 +329,22 @@ impl Clone for Hit_record{
             mat: Arc::clone(&self.mat),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Hittable for Transform{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        Self::hit_object(self.object.as_ref(), &self.matrix, &self.inverse, r, ray_t, rec)
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        Self::object_pdf_value(self.object.as_ref(), &self.inverse, origin, direction)
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        self.matrix.vector(self.object.random(self.inverse.point(origin)))
+    }
+    fn intervals(&self, r: &Ray, spans: &mut Vec<Span>){
+        Self::object_intervals(self.object.as_ref(), &self.matrix, &self.inverse, r, spans)
+    }
+}
@@ -6,7 +6,7 @@ use crate::interval::Interval as Interval;
 use crate::material::Lambertian as Lambertian;
 use crate::vec3::Vec3 as Vec3;
 use crate::aabb::AABB as AABB;
-use rand::Rng; 
+use crate::sampler;
 
 pub struct Hittable_List{
     pub objects: Vec<Arc<dyn Hittable>>,
@@ -77,6 +77,9 @@ impl Hittable for Hittable_List{
     fn random(&self, origin: Vec3)->Vec3{
         //eprintln!("hello!");
         let listSize = self.objects.len();
-        self.objects[rand::thread_rng().gen_range(0..listSize-1)].random(origin)
+        if listSize == 0{
+            return Vec3::new(1.0,0.0,0.0)
+        }
+        self.objects[((sampler::get_1d()*listSize as f64) as usize).min(listSize-1)].random(origin)
     }
 }
\ No newline at end of file
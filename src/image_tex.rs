@@ -1,5 +1,5 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use stb_image::image::{load, LoadResult};
 
 pub struct RtwImage{
@@ -116,4 +116,13 @@ impl RtwImage{
         }
         high-1
     }
-}
\ No newline at end of file
+}
+
+// prefer a path relative to the file that names the image, otherwise let RtwImage search
+pub fn texture_path(dir: &Path, name: &str)->String{
+    let candidate: PathBuf = dir.join(name);
+    if candidate.exists(){
+        return candidate.display().to_string()
+    }
+    name.to_string()
+}
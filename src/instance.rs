@@ -0,0 +1,73 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::hittable::Span as Span;
+use crate::hittable::Transform as Transform;
+use crate::material::Material as Material;
+use crate::matrix::Mat4 as Mat4;
+use crate::ray::Ray as Ray;
+use crate::vec3::Vec3 as Vec3;
+use crate::interval::Interval as Interval;
+use crate::aabb::AABB as AABB;
+
+use std::sync::Arc;
+
+// one placement of shared geometry. the object, usually a BVH, is built once
+// and every instance of it only stores a transform, so a BVH over instances
+// is the top level of a two level hierarchy
+pub struct Instance{
+    transform: Transform,
+    // replaces whatever material the geometry was built with
+    material: Option<Arc<dyn Material>>,
+}
+
+impl Instance{
+    // transform must be affine and invertible, see Transform::new
+    pub fn new(object: Arc<dyn Hittable>, transform: Mat4)->Self{
+        Self{
+            transform: Transform::new(object, transform),
+            material: None,
+        }
+    }
+    pub fn newm(object: Arc<dyn Hittable>, transform: Mat4, material: Arc<dyn Material>)->Self{
+        let mut ret = Self::new(object, transform);
+        ret.material = Some(material);
+        ret
+    }
+    pub fn object(&self)->&Arc<dyn Hittable>{
+        self.transform.object()
+    }
+    pub fn transform(&self)->Mat4{
+        self.transform.matrix()
+    }
+}
+
+impl Hittable for Instance{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        if !self.transform.hit(r, ray_t, rec){
+            return false
+        }
+        if let Some(mat) = &self.material{
+            rec.mat = mat.clone();
+        }
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.transform.bounding_box()
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        self.transform.pdf_value(origin, direction)
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        self.transform.random(origin)
+    }
+    fn intervals(&self, r: &Ray, spans: &mut Vec<Span>){
+        let first = spans.len();
+        self.transform.intervals(r, spans);
+        if let Some(mat) = &self.material{
+            for span in &mut spans[first..]{
+                span.enter.mat = mat.clone();
+                span.exit.mat = mat.clone();
+            }
+        }
+    }
+}
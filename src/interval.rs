@@ -66,7 +66,7 @@ impl Interval{
         let padding: f64 = delta / 2.0;
         Interval{
             min: self.min - padding,
-            max: self.max - padding,
+            max: self.max + padding,
         }
     }
 }
@@ -16,6 +16,30 @@ pub mod quad;
 pub mod constant_medium;
 pub mod onb;
 pub mod pdf;
+pub mod triangle;
+pub mod obj;
+pub mod scene;
+pub mod cli;
+pub mod framebuffer;
+pub mod output;
+pub mod zlib;
+pub mod rng;
+pub mod sampler;
+pub mod matrix;
+pub mod instance;
+pub mod motion;
+pub mod poly;
+pub mod disk;
+pub mod cylinder;
+pub mod torus;
+pub mod plane;
+pub mod csg;
+pub mod sdf;
+pub mod heightfield;
+pub mod curve;
+pub mod hair;
+pub mod metaballs;
+pub mod voxel;
 
 use std::sync::Arc;
 use vec3::Vec3 as Vec3;
@@ -26,6 +50,9 @@ use hittable::Hit_record as Hit_record;
 use hittable_list::Hittable_List as Hittable_List;
 use interval::Interval as Interval;
 use camera::Camera as Camera;
+use camera::AdaptiveSampling as AdaptiveSampling;
+use framebuffer::Framebuffer as Framebuffer;
+use output::OutputSettings as OutputSettings;
 use material::Lambertian as Lambertian;
 use material::Material as Material;
 use material::Metal as Metal;
@@ -36,8 +63,19 @@ use hittable::RotateY as RotateY;
 use hittable::Translate as Translate;
 use quad::Cube as Cube;
 use material::EmptyMat as EmptyMat;
+use scene::Scene as Scene;
+use cli::Options as Options;
+use bvh::BVH as BVH;
+use bvh::BvhOptions as BvhOptions;
+use constant_medium::ConstantMedium as ConstantMedium;
+use texture::Image_Texture as Image_Texture;
+use texture::Noise_Texture as Noise_Texture;
+use rng::Pcg32 as Pcg32;
 
-fn cornell_box(){
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+fn cornell_box()->Scene{
     let mut world: Hittable_List = Hittable_List::new();
 
     let red = Arc::new(Lambertian::new(Vec3::new(0.65,0.05,0.05)));
@@ -70,12 +108,209 @@ fn cornell_box(){
     lights.push(Arc::new(Quad::new(Vec3::new(343.0,554.0,332.0),Vec3::new(-130.0,0.0,0.0),Vec3::new(0.0,0.0,-105.0),empty_material.clone())));
     lights.push(Arc::new(Sphere::new(Vec3::new(190.0,90.0,190.0),90.0,empty_material)));
 
-    let mut cam: Camera = Camera::new(1.0,600,10000,50,40.0,Vec3::new(278.0,278.0,-800.0),
+    let cam: Camera = Camera::new(1.0,600,10000,50,40.0,Vec3::new(278.0,278.0,-800.0),
+    Vec3::new(278.0,278.0,0.0), Vec3::new(0.0,1.0,0.0), 0.0, 10.0, Vec3::enew());
+
+    Scene{
+        camera: cam,
+        world,
+        lights,
+    }
+}
+
+// the final scene of Ray Tracing: The Next Week, with fixed random numbers
+fn final_scene(bvh_options: &BvhOptions)->Scene{
+    let mut rng = Pcg32::new(2024, 0);
+    let mut world: Hittable_List = Hittable_List::new();
+
+    let ground = Arc::new(Lambertian::new(Vec3::new(0.48,0.83,0.53)));
+    let boxes_per_side = 20;
+    let mut boxes1: Vec<Arc<dyn Hittable>> = Vec::new();
+    for i in 0..boxes_per_side{
+        for j in 0..boxes_per_side{
+            let w = 100.0;
+            let x0 = -1000.0+i as f64*w;
+            let z0 = -1000.0+j as f64*w;
+            let y1 = rng.between(1.0,101.0);
+            boxes1.push(Cube::new(Vec3::new(x0,0.0,z0), Vec3::new(x0+w,y1,z0+w), ground.clone()));
+        }
+    }
+    let ground_boxes = BVH::with_options(boxes1, bvh_options);
+    eprintln!("BVH for the ground boxes: {}", ground_boxes.stats());
+    world.push(Arc::new(ground_boxes));
+
+    let light = Arc::new(Diffuse_Light::newc(Vec3::new(7.0,7.0,7.0)));
+    world.push(Arc::new(Quad::new(Vec3::new(123.0,554.0,147.0), Vec3::new(300.0,0.0,0.0), Vec3::new(0.0,0.0,265.0), light)));
+
+    let center1 = Vec3::new(400.0,400.0,200.0);
+    let center2 = center1+Vec3::new(30.0,0.0,0.0);
+    world.push(Arc::new(Sphere::newt(center1, center2, 50.0, Arc::new(Lambertian::new(Vec3::new(0.7,0.3,0.1))))));
+
+    world.push(Arc::new(Sphere::new(Vec3::new(260.0,150.0,45.0), 50.0, Arc::new(Dielectric::new(1.5)))));
+    world.push(Arc::new(Sphere::new(Vec3::new(0.0,150.0,145.0), 50.0, Arc::new(Metal::new(Vec3::new(0.8,0.8,0.9),1.0)))));
+
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::new(360.0,150.0,145.0), 70.0, Arc::new(Dielectric::new(1.5))));
+    world.push(boundary.clone());
+    world.push(Arc::new(ConstantMedium::newc(boundary, 0.2, Vec3::new(0.2,0.4,0.9))));
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new(Vec3::enew(), 5000.0, Arc::new(Dielectric::new(1.5))));
+    world.push(Arc::new(ConstantMedium::newc(boundary, 0.0001, Vec3::new(1.0,1.0,1.0))));
+
+    let emat = Arc::new(Lambertian::newt(Arc::new(Image_Texture::new("earthmap.jpg"))));
+    world.push(Arc::new(Sphere::new(Vec3::new(400.0,200.0,400.0), 100.0, emat)));
+    let pertext = Arc::new(Noise_Texture::new(0.2, 0));
+    world.push(Arc::new(Sphere::new(Vec3::new(220.0,280.0,300.0), 80.0, Arc::new(Lambertian::newt(pertext)))));
+
+    let white = Arc::new(Lambertian::new(Vec3::new(0.73,0.73,0.73)));
+    let mut boxes2: Vec<Arc<dyn Hittable>> = Vec::new();
+    for _ in 0..1000{
+        let center = Vec3::new(rng.between(0.0,165.0), rng.between(0.0,165.0), rng.between(0.0,165.0));
+        boxes2.push(Arc::new(Sphere::new(center, 10.0, white.clone())));
+    }
+    let spheres = BVH::with_options(boxes2, bvh_options);
+    eprintln!("BVH for the sphere cluster: {}", spheres.stats());
+    let mut cluster: Arc<dyn Hittable> = Arc::new(spheres);
+    cluster = Arc::new(RotateY::new(cluster, 15.0));
+    cluster = Arc::new(Translate::new(cluster, Vec3::new(-100.0,270.0,395.0)));
+    world.push(cluster);
+
+    let mut lights = Hittable_List::new();
+    lights.push(Arc::new(Quad::new(Vec3::new(123.0,554.0,147.0), Vec3::new(300.0,0.0,0.0), Vec3::new(0.0,0.0,265.0), Arc::new(EmptyMat::new()))));
+
+    let cam: Camera = Camera::new(1.0,800,10000,40,40.0,Vec3::new(478.0,278.0,-600.0),
     Vec3::new(278.0,278.0,0.0), Vec3::new(0.0,1.0,0.0), 0.0, 10.0, Vec3::enew());
 
-    cam.render(&world, Arc::new(lights));
+    Scene{
+        camera: cam,
+        world,
+        lights,
+    }
+}
+
+fn fail(message: &str)->!{
+    eprintln!("error: {}\n\n{}", message, cli::usage());
+    process::exit(2)
 }
 
 fn main() {
-    cornell_box();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let opts = Options::parse(&args).unwrap_or_else(|e| fail(&e));
+    if opts.help{
+        println!("{}", cli::usage());
+        return
+    }
+
+    // before loading, the BVH builds run on the same pool
+    if let Some(threads) = opts.threads{
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()
+            .unwrap_or_else(|e| fail(&format!("cannot create {} threads: {}", threads, e)));
+    }
+
+    let mut scene = match opts.scene.as_str(){
+        "cornell"=>cornell_box(),
+        "final"=>final_scene(&opts.bvh.unwrap_or_default()),
+        path=>Scene::load_with(path, opts.bvh.as_ref()).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1)
+        }),
+    };
+
+    let cam = &mut scene.camera;
+    if let Some(width) = opts.width{
+        cam.set_image_width(width);
+    }
+    if let Some(aspect) = opts.aspect{
+        cam.set_aspect_ratio(aspect);
+    }
+    if let Some(spp) = opts.spp{
+        cam.set_samples_per_pixel(spp);
+    }
+    if let Some(depth) = opts.max_depth{
+        cam.set_max_depth(depth);
+    }
+    if let Some(seed) = opts.seed{
+        cam.set_seed(seed);
+    }
+    if let Some(sampler) = opts.sampler{
+        cam.set_sampler(sampler);
+    }
+    match cam.adaptive(){
+        Some(mut adaptive)=>{
+            if let Some(threshold) = opts.adaptive{
+                adaptive.threshold = threshold;
+            }
+            if let Some(max_spp) = opts.max_spp.or(opts.spp){
+                adaptive.max_spp = max_spp;
+            }
+            if let Some(min_spp) = opts.min_spp{
+                adaptive.min_spp = min_spp;
+            }
+            adaptive.check().unwrap_or_else(|e| fail(&format!("adaptive sampling: {}", e)));
+            cam.set_adaptive(Some(adaptive));
+        }
+        None=>match opts.adaptive{
+            Some(threshold)=>{
+                let max_spp = opts.max_spp.unwrap_or(cam.samples_per_pixel());
+                let adaptive = AdaptiveSampling{
+                    min_spp: opts.min_spp.unwrap_or(AdaptiveSampling::DEFAULT_MIN_SPP.min(max_spp)),
+                    max_spp,
+                    threshold,
+                };
+                adaptive.check().unwrap_or_else(|e| fail(&format!("adaptive sampling: {}", e)));
+                cam.set_adaptive(Some(adaptive));
+            }
+            None=>if opts.min_spp.is_some() || opts.max_spp.is_some() || opts.heatmap.is_some(){
+                fail("--min-spp, --max-spp and --heatmap need adaptive sampling, use --adaptive or the scene's camera.adaptive");
+            }
+        }
+    }
+    if opts.shutter.is_some() || opts.shutter_curve.is_some() || opts.rolling_shutter.is_some(){
+        let mut shutter = cam.shutter();
+        if let Some((open, close)) = opts.shutter{
+            shutter.open = open;
+            shutter.close = close;
+        }
+        if let Some(curve) = opts.shutter_curve{
+            shutter.curve = curve;
+        }
+        if let Some(rolling) = opts.rolling_shutter{
+            shutter.rolling = rolling;
+        }
+        shutter.check().unwrap_or_else(|e| fail(&format!("shutter: {}", e)));
+        cam.set_shutter(shutter);
+    }
+    if cam.image_height() < 1{
+        fail(&format!("--width {} with aspect ratio gives an image less than one pixel tall", cam.image_width()));
+    }
+
+    let mut settings = opts.output_settings;
+    settings.display = scene.camera.display();
+    if let Some(exposure) = opts.exposure{
+        settings.display.exposure = exposure;
+    }
+    if let Some(tone_map) = opts.tone_map{
+        settings.display.tone_map = tone_map;
+    }
+
+    let lights = Arc::new(scene.lights);
+    let fb = scene.camera.render(&scene.world, lights);
+
+    let written = match &opts.output{
+        Some(path)=>output::write_image(fb, path, &settings),
+        None=>{
+            let mut out = BufWriter::new(io::stdout().lock());
+            output::write_ppm(fb, &settings.display, &mut out).and_then(|_| out.flush())
+        }
+    };
+    if let Err(e) = written{
+        eprintln!("error: failed to write image: {}", e);
+        process::exit(1)
+    }
+
+    if let (Some(path), Some(adaptive)) = (&opts.heatmap, scene.camera.adaptive()){
+        let heatmap = Framebuffer::heatmap(scene.camera.film().width(), scene.camera.film().height(), scene.camera.sample_counts(), adaptive.min_spp, adaptive.max_spp);
+        if let Err(e) = output::write_image(&heatmap, path, &OutputSettings::default()){
+            eprintln!("error: failed to write heatmap: {}", e);
+            process::exit(1)
+        }
+    }
 }
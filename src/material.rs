@@ -72,7 +72,7 @@ impl Metal{
     pub fn new(albedo: Vec3, fuzz: f64)->Metal{
         Metal{
             albedo: albedo,
-            fuzz: if fuzz > 1.0 {
+            fuzz: if fuzz < 1.0 {
                 fuzz
             }else{
                 1.0
@@ -0,0 +1,263 @@
+use crate::vec3::Vec3 as Vec3;
+use crate::aabb::AABB as AABB;
+
+use std::ops::Mul;
+
+// row-major 4x4 matrix acting on column vectors, points get w = 1 and
+// vectors w = 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4{
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4{
+    pub fn new(m: [[f64; 4]; 4])->Self{
+        Self{m}
+    }
+    pub fn identity()->Self{
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn translate(offset: Vec3)->Self{
+        let mut ret = Self::identity();
+        for i in 0..3{
+            ret.m[i][3] = offset[i];
+        }
+        ret
+    }
+    pub fn scale(s: Vec3)->Self{
+        let mut ret = Self::identity();
+        for i in 0..3{
+            ret.m[i][i] = s[i];
+        }
+        ret
+    }
+    // rotations are counter-clockwise looking down the axis towards the
+    // origin, the same sense as RotateY. angles are in degrees
+    pub fn rotate_x(angle: f64)->Self{
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn rotate_y(angle: f64)->Self{
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::new([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn rotate_z(angle: f64)->Self{
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Self::new([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    // rodrigues' formula, axis doesn't need to be normalized
+    pub fn rotate(axis: Vec3, angle: f64)->Self{
+        let a = axis.unit_vector();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let t = 1.0-cos;
+        Self::new([
+            [t*a.x()*a.x()+cos, t*a.x()*a.y()-sin*a.z(), t*a.x()*a.z()+sin*a.y(), 0.0],
+            [t*a.x()*a.y()+sin*a.z(), t*a.y()*a.y()+cos, t*a.y()*a.z()-sin*a.x(), 0.0],
+            [t*a.x()*a.z()-sin*a.y(), t*a.y()*a.z()+sin*a.x(), t*a.z()*a.z()+cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    // moves the origin to from and turns +z towards to, with +y as close to
+    // up as it can be. None when from and to coincide or up is parallel
+    pub fn look_at(from: Vec3, to: Vec3, up: Vec3)->Option<Self>{
+        let w = to-from;
+        let u = up.cross(w);
+        if w.length_squared() == 0.0 || u.length_squared() <= 1e-12*w.length_squared()*up.length_squared(){
+            return None
+        }
+        let w = w.unit_vector();
+        let u = u.unit_vector();
+        let v = w.cross(u);
+        Some(Self::new([
+            [u.x(), v.x(), w.x(), from.x()],
+            [u.y(), v.y(), w.y(), from.y()],
+            [u.z(), v.z(), w.z(), from.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ]))
+    }
+    pub fn get(&self, row: usize, col: usize)->f64{
+        self.m[row][col]
+    }
+    // no perspective, the bottom row is 0 0 0 1
+    pub fn is_affine(&self)->bool{
+        self.m[3] == [0.0, 0.0, 0.0, 1.0]
+    }
+    pub fn transpose(&self)->Self{
+        let mut ret = *self;
+        for i in 0..4{
+            for j in 0..4{
+                ret.m[i][j] = self.m[j][i];
+            }
+        }
+        ret
+    }
+    // gauss-jordan with partial pivoting, None when the matrix is singular
+    pub fn inverse(&self)->Option<Self>{
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4{
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 || !a[pivot][col].is_finite(){
+                return None
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let d = 1.0/a[col][col];
+            for j in 0..4{
+                a[col][j] *= d;
+                inv[col][j] *= d;
+            }
+            for i in 0..4{
+                if i == col{
+                    continue
+                }
+                let f = a[i][col];
+                for j in 0..4{
+                    a[i][j] -= f*a[col][j];
+                    inv[i][j] -= f*inv[col][j];
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+    // of the upper 3x3, which is how much the transform scales volumes
+    pub fn determinant(&self)->f64{
+        let m = &self.m;
+        m[0][0]*(m[1][1]*m[2][2]-m[1][2]*m[2][1])
+            - m[0][1]*(m[1][0]*m[2][2]-m[1][2]*m[2][0])
+            + m[0][2]*(m[1][0]*m[2][1]-m[1][1]*m[2][0])
+    }
+    pub fn point(&self, p: Vec3)->Vec3{
+        let m = &self.m;
+        let x = m[0][0]*p.x()+m[0][1]*p.y()+m[0][2]*p.z()+m[0][3];
+        let y = m[1][0]*p.x()+m[1][1]*p.y()+m[1][2]*p.z()+m[1][3];
+        let z = m[2][0]*p.x()+m[2][1]*p.y()+m[2][2]*p.z()+m[2][3];
+        let w = m[3][0]*p.x()+m[3][1]*p.y()+m[3][2]*p.z()+m[3][3];
+        if w == 1.0 { Vec3::new(x,y,z) } else { Vec3::new(x/w,y/w,z/w) }
+    }
+    pub fn vector(&self, v: Vec3)->Vec3{
+        let m = &self.m;
+        Vec3::new(m[0][0]*v.x()+m[0][1]*v.y()+m[0][2]*v.z(),
+                  m[1][0]*v.x()+m[1][1]*v.y()+m[1][2]*v.z(),
+                  m[2][0]*v.x()+m[2][1]*v.y()+m[2][2]*v.z())
+    }
+    // normals go through the inverse transpose, so call this on the inverse
+    pub fn normal(&self, n: Vec3)->Vec3{
+        let m = &self.m;
+        Vec3::new(m[0][0]*n.x()+m[1][0]*n.y()+m[2][0]*n.z(),
+                  m[0][1]*n.x()+m[1][1]*n.y()+m[2][1]*n.z(),
+                  m[0][2]*n.x()+m[1][2]*n.y()+m[2][2]*n.z())
+    }
+    // arvo's method, each output axis takes the smaller and larger end of
+    // every term. terms with a zero coefficient are skipped so infinite
+    // boxes stay infinite only along the axes they extend in
+    pub fn bbox(&self, b: &AABB)->AABB{
+        let mut min = Vec3::enew();
+        let mut max = Vec3::enew();
+        for i in 0..3{
+            min[i] = self.m[i][3];
+            max[i] = self.m[i][3];
+            for (j, ax) in [b.x(), b.y(), b.z()].iter().enumerate(){
+                let c = self.m[i][j];
+                if c == 0.0{
+                    continue
+                }
+                let (lo, hi) = if c > 0.0 { (c*ax.min, c*ax.max) } else { (c*ax.max, c*ax.min) };
+                min[i] += lo;
+                max[i] += hi;
+            }
+        }
+        AABB::newi(min, max)
+    }
+}
+
+impl Mul for Mat4{
+    type Output = Mat4;
+    // (a*b) applies b first, then a
+    fn mul(self, other: Mat4)->Mat4{
+        let mut ret = [[0.0; 4]; 4];
+        for (i, row) in ret.iter_mut().enumerate(){
+            for (j, v) in row.iter_mut().enumerate(){
+                *v = (0..4).map(|k| self.m[i][k]*other.m[k][j]).sum();
+            }
+        }
+        Mat4::new(ret)
+    }
+}
+
+// unit quaternion for rotations that have to be interpolated
+#[derive(Clone, Copy, Debug)]
+pub struct Quat{
+    pub w: f64,
+    pub v: Vec3,
+}
+
+impl Quat{
+    pub fn identity()->Self{
+        Self{w: 1.0, v: Vec3::enew()}
+    }
+    // same sense as Mat4::rotate, angle in degrees
+    pub fn from_axis_angle(axis: Vec3, angle: f64)->Self{
+        let (sin, cos) = (angle.to_radians()*0.5).sin_cos();
+        Self{w: cos, v: sin*axis.unit_vector()}
+    }
+    pub fn dot(&self, other: Quat)->f64{
+        self.w*other.w+self.v.dot(other.v)
+    }
+    pub fn normalize(&self)->Self{
+        let len = self.dot(*self).sqrt();
+        Self{w: self.w/len, v: self.v/len}
+    }
+    // the rotation from self to other in radians, taking the short way round
+    pub fn angle_to(&self, other: Quat)->f64{
+        2.0*self.dot(other).abs().min(1.0).acos()
+    }
+    // constant angular speed along the shorter arc, so keys more than half
+    // a turn apart need a key in between
+    pub fn slerp(&self, other: Quat, t: f64)->Self{
+        let mut cos = self.dot(other);
+        let mut other = other;
+        if cos < 0.0{
+            cos = -cos;
+            other = Quat{w: -other.w, v: -other.v};
+        }
+        let (a, b) = if cos > 0.9995{
+            // nearly the same rotation, a straight line is close enough
+            (1.0-t, t)
+        }else{
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0-t)*theta).sin()/sin, (t*theta).sin()/sin)
+        };
+        Quat{w: a*self.w+b*other.w, v: a*self.v+b*other.v}.normalize()
+    }
+    pub fn to_mat4(&self)->Mat4{
+        let (w, x, y, z) = (self.w, self.v.x(), self.v.y(), self.v.z());
+        Mat4::new([
+            [1.0-2.0*(y*y+z*z), 2.0*(x*y-w*z), 2.0*(x*z+w*y), 0.0],
+            [2.0*(x*y+w*z), 1.0-2.0*(x*x+z*z), 2.0*(y*z-w*x), 0.0],
+            [2.0*(x*z-w*y), 2.0*(y*z+w*x), 1.0-2.0*(x*x+y*y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
@@ -0,0 +1,207 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::sphere::Sphere as Sphere;
+
+use crate::poly;
+use std::sync::Arc;
+
+// how a ball's field falls from 1 at its center to 0 at its radius, as a
+// polynomial in q, the squared distance over the squared radius
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MetaKernel{
+    // the soft objects of Wyvill, McPheeters and Wyvill
+    Wyvill,
+    // (1-q)^3
+    Cubic,
+}
+
+// one ball. its center moves along a line over the shutter, like a moving
+// sphere, and a negative weight carves into the others
+#[derive(Clone, Copy)]
+pub struct Ball{
+    center: Ray,
+    radius: f64,
+    weight: f64,
+}
+
+// the surface where the summed field of the balls equals threshold. each
+// ball only reaches as far as its radius, so along a ray the field is a
+// polynomial between the points where the ray enters and leaves balls, and
+// each of those pieces is solved exactly
+pub struct Metaballs{
+    balls: Vec<Ball>,
+    kernel: MetaKernel,
+    threshold: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl MetaKernel{
+    pub const NAMES: &'static str = "wyvill, cubic";
+
+    pub fn from_name(name: &str)->Option<Self>{
+        match name{
+            "wyvill"=>Some(MetaKernel::Wyvill),
+            "cubic"=>Some(MetaKernel::Cubic),
+            _=>None,
+        }
+    }
+    // coefficients in q, lowest degree first
+    fn coefficients(&self)->[f64; 4]{
+        match self{
+            MetaKernel::Wyvill=>[1.0, -22.0/9.0, 17.0/9.0, -4.0/9.0],
+            MetaKernel::Cubic=>[1.0, -3.0, 3.0, -1.0],
+        }
+    }
+}
+
+impl Ball{
+    pub fn new(center: Vec3, radius: f64, weight: f64)->Self{
+        Self{center: Ray::new(center, Vec3::enew()), radius: radius.max(0.0), weight}
+    }
+    pub fn newt(center1: Vec3, center2: Vec3, radius: f64, weight: f64)->Self{
+        Self{center: Ray::new(center1, center2-center1), radius: radius.max(0.0), weight}
+    }
+    fn bounding_box(&self)->AABB{
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let (a, b) = (self.center.at(0.0), self.center.at(1.0));
+        AABB::newb(AABB::newi(a-r, a+r), AABB::newi(b-r, b+r))
+    }
+}
+
+impl Metaballs{
+    // threshold is the field on the surface, above 0 and below the weight of
+    // a ball standing alone if it is to show
+    pub fn new(balls: Vec<Ball>, kernel: MetaKernel, threshold: f64, mat: Arc<dyn Material>)->Self{
+        let balls: Vec<Ball> = balls.into_iter().filter(|b| b.radius > 0.0 && b.weight != 0.0).collect();
+        let bbox = balls.iter().filter(|b| b.weight > 0.0)
+            .fold(AABB::empty(), |bbox, b| AABB::newb(bbox, b.bounding_box()));
+        Self{balls, kernel, threshold, mat, bbox}
+    }
+    // which way the field rises fastest at p for the given time
+    fn gradient(&self, p: Vec3, time: f64)->Vec3{
+        let dk = poly::derivative(&self.kernel.coefficients());
+        let mut gradient = Vec3::enew();
+        for ball in &self.balls{
+            let offset = p-ball.center.at(time);
+            let r2 = ball.radius*ball.radius;
+            let q = offset.length_squared()/r2;
+            if q < 1.0{
+                gradient += (ball.weight*poly::eval(&dk, q)*2.0/r2)*offset;
+            }
+        }
+        gradient
+    }
+}
+
+// product of two polynomials, lowest degree first
+fn multiply(a: &[f64], b: &[f64])->Vec<f64>{
+    let mut c = vec![0.0; a.len()+b.len()-1];
+    for (i, &x) in a.iter().enumerate(){
+        for (j, &y) in b.iter().enumerate(){
+            c[i+j] += x*y;
+        }
+    }
+    c
+}
+
+impl Hittable for Metaballs{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let len = r.direction().length();
+        let d = r.direction()/len;
+        let (t_min, t_max) = (ray_t.min()*len, ray_t.max()*len);
+
+        // each ball the ray passes through adds its field between where it
+        // enters and leaves. the ball's box rules most of them out cheaply
+        let mut reach: Vec<(f64, f64, usize)> = Vec::new();
+        for (i, ball) in self.balls.iter().enumerate(){
+            let mut span = ray_t;
+            let rv = Vec3::new(ball.radius, ball.radius, ball.radius);
+            let c = ball.center.at(r.time());
+            if !AABB::newi(c-rv, c+rv).hit(*r, &mut span){
+                continue
+            }
+            let oc = c-r.origin();
+            let h = oc.dot(d);
+            let discriminant = h*h-(oc.length_squared()-ball.radius*ball.radius);
+            if discriminant <= 0.0{
+                continue
+            }
+            let sqrtd = discriminant.sqrt();
+            let (t0, t1) = ((h-sqrtd).max(t_min), (h+sqrtd).min(t_max));
+            if t0 < t1{
+                reach.push((t0, t1, i));
+            }
+        }
+        if !reach.iter().any(|&(_, _, i)| self.balls[i].weight > 0.0){
+            return false
+        }
+
+        let mut ends: Vec<f64> = reach.iter().flat_map(|&(t0, t1, _)| [t0, t1]).collect();
+        ends.sort_by(|a, b| a.total_cmp(b));
+        ends.dedup();
+        // solve from the first end onward, which keeps the coefficients small
+        let start = ends[0];
+        let o = r.origin()+start*d;
+        let k = self.kernel.coefficients();
+
+        let mut found = None;
+        for w in ends.windows(2){
+            let (a, b) = (w[0], w[1]);
+            let mid = 0.5*(a+b);
+            // the field minus the threshold as a polynomial in s = t-start
+            let mut c = vec![-self.threshold];
+            for &(t0, t1, i) in &reach{
+                if mid < t0 || mid > t1{
+                    continue
+                }
+                let ball = &self.balls[i];
+                let oc = o-ball.center.at(r.time());
+                let r2 = ball.radius*ball.radius;
+                let q = [oc.length_squared()/r2, 2.0*oc.dot(d)/r2, 1.0/r2];
+                // k(q(s)) by Horner's rule on polynomials
+                let mut term = vec![k[3]];
+                for j in (0..3).rev(){
+                    term = multiply(&term, &q);
+                    term[0] += k[j];
+                }
+                if c.len() < term.len(){
+                    c.resize(term.len(), 0.0);
+                }
+                for (x, y) in c.iter_mut().zip(&term){
+                    *x += ball.weight*y;
+                }
+            }
+            if c.len() == 1{
+                continue
+            }
+            if let Some(s) = poly::roots_in(&c, a-start, b-start).into_iter().find(|&s| ray_t.surrounds((s+start)/len)){
+                found = Some((s+start)/len);
+                break
+            }
+        }
+        let Some(t) = found else{
+            return false
+        };
+
+        let p = r.at(t);
+        let gradient = self.gradient(p, r.time());
+        // the field falls going out of the surface
+        let normal = if gradient.near_zero(){ -d }else{ -gradient.unit_vector() };
+        rec.setT(t);
+        rec.setP(p);
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &normal);
+        Sphere::get_sphere_uv(normal, &mut rec.u, &mut rec.v);
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+}
+
@@ -0,0 +1,156 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::hittable::Span as Span;
+use crate::hittable::Transform as Transform;
+use crate::matrix::Mat4 as Mat4;
+use crate::matrix::Quat as Quat;
+use crate::ray::Ray as Ray;
+use crate::vec3::Vec3 as Vec3;
+use crate::interval::Interval as Interval;
+use crate::aabb::AABB as AABB;
+
+use std::sync::Arc;
+
+// the object is scaled, then rotated, then translated
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe{
+    pub time: f64,
+    pub translate: Vec3,
+    pub rotate: Quat,
+    pub scale: Vec3,
+}
+
+// moves an object through its keyframes as the ray time goes by. before the
+// first key and after the last one the object holds still
+pub struct AnimatedTransform{
+    object: Arc<dyn Hittable>,
+    keys: Vec<Keyframe>,
+    bbox: AABB,
+}
+
+// poses sampled per pair of keys when bounding the motion
+const BOUND_STEPS: usize = 64;
+
+impl Keyframe{
+    pub fn new(time: f64, translate: Vec3, rotate: Quat, scale: Vec3)->Self{
+        Self{time, translate, rotate, scale}
+    }
+    pub fn matrix(&self)->Mat4{
+        Mat4::translate(self.translate)*self.rotate.to_mat4()*Mat4::scale(self.scale)
+    }
+    // built from the parts, no general inverse needed per ray
+    pub fn inverse(&self)->Mat4{
+        let inv_scale = Vec3::new(1.0/self.scale.x(), 1.0/self.scale.y(), 1.0/self.scale.z());
+        Mat4::scale(inv_scale)*self.rotate.to_mat4().transpose()*Mat4::translate(-self.translate)
+    }
+    fn lerp(&self, other: &Keyframe, t: f64)->Keyframe{
+        Keyframe{
+            time: (1.0-t)*self.time+t*other.time,
+            translate: (1.0-t)*self.translate+t*other.translate,
+            rotate: self.rotate.slerp(other.rotate, t),
+            scale: (1.0-t)*self.scale+t*other.scale,
+        }
+    }
+}
+
+impl AnimatedTransform{
+    // keys are sorted by time, there must be at least one and no scale may
+    // be zero at any key
+    pub fn new(object: Arc<dyn Hittable>, mut keys: Vec<Keyframe>)->Self{
+        assert!(!keys.is_empty(), "an animated transform needs at least one keyframe");
+        assert!(keys.iter().all(|k| k.scale.x()*k.scale.y()*k.scale.z() != 0.0), "keyframe scale must not be zero");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let mut ret = Self{
+            object,
+            keys,
+            bbox: AABB::empty(),
+        };
+        ret.bbox = ret.motion_bounds();
+        ret
+    }
+    pub fn object(&self)->&Arc<dyn Hittable>{
+        &self.object
+    }
+    pub fn keys(&self)->&Vec<Keyframe>{
+        &self.keys
+    }
+    pub fn at(&self, time: f64)->Keyframe{
+        let next = self.keys.partition_point(|k| k.time <= time);
+        if next == 0{
+            return self.keys[0]
+        }
+        if next == self.keys.len(){
+            return self.keys[next-1]
+        }
+        let (a, b) = (&self.keys[next-1], &self.keys[next]);
+        a.lerp(b, (time-a.time)/(b.time-a.time))
+    }
+    // the union of the transformed box at evenly spaced poses, padded by
+    // how far a corner can stray from the straight line between two poses.
+    // with the rotation turning theta and the scale changing by ds over a
+    // pair of keys, a corner at distance r from the origin has an
+    // acceleration of at most r*(theta^2*s + 2*theta*ds), and a path's
+    // chord error over a step h is at most h^2/8 of that
+    fn motion_bounds(&self)->AABB{
+        let local = self.object.bounding_box();
+        let mut bbox = self.keys[0].matrix().bbox(&local);
+        let mut radius: f64 = 0.0;
+        for i in 0..8{
+            let corner = Vec3::new(
+                if i & 1 == 0 { local.x().min } else { local.x().max },
+                if i & 2 == 0 { local.y().min } else { local.y().max },
+                if i & 4 == 0 { local.z().min } else { local.z().max },
+            );
+            radius = radius.max(corner.length());
+        }
+
+        for pair in self.keys.windows(2){
+            let (a, b) = (&pair[0], &pair[1]);
+            for step in 1..=BOUND_STEPS{
+                let pose = a.lerp(b, step as f64/BOUND_STEPS as f64);
+                bbox = AABB::newb(bbox, pose.matrix().bbox(&local));
+            }
+            let theta = a.rotate.angle_to(b.rotate);
+            let mut scale: f64 = 0.0;
+            let mut ds: f64 = 0.0;
+            for c in 0..3{
+                scale = scale.max(a.scale[c].abs()).max(b.scale[c].abs());
+                ds = ds.max((b.scale[c]-a.scale[c]).abs());
+            }
+            let h = 1.0/BOUND_STEPS as f64;
+            let pad = radius*(theta*theta*scale+2.0*theta*ds)*h*h/8.0;
+            if pad > 0.0{
+                bbox = AABB::newb(bbox, AABB::newi(
+                    Vec3::new(bbox.x().min-pad, bbox.y().min-pad, bbox.z().min-pad),
+                    Vec3::new(bbox.x().max+pad, bbox.y().max+pad, bbox.z().max+pad)));
+            }
+        }
+        bbox
+    }
+    // light sampling has no ray time, so it aims at the middle of the
+    // motion. the pdf and the samples agree, which keeps it unbiased
+    fn sampling_pose(&self)->Keyframe{
+        self.at(0.5*(self.keys[0].time+self.keys[self.keys.len()-1].time))
+    }
+}
+
+impl Hittable for AnimatedTransform{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let pose = self.at(r.time());
+        Transform::hit_object(self.object.as_ref(), &pose.matrix(), &pose.inverse(), r, ray_t, rec)
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        Transform::object_pdf_value(self.object.as_ref(), &self.sampling_pose().inverse(), origin, direction)
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        let pose = self.sampling_pose();
+        pose.matrix().vector(self.object.random(pose.inverse().point(origin)))
+    }
+    fn intervals(&self, r: &Ray, spans: &mut Vec<Span>){
+        let pose = self.at(r.time());
+        Transform::object_intervals(self.object.as_ref(), &pose.matrix(), &pose.inverse(), r, spans)
+    }
+}
@@ -0,0 +1,375 @@
+use crate::hittable::Hittable as Hittable;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::material::Lambertian as Lambertian;
+use crate::material::Metal as Metal;
+use crate::material::Dielectric as Dielectric;
+use crate::material::Diffuse_Light as Diffuse_Light;
+use crate::texture::Image_Texture as Image_Texture;
+use crate::triangle::TriangleMesh as TriangleMesh;
+use crate::triangle::MeshData as MeshData;
+use crate::bvh::BvhOptions as BvhOptions;
+use crate::image_tex::texture_path as texture_path;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct ObjError{
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+pub struct ObjModel{
+    pub meshes: Vec<Arc<TriangleMesh>>,
+    pub lights: Vec<Arc<dyn Hittable>>,
+}
+
+// raw MTL fields, turned into a material once the whole entry is read
+#[derive(Clone)]
+struct MtlEntry{
+    kd: Vec3,
+    ks: Vec3,
+    ke: Vec3,
+    ns: f64,
+    ni: f64,
+    dissolve: f64,
+    illum: i32,
+    map_kd: Option<String>,
+}
+
+struct ObjMaterial{
+    mat: Arc<dyn Material>,
+    emissive: bool,
+}
+
+// one unique (position, uv, normal) combination per output vertex
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct FaceGroup{
+    keys: Vec<VertexKey>,
+    lookup: HashMap<VertexKey, usize>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl ObjError{
+    fn new(file: &Path, line: usize, message: String)->Self{
+        Self{
+            file: file.display().to_string(),
+            line,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ObjError{
+    fn fmt(&self, f: &mut fmt::Formatter)->fmt::Result{
+        if self.line == 0{
+            return write!(f, "{}: {}", self.file, self.message)
+        }
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for ObjError{}
+
+impl MtlEntry{
+    fn new()->Self{
+        Self{
+            kd: Vec3::new(0.8,0.8,0.8),
+            ks: Vec3::enew(),
+            ke: Vec3::enew(),
+            ns: 0.0,
+            ni: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+    fn build(&self, dir: &Path)->ObjMaterial{
+        if max_component(self.ke) > 0.0{
+            return ObjMaterial{
+                mat: Arc::new(Diffuse_Light::newc(self.ke)),
+                emissive: true,
+            }
+        }
+
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        let mat: Arc<dyn Material> = if transparent && self.ni > 1.0{
+            Arc::new(Dielectric::new(self.ni))
+        }else if self.illum == 3 || (max_component(self.ks) > max_component(self.kd) && self.map_kd.is_none()){
+            // phong exponent to a rough metal fuzz, Ns 0 is fully rough
+            let fuzz = (2.0/(self.ns+2.0)).sqrt();
+            Arc::new(Metal::new(self.ks, fuzz))
+        }else if let Some(map) = &self.map_kd{
+            Arc::new(Lambertian::newt(Arc::new(Image_Texture::new(&texture_path(dir, map)))))
+        }else{
+            Arc::new(Lambertian::new(self.kd))
+        };
+
+        ObjMaterial{mat, emissive: false}
+    }
+}
+
+impl ObjModel{
+    pub fn load(path: &str)->Result<Self, ObjError>{
+        Self::load_with(path, &BvhOptions::default())
+    }
+    pub fn load_with(path: &str, bvh_options: &BvhOptions)->Result<Self, ObjError>{
+        let path = Path::new(path);
+        let source = fs::read_to_string(path)
+            .map_err(|e| ObjError::new(path, 0, format!("cannot read file: {}", e)))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut uvs: Vec<(f64,f64)> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+
+        let mut materials: HashMap<String, ObjMaterial> = HashMap::new();
+        let mut groups: Vec<(String, FaceGroup)> = Vec::new();
+        let mut current = String::new();
+
+        for (n, raw) in source.lines().enumerate(){
+            let line = n+1;
+            let err = |message: String| ObjError::new(path, line, message);
+            let content = raw.split('#').next().unwrap_or("").trim();
+            let mut tokens = content.split_whitespace();
+            let keyword = match tokens.next(){
+                Some(k)=>k,
+                None=>continue,
+            };
+            let args: Vec<&str> = tokens.collect();
+
+            match keyword{
+                "v"=>positions.push(parse_vec3(&args).map_err(err)?),
+                "vn"=>normals.push(parse_vec3(&args).map_err(err)?),
+                "vt"=>{
+                    if args.is_empty(){
+                        return Err(err("vt needs at least one coordinate".to_string()))
+                    }
+                    let u = parse_f64(args[0]).map_err(err)?;
+                    let v = match args.get(1){
+                        Some(s)=>parse_f64(s).map_err(err)?,
+                        None=>0.0,
+                    };
+                    uvs.push((u,v));
+                }
+                "f"=>{
+                    if args.len() < 3{
+                        return Err(err(format!("face needs at least 3 vertices, found {}", args.len())))
+                    }
+                    let mut keys = Vec::with_capacity(args.len());
+                    for arg in &args{
+                        keys.push(parse_face_vertex(arg, positions.len(), uvs.len(), normals.len()).map_err(err)?);
+                    }
+
+                    let group = match groups.iter_mut().position(|(name, _)| *name == current){
+                        Some(i)=>&mut groups[i].1,
+                        None=>{
+                            groups.push((current.clone(), FaceGroup::default()));
+                            &mut groups.last_mut().unwrap().1
+                        }
+                    };
+                    // fan triangulation, fine for the convex polygons exporters write
+                    let ids: Vec<usize> = keys.into_iter().map(|k| group.vertex(k)).collect();
+                    for i in 1..ids.len()-1{
+                        group.indices.push([ids[0], ids[i], ids[i+1]]);
+                    }
+                }
+                "mtllib"=>{
+                    if args.is_empty(){
+                        return Err(err("mtllib needs a file name".to_string()))
+                    }
+                    let mtl_path = dir.join(args.join(" "));
+                    // a missing library is reported against the line that names it
+                    let entries = load_mtl(&mtl_path).map_err(|e| if e.line == 0{
+                        err(format!("{}: {}", e.file, e.message))
+                    }else{
+                        e
+                    })?;
+                    for (name, entry) in entries{
+                        materials.insert(name, entry.build(mtl_path.parent().unwrap_or(Path::new(""))));
+                    }
+                }
+                "usemtl"=>{
+                    let name = args.join(" ");
+                    if !materials.contains_key(&name){
+                        return Err(err(format!("unknown material '{}'", name)))
+                    }
+                    current = name;
+                }
+                // groups, smoothing and free-form data don't affect the triangles
+                _=>{}
+            }
+        }
+
+        let default_mat: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.73,0.73,0.73)));
+        let mut model = ObjModel{
+            meshes: Vec::new(),
+            lights: Vec::new(),
+        };
+
+        for (name, group) in groups{
+            let (mat, emissive) = match materials.get(&name){
+                Some(m)=>(m.mat.clone(), m.emissive),
+                None=>(default_mat.clone(), false),
+            };
+
+            let has_uvs = group.keys.iter().any(|k| k.1.is_some());
+            let has_normals = group.keys.iter().all(|k| k.2.is_some());
+
+            let mesh_positions = group.keys.iter().map(|k| positions[k.0]).collect();
+            let mesh_uvs = if has_uvs{
+                group.keys.iter().map(|k| k.1.map(|i| uvs[i]).unwrap_or((0.0,0.0))).collect()
+            }else{
+                Vec::new()
+            };
+            let mesh_normals = if has_normals{
+                group.keys.iter().map(|k| normals[k.2.unwrap()]).collect()
+            }else{
+                Vec::new()
+            };
+
+            let data = Arc::new(MeshData::new(mesh_positions, mesh_normals, mesh_uvs, group.indices));
+            let mesh = Arc::new(TriangleMesh::from_data_with(data, mat, bvh_options));
+            if emissive{
+                model.lights.push(mesh.clone());
+            }
+            model.meshes.push(mesh);
+        }
+
+        Ok(model)
+    }
+    pub fn objects(&self)->Vec<Arc<dyn Hittable>>{
+        self.meshes.iter().map(|m| m.clone() as Arc<dyn Hittable>).collect()
+    }
+}
+
+impl FaceGroup{
+    fn vertex(&mut self, key: VertexKey)->usize{
+        if let Some(&i) = self.lookup.get(&key){
+            return i
+        }
+        let i = self.keys.len();
+        self.keys.push(key);
+        self.lookup.insert(key, i);
+        i
+    }
+}
+
+fn load_mtl(path: &Path)->Result<Vec<(String, MtlEntry)>, ObjError>{
+    let source = fs::read_to_string(path)
+        .map_err(|e| ObjError::new(path, 0, format!("cannot read material library: {}", e)))?;
+
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+
+    for (n, raw) in source.lines().enumerate(){
+        let line = n+1;
+        let err = |message: String| ObjError::new(path, line, message);
+        let content = raw.split('#').next().unwrap_or("").trim();
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next(){
+            Some(k)=>k,
+            None=>continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl"{
+            if args.is_empty(){
+                return Err(err("newmtl needs a name".to_string()))
+            }
+            entries.push((args.join(" "), MtlEntry::new()));
+            continue
+        }
+
+        let entry = match entries.last_mut(){
+            Some((_, e))=>e,
+            None=>return Err(err(format!("'{}' before any newmtl", keyword))),
+        };
+
+        match keyword{
+            "Kd"=>entry.kd = parse_vec3(&args).map_err(err)?,
+            "Ks"=>entry.ks = parse_vec3(&args).map_err(err)?,
+            "Ke"=>entry.ke = parse_vec3(&args).map_err(err)?,
+            "Ns"=>entry.ns = parse_scalar(&args).map_err(err)?,
+            "Ni"=>entry.ni = parse_scalar(&args).map_err(err)?,
+            "d"=>entry.dissolve = parse_scalar(&args).map_err(err)?,
+            "Tr"=>entry.dissolve = 1.0-parse_scalar(&args).map_err(err)?,
+            "illum"=>{
+                let s = args.first().ok_or_else(|| err("illum needs a value".to_string()))?;
+                entry.illum = s.parse().map_err(|_| err(format!("invalid illum model '{}'", s)))?;
+            }
+            "map_Kd"=>{
+                // options such as -s or -o come first, the file name is last
+                let file = args.last().ok_or_else(|| err("map_Kd needs a file name".to_string()))?;
+                entry.map_kd = Some(file.to_string());
+            }
+            _=>{}
+        }
+    }
+
+    Ok(entries)
+}
+
+fn max_component(v: Vec3)->f64{
+    v.x().max(v.y()).max(v.z())
+}
+
+fn parse_f64(s: &str)->Result<f64, String>{
+    s.parse::<f64>().map_err(|_| format!("invalid number '{}'", s))
+}
+
+fn parse_scalar(args: &[&str])->Result<f64, String>{
+    match args.first(){
+        Some(s)=>parse_f64(s),
+        None=>Err("expected a number".to_string()),
+    }
+}
+
+fn parse_vec3(args: &[&str])->Result<Vec3, String>{
+    if args.len() < 3{
+        // MTL allows a single value for grey colors
+        if args.len() == 1{
+            let x = parse_f64(args[0])?;
+            return Ok(Vec3::new(x,x,x))
+        }
+        return Err(format!("expected 3 numbers, found {}", args.len()))
+    }
+    Ok(Vec3::new(parse_f64(args[0])?, parse_f64(args[1])?, parse_f64(args[2])?))
+}
+
+// resolves 1-based and negative (relative) OBJ indices to 0-based ones
+fn resolve_index(s: &str, count: usize, what: &str)->Result<usize, String>{
+    let i: i64 = s.parse().map_err(|_| format!("invalid {} index '{}'", what, s))?;
+    let resolved = if i > 0{
+        i-1
+    }else{
+        count as i64+i
+    };
+    if i == 0 || resolved < 0 || resolved >= count as i64{
+        return Err(format!("{} index {} out of range (have {})", what, i, count))
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_face_vertex(s: &str, positions: usize, uvs: usize, normals: usize)->Result<VertexKey, String>{
+    let mut parts = s.split('/');
+    let v = resolve_index(parts.next().unwrap_or(""), positions, "vertex")?;
+    let vt = match parts.next(){
+        Some(p) if !p.is_empty()=>Some(resolve_index(p, uvs, "texture")?),
+        _=>None,
+    };
+    let vn = match parts.next(){
+        Some(p) if !p.is_empty()=>Some(resolve_index(p, normals, "normal")?),
+        _=>None,
+    };
+    if parts.next().is_some(){
+        return Err(format!("malformed face vertex '{}'", s))
+    }
+    Ok((v, vt, vn))
+}
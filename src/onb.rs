@@ -1,6 +1,6 @@
 use crate::vec3::Vec3 as Vec3;
 
-
+#[derive(Clone, Copy)]
 pub struct ONB{
     axis: [Vec3; 3],
 }
@@ -30,4 +30,8 @@ impl ONB{
     pub fn transform(&self, v: Vec3)->Vec3{
         (v[0]*self.axis[0])+(v[1]*self.axis[1])+(v[2]*self.axis[2])
     }
+    // the inverse of transform, world to basis coordinates
+    pub fn local(&self, v: Vec3)->Vec3{
+        Vec3::new(v.dot(self.axis[0]), v.dot(self.axis[1]), v.dot(self.axis[2]))
+    }
 }
\ No newline at end of file
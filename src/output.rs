@@ -0,0 +1,528 @@
+use crate::framebuffer::Framebuffer as Framebuffer;
+use crate::color::Display as Display;
+use crate::zlib;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format{
+    Ppm,
+    Png,
+    Pfm,
+    Hdr,
+    Exr,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExrPixelType{
+    Half,
+    Float,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExrCompression{
+    None,
+    Zip,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OutputSettings{
+    pub display: Display,
+    pub exr_pixel_type: ExrPixelType,
+    pub exr_compression: ExrCompression,
+}
+
+impl Format{
+    pub fn from_path(path: &str)->Option<Self>{
+        let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str(){
+            "ppm"=>Some(Format::Ppm),
+            "png"=>Some(Format::Png),
+            "pfm"=>Some(Format::Pfm),
+            "hdr"=>Some(Format::Hdr),
+            "exr"=>Some(Format::Exr),
+            _=>None,
+        }
+    }
+    pub fn extensions()->&'static str{
+        ".ppm, .png, .pfm, .hdr, .exr"
+    }
+}
+
+impl Default for OutputSettings{
+    fn default()->Self{
+        Self{
+            display: Display::default(),
+            exr_pixel_type: ExrPixelType::Half,
+            exr_compression: ExrCompression::Zip,
+        }
+    }
+}
+
+pub fn write_image(fb: &Framebuffer, path: &str, settings: &OutputSettings)->io::Result<()>{
+    let format = Format::from_path(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+        format!("unsupported image format '{}', expected one of {}", path, Format::extensions())))?;
+    let mut out = BufWriter::new(File::create(path)?);
+    write_format(fb, format, settings, &mut out)?;
+    out.flush()
+}
+
+pub fn write_format(fb: &Framebuffer, format: Format, settings: &OutputSettings, out: &mut dyn Write)->io::Result<()>{
+    match format{
+        Format::Ppm=>write_ppm(fb, &settings.display, out),
+        Format::Png=>write_png(fb, &settings.display, out),
+        Format::Pfm=>write_pfm(fb, out),
+        Format::Hdr=>write_hdr(fb, out),
+        Format::Exr=>write_exr(fb, settings.exr_pixel_type, settings.exr_compression, out),
+    }
+}
+
+// binary P6 ppm
+pub fn write_ppm(fb: &Framebuffer, display: &Display, out: &mut dyn Write)->io::Result<()>{
+    write!(out, "P6\n{} {}\n255\n", fb.width(), fb.height())?;
+    out.write_all(&fb.to_rgb8(display))
+}
+
+// little-endian pfm, stored bottom row first, linear values
+pub fn write_pfm(fb: &Framebuffer, out: &mut dyn Write)->io::Result<()>{
+    write!(out, "PF\n{} {}\n-1.0\n", fb.width(), fb.height())?;
+    let mut row = Vec::with_capacity(fb.width()*12);
+    for y in (0..fb.height()).rev(){
+        row.clear();
+        for x in 0..fb.width(){
+            for c in fb.get(x, y){
+                row.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
+
+pub fn write_png(fb: &Framebuffer, display: &Display, out: &mut dyn Write)->io::Result<()>{
+    let (width, height) = (fb.width(), fb.height());
+    let rgb = fb.to_rgb8(display);
+    let stride = width*3;
+
+    // each scanline gets the filter with the smallest sum of absolute residuals
+    let mut filtered = Vec::with_capacity((stride+1)*height);
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    let zero_row = vec![0u8; stride];
+    for y in 0..height{
+        let row = &rgb[y*stride..(y+1)*stride];
+        let prev = if y > 0{ &rgb[(y-1)*stride..y*stride] }else{ &zero_row[..] };
+
+        let mut best_filter = 0;
+        let mut best_score = u64::MAX;
+        for filter in 0..5u8{
+            for i in 0..stride{
+                let a = if i >= 3{ row[i-3] }else{ 0 };
+                let b = prev[i];
+                let c = if i >= 3{ prev[i-3] }else{ 0 };
+                let predicted = match filter{
+                    0=>0,
+                    1=>a,
+                    2=>b,
+                    3=>((a as u16+b as u16)/2) as u8,
+                    _=>paeth(a, b, c),
+                };
+                candidate[i] = row[i].wrapping_sub(predicted);
+            }
+            let score: u64 = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+            if score < best_score{
+                best_score = score;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+
+    out.write_all(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A])?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit rgb, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_png_chunk(out, b"IHDR", &ihdr)?;
+    write_png_chunk(out, b"IDAT", &zlib::compress(&filtered))?;
+    write_png_chunk(out, b"IEND", &[])
+}
+
+// radiance rgbe with the run-length encoded scanlines most readers expect
+pub fn write_hdr(fb: &Framebuffer, out: &mut dyn Write)->io::Result<()>{
+    let (width, height) = (fb.width(), fb.height());
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut encoded = Vec::with_capacity(width*4);
+    for y in 0..height{
+        for (x, rgbe) in scanline.iter_mut().enumerate(){
+            *rgbe = float_to_rgbe(fb.get(x, y));
+        }
+
+        // the rle format only covers widths 8 to 32767, use flat pixels otherwise
+        if !(8..0x8000).contains(&width){
+            for rgbe in &scanline{
+                out.write_all(rgbe)?;
+            }
+            continue
+        }
+
+        encoded.clear();
+        encoded.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xFF) as u8]);
+        for c in 0..4{
+            let channel: Vec<u8> = scanline.iter().map(|p| p[c]).collect();
+            rle_channel(&channel, &mut encoded);
+        }
+        out.write_all(&encoded)?;
+    }
+    Ok(())
+}
+
+fn float_to_rgbe(c: [f32; 3])->[u8; 4]{
+    let (r, g, b) = (c[0].max(0.0) as f64, c[1].max(0.0) as f64, c[2].max(0.0) as f64);
+    let v = r.max(g).max(b);
+    if !v.is_finite() || v < 1e-32{
+        return [0, 0, 0, 0]
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let mut e = v.log2().floor() as i32+1;
+    if v/2f64.powi(e) >= 1.0{
+        e += 1;
+    }
+    let scale = 256.0/2f64.powi(e);
+    [(r*scale) as u8, (g*scale) as u8, (b*scale) as u8, (e+128) as u8]
+}
+
+// runs of MIN_RUN or more equal bytes become (128+len, byte), the rest literal (len, bytes...)
+fn rle_channel(data: &[u8], out: &mut Vec<u8>){
+    const MIN_RUN: usize = 4;
+    let mut cur = 0;
+    while cur < data.len(){
+        let mut beg_run = cur;
+        let mut run_count = 0;
+        let mut old_run_count = 0;
+        while run_count < MIN_RUN && beg_run < data.len(){
+            beg_run += run_count;
+            old_run_count = run_count;
+            run_count = 1;
+            while beg_run+run_count < data.len() && run_count < 127 && data[beg_run] == data[beg_run+run_count]{
+                run_count += 1;
+            }
+        }
+        // a short run just before the long one is cheaper as a run too
+        if old_run_count > 1 && old_run_count == beg_run-cur{
+            out.push(128+old_run_count as u8);
+            out.push(data[cur]);
+            cur = beg_run;
+        }
+        while cur < beg_run{
+            let count = (beg_run-cur).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&data[cur..cur+count]);
+            cur += count;
+        }
+        if run_count >= MIN_RUN{
+            out.push(128+run_count as u8);
+            out.push(data[beg_run]);
+            cur += run_count;
+        }
+    }
+}
+
+// single part scanline openexr with B, G, R channels
+pub fn write_exr(fb: &Framebuffer, pixel_type: ExrPixelType, compression: ExrCompression, out: &mut dyn Write)->io::Result<()>{
+    let (width, height) = (fb.width(), fb.height());
+    let lines_per_block = match compression{
+        ExrCompression::None=>1,
+        ExrCompression::Zip=>16,
+    };
+    let (type_id, bytes_per_sample) = match pixel_type{
+        ExrPixelType::Half=>(1i32, 2),
+        ExrPixelType::Float=>(2i32, 4),
+    };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2F, 0x31, 0x01, 2, 0, 0, 0]);
+
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"]{
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&type_id.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+
+    let compression_id: u8 = match compression{
+        ExrCompression::None=>0,
+        ExrCompression::Zip=>3,
+    };
+    exr_attribute(&mut header, "compression", "compression", &[compression_id]);
+
+    let mut window = Vec::new();
+    for v in [0, 0, width as i32-1, height as i32-1]{
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+    exr_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    let block_count = height.div_ceil(lines_per_block);
+    let mut blocks = Vec::with_capacity(block_count);
+    for block in 0..block_count{
+        let y0 = block*lines_per_block;
+        let y1 = (y0+lines_per_block).min(height);
+
+        // each scanline holds all of B, then all of G, then all of R
+        let mut raw = Vec::with_capacity((y1-y0)*width*3*bytes_per_sample);
+        for y in y0..y1{
+            for c in [2, 1, 0]{
+                for x in 0..width{
+                    let v = fb.get(x, y)[c];
+                    match pixel_type{
+                        ExrPixelType::Half=>raw.extend_from_slice(&f32_to_half(v).to_le_bytes()),
+                        ExrPixelType::Float=>raw.extend_from_slice(&v.to_le_bytes()),
+                    }
+                }
+            }
+        }
+
+        let data = match compression{
+            ExrCompression::None=>raw,
+            ExrCompression::Zip=>{
+                let packed = zlib::compress(&exr_zip_predict(&raw));
+                // readers treat a block as uncompressed when it is not smaller
+                if packed.len() < raw.len(){ packed }else{ raw }
+            }
+        };
+        blocks.push((y0, data));
+    }
+
+    let mut offset = (header.len()+block_count*8) as u64;
+    for (_, data) in &blocks{
+        header.extend_from_slice(&offset.to_le_bytes());
+        offset += 8+data.len() as u64;
+    }
+    out.write_all(&header)?;
+
+    for (y, data) in &blocks{
+        out.write_all(&(*y as i32).to_le_bytes())?;
+        out.write_all(&(data.len() as i32).to_le_bytes())?;
+        out.write_all(data)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]){
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// ZIP blocks split even and odd bytes, then delta encode them
+fn exr_zip_predict(raw: &[u8])->Vec<u8>{
+    let mut t = Vec::with_capacity(raw.len());
+    t.extend(raw.iter().step_by(2));
+    t.extend(raw.iter().skip(1).step_by(2));
+
+    let mut prev = t.first().copied().unwrap_or(0);
+    for v in t.iter_mut().skip(1){
+        let cur = *v;
+        *v = cur.wrapping_sub(prev).wrapping_add(128);
+        prev = cur;
+    }
+    t
+}
+
+// round to nearest even half float, overflow becomes infinity
+fn f32_to_half(f: f32)->u16{
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let mant = bits & 0x7FFFFF;
+
+    if exp == 0xFF{
+        let nan = if mant != 0{ 0x200 }else{ 0 };
+        return sign | 0x7C00 | nan
+    }
+
+    let e = exp-127+15;
+    if e >= 0x1F{
+        return sign | 0x7C00
+    }
+    if e <= 0{
+        if e < -10{
+            return sign
+        }
+        // subnormal half
+        let m = mant | 0x800000;
+        let shift = (14-e) as u32;
+        let half = m >> shift;
+        let rem = m & ((1 << shift)-1);
+        let halfway = 1 << (shift-1);
+        let round = rem > halfway || (rem == halfway && half & 1 == 1);
+        return sign | (half+round as u32) as u16
+    }
+
+    let half = ((e as u32) << 10) | (mant >> 13);
+    let rem = mant & 0x1FFF;
+    let round = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+    // a carry out of the mantissa correctly bumps the exponent
+    sign | (half+round as u32) as u16
+}
+
+fn paeth(a: u8, b: u8, c: u8)->u8{
+    let p = a as i16+b as i16-c as i16;
+    let pa = (p-a as i16).abs();
+    let pb = (p-b as i16).abs();
+    let pc = (p-c as i16).abs();
+    if pa <= pb && pa <= pc{
+        a
+    }else if pb <= pc{
+        b
+    }else{
+        c
+    }
+}
+
+fn write_png_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8])->io::Result<()>{
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(&[&kind[..], data]);
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32(parts: &[&[u8]])->u32{
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate(){
+        let mut c = n as u32;
+        for _ in 0..8{
+            c = if c & 1 != 0{ 0xEDB88320 ^ (c >> 1) }else{ c >> 1 };
+        }
+        *entry = c;
+    }
+
+    let mut crc = 0xFFFFFFFFu32;
+    for part in parts{
+        for &byte in *part{
+            crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+    crc ^ 0xFFFFFFFF
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::vec3::Vec3 as Vec3;
+    use crate::rng::Pcg32 as Pcg32;
+    use crate::zlib::tests::decompress as decompress;
+
+    // smooth, flat and noisy areas, so every scanline filter gets picked
+    fn test_image(width: usize, height: usize)->Framebuffer{
+        let mut fb = Framebuffer::new(width, height);
+        let mut rng = Pcg32::new(3, 0);
+        for y in 0..height{
+            for x in 0..width{
+                let color = if y < height/3{
+                    Vec3::new(x as f64/width as f64, y as f64/height as f64, 0.5)
+                }else if y < 2*height/3{
+                    Vec3::new(0.2, 0.4, 0.6)
+                }else{
+                    Vec3::new(rng.next_f64(), rng.next_f64(), rng.next_f64())
+                };
+                fb.set(x, y, color);
+            }
+        }
+        fb
+    }
+
+    // unfilters scanlines the way a png reader does
+    fn unfilter(data: &[u8], width: usize, height: usize)->(Vec<u8>, Vec<u8>){
+        let stride = width*3;
+        let mut rgb = vec![0u8; stride*height];
+        let mut filters = Vec::new();
+        for y in 0..height{
+            let line = &data[y*(stride+1)..(y+1)*(stride+1)];
+            filters.push(line[0]);
+            for i in 0..stride{
+                let a = if i >= 3{ rgb[y*stride+i-3] as i16 }else{ 0 };
+                let b = if y > 0{ rgb[(y-1)*stride+i] as i16 }else{ 0 };
+                let c = if i >= 3 && y > 0{ rgb[(y-1)*stride+i-3] as i16 }else{ 0 };
+                let predicted = match line[0]{
+                    0=>0,
+                    1=>a,
+                    2=>b,
+                    3=>(a+b)/2,
+                    4=>{
+                        let p = a+b-c;
+                        let (pa, pb, pc) = ((p-a).abs(), (p-b).abs(), (p-c).abs());
+                        if pa <= pb && pa <= pc{ a }else if pb <= pc{ b }else{ c }
+                    }
+                    f=>panic!("unknown filter {}", f),
+                };
+                rgb[y*stride+i] = line[1+i].wrapping_add(predicted as u8);
+            }
+        }
+        (rgb, filters)
+    }
+
+    #[test]
+    fn png_round_trips_through_a_decoder(){
+        let (width, height) = (61, 45);
+        let fb = test_image(width, height);
+        let display = Display::default();
+        let mut png = Vec::new();
+        write_png(&fb, &display, &mut png).unwrap();
+
+        assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        let mut pos = 8;
+        let mut chunks = Vec::new();
+        while pos < png.len(){
+            let len = u32::from_be_bytes(png[pos..pos+4].try_into().unwrap()) as usize;
+            let kind = &png[pos+4..pos+8];
+            let data = &png[pos+8..pos+8+len];
+            let crc = u32::from_be_bytes(png[pos+8+len..pos+12+len].try_into().unwrap());
+            assert_eq!(crc, crc32(&[kind, data]), "bad crc on {:?}", String::from_utf8_lossy(kind));
+            chunks.push((kind.to_vec(), data.to_vec()));
+            pos += 12+len;
+        }
+        assert_eq!(pos, png.len());
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(k, _)| &k[..]).collect();
+        assert_eq!(kinds, [&b"IHDR"[..], b"IDAT", b"IEND"]);
+
+        let ihdr = &chunks[0].1;
+        assert_eq!(u32::from_be_bytes(ihdr[0..4].try_into().unwrap()) as usize, width);
+        assert_eq!(u32::from_be_bytes(ihdr[4..8].try_into().unwrap()) as usize, height);
+        assert_eq!(ihdr[8..], [8, 2, 0, 0, 0]);
+
+        let (rgb, filters) = unfilter(&decompress(&chunks[1].1), width, height);
+        assert_eq!(rgb, fb.to_rgb8(&display));
+        assert!(filters.iter().any(|&f| f != filters[0]), "expected more than one filter");
+    }
+
+    #[test]
+    fn crc32_matches_known_values(){
+        // every png ends with this chunk
+        assert_eq!(crc32(&[b"IEND"]), 0xAE426082);
+        assert_eq!(crc32(&[b"123", b"456789"]), 0xCBF43926);
+    }
+}
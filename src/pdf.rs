@@ -2,6 +2,7 @@ use crate::vec3::Vec3 as Vec3;
 use crate::onb::ONB as ONB;
 use crate::hittable::Hittable as Hittable;
 
+use crate::sampler;
 use std::sync::Arc;
 
 pub trait PDF{
@@ -89,7 +90,7 @@ impl PDF for MixturePDF{
         0.5*self.p[0].value(direction)+0.5*self.p[1].value(direction)
     }
     fn generate(&self)->Vec3{
-        if Vec3::random_double()<0.5{
+        if sampler::get_1d()<0.5{
             return self.p[0].generate()
         }
         self.p[1].generate()
@@ -1,6 +1,6 @@
 use crate::vec3::Vec3 as Vec3;
 
-use rand::Rng;
+use crate::rng::Pcg32 as Pcg32;
 
 #[derive(Clone)]
 pub struct Perlin{
@@ -12,19 +12,17 @@ pub struct Perlin{
 }
 
 impl Perlin{
-    pub fn new()->Self{
+    // the same seed always gives the same table
+    pub fn newseed(seed: u64)->Self{
+        let mut rng = Pcg32::new(seed, 0x5045524C494E);
         let mut per: Perlin = Perlin::initialize();
         for i in 0..256{
-            per.randVec[i] = Vec3::unit_vector(&Vec3::random_vars(-1.0,1.0));
+            per.randVec[i] = Vec3::unit_vector(&Vec3::new(rng.between(-1.0,1.0), rng.between(-1.0,1.0), rng.between(-1.0,1.0)));
         }
 
-        for i in 0..256{
-           eprintln!("i: {} {} {} {}",i, per.randVec[i].x(), per.randVec[i].y(), per.randVec[i].z());
-        }
-
-        Self::perlin_generate_perm(&mut per.perm_x, per.point_count);
-        Self::perlin_generate_perm(&mut per.perm_y, per.point_count);
-        Self::perlin_generate_perm(&mut per.perm_z, per.point_count);
+        Self::perlin_generate_perm(&mut per.perm_x, per.point_count, &mut rng);
+        Self::perlin_generate_perm(&mut per.perm_y, per.point_count, &mut rng);
+        Self::perlin_generate_perm(&mut per.perm_z, per.point_count, &mut rng);
         per
     }
     pub fn noise(&self, p: Vec3)->f64{
@@ -33,9 +31,10 @@ impl Perlin{
         let w = p.z()-p.z().floor();
         
 
-        let i: usize = p.x().floor() as usize;
-        let j: usize = p.y().floor() as usize;
-        let k: usize = p.z().floor() as usize;
+        // signed, so the lattice doesn't collapse for negative coordinates
+        let i: i64 = p.x().floor() as i64;
+        let j: i64 = p.y().floor() as i64;
+        let k: i64 = p.z().floor() as i64;
 
         let mut c = [[[Vec3::enew(); 2]; 2]; 2];
 
@@ -43,9 +42,9 @@ impl Perlin{
             for dj in 0..2{
                 for dk in 0..2{
                     c[di][dj][dk] = self.randVec[
-                        (self.perm_x[((i+di) & 255) as usize] ^
-                        self.perm_y[((j+dj) & 255) as usize] ^
-                        self.perm_z[((k+dk) & 255) as usize]) as usize
+                        (self.perm_x[((i+di as i64) & 255) as usize] ^
+                        self.perm_y[((j+dj as i64) & 255) as usize] ^
+                        self.perm_z[((k+dk as i64) & 255) as usize]) as usize
                     ];
                 }
             }
@@ -83,19 +82,19 @@ impl Perlin{
             perm_z: vec![0; 256],
         }
     }
-    fn perlin_generate_perm(arr: &mut Vec<i32>, n: usize){
+    fn perlin_generate_perm(arr: &mut Vec<i32>, n: usize, rng: &mut Pcg32){
         for i in 0..n{
             arr[i] = i as i32;
         }
 
-        Self::permute(arr, n);
+        Self::permute(arr, n, rng);
     }
-    fn permute(arr: &mut Vec<i32>, n: usize){
+    fn permute(arr: &mut Vec<i32>, n: usize, rng: &mut Pcg32){
         for i in (0..n).rev(){
-            let target = rand::thread_rng().gen_range(0..=i);
+            let target = rng.below(i+1);
             let tmp = arr[i];
-            arr[i] = arr[target as usize];
-            arr[target as usize] = tmp;
+            arr[i] = arr[target];
+            arr[target] = tmp;
         }
     }
     fn perlin_interp(arr: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64)->f64{
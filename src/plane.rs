@@ -0,0 +1,70 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::onb::ONB as ONB;
+
+use std::sync::Arc;
+
+// the whole plane through point facing along normal. its box is infinite
+// except across an axis aligned plane, so a BVH keeps it out of the tree and
+// tests it on every ray
+pub struct InfinitePlane{
+    point: Vec3,
+    frame: ONB,
+    // texture coordinates repeat every tile units across the plane
+    tile: f64,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl InfinitePlane{
+    pub fn new(point: Vec3, normal: Vec3, mat: Arc<dyn Material>)->Self{
+        Self::newu(point, normal, 1.0, mat)
+    }
+    pub fn newu(point: Vec3, normal: Vec3, tile: f64, mat: Arc<dyn Material>)->Self{
+        let frame = ONB::new(normal);
+        let n = frame.w();
+        let axis = |i: usize|{
+            if n[i].abs() == 1.0 { Interval::new(point[i], point[i]) } else { Interval::universe() }
+        };
+        Self{
+            point,
+            frame,
+            tile,
+            mat,
+            bbox: AABB::new(axis(0), axis(1), axis(2)),
+        }
+    }
+}
+
+impl Hittable for InfinitePlane{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let normal = self.frame.w();
+        let denom = normal.dot(r.direction());
+        if denom.abs() < 1e-8{
+            return false
+        }
+
+        let t = normal.dot(self.point-r.origin())/denom;
+        if !ray_t.contains(t){
+            return false
+        }
+        let p = r.at(t);
+        let local = self.frame.local(p-self.point);
+
+        rec.setT(t);
+        rec.setP(p);
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &normal);
+        rec.u = (local.x()/self.tile).rem_euclid(1.0);
+        rec.v = (local.y()/self.tile).rem_euclid(1.0);
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+}
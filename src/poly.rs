@@ -0,0 +1,143 @@
+// real roots of polynomials, for shapes whose intersections have no simple
+// closed form. coefficients are lowest degree first
+
+pub fn eval(c: &[f64], x: f64)->f64{
+    c.iter().rev().fold(0.0, |acc, &a| acc*x+a)
+}
+
+pub fn derivative(c: &[f64])->Vec<f64>{
+    c.iter().enumerate().skip(1).map(|(i, &a)| i as f64*a).collect()
+}
+
+// roots in [lo, hi] in increasing order. the roots of the derivative split
+// the range into pieces where the polynomial is monotonic, and each piece
+// whose ends differ in sign holds exactly one root, found by bisection
+pub fn roots_in(c: &[f64], lo: f64, hi: f64)->Vec<f64>{
+    let mut c = c;
+    while let [rest @ .., last] = c && *last == 0.0{
+        c = rest;
+    }
+    match c.len(){
+        0 | 1=>return Vec::new(),
+        2=>{
+            let x = -c[0]/c[1];
+            return if lo <= x && x <= hi { vec![x] } else { Vec::new() }
+        }
+        _=>{}
+    }
+
+    let mut ends = vec![lo];
+    ends.extend(roots_in(&derivative(c), lo, hi));
+    ends.push(hi);
+
+    let mut roots = Vec::new();
+    let mut a = lo;
+    let mut fa = eval(c, a);
+    for &b in &ends[1..]{
+        let fb = eval(c, b);
+        if fa == 0.0{
+            if roots.last() != Some(&a){
+                roots.push(a);
+            }
+        }else if fa*fb < 0.0{
+            roots.push(bisect(c, a, b, fa));
+        }
+        a = b;
+        fa = fb;
+    }
+    if fa == 0.0 && roots.last() != Some(&a){
+        roots.push(a);
+    }
+    roots
+}
+
+// f(a) has sign of fa and f(b) the other sign
+fn bisect(c: &[f64], mut a: f64, mut b: f64, fa: f64)->f64{
+    for _ in 0..64{
+        let m = 0.5*(a+b);
+        if m <= a || m >= b{
+            break
+        }
+        let fm = eval(c, m);
+        if fm == 0.0{
+            return m
+        }
+        if (fm < 0.0) == (fa < 0.0){
+            a = m;
+        }else{
+            b = m;
+        }
+    }
+    0.5*(a+b)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // coefficients of the product of (x-r) over the roots, lowest degree first
+    fn from_roots(roots: &[f64])->Vec<f64>{
+        roots.iter().fold(vec![1.0], |c, &r|{
+            let mut next = vec![0.0; c.len()+1];
+            for (i, &a) in c.iter().enumerate(){
+                next[i] -= r*a;
+                next[i+1] += a;
+            }
+            next
+        })
+    }
+
+    fn assert_roots(found: &[f64], expected: &[f64]){
+        assert_eq!(found.len(), expected.len(), "found {:?}, expected {:?}", found, expected);
+        for (f, e) in found.iter().zip(expected){
+            assert!((f-e).abs() < 1e-9, "found {:?}, expected {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn finds_simple_roots_in_order(){
+        let c = from_roots(&[3.0, -1.5, 0.25, 2.0]);
+        assert_roots(&roots_in(&c, -10.0, 10.0), &[-1.5, 0.25, 2.0, 3.0]);
+        // only those inside the range
+        assert_roots(&roots_in(&c, 0.0, 2.5), &[0.25, 2.0]);
+        assert_roots(&roots_in(&c, 3.5, 10.0), &[]);
+    }
+
+    #[test]
+    fn finds_degree_six_roots(){
+        let roots = [-2.0, -0.75, 0.1, 0.5, 1.25, 4.0];
+        assert_roots(&roots_in(&from_roots(&roots), -5.0, 5.0), &roots);
+    }
+
+    #[test]
+    fn reports_a_repeated_root_once(){
+        // touches zero at 1 without crossing
+        let c = from_roots(&[1.0, 1.0, -2.0]);
+        assert_roots(&roots_in(&c, -5.0, 5.0), &[-2.0, 1.0]);
+        // a triple root crosses
+        let c = from_roots(&[0.5, 0.5, 0.5]);
+        assert_roots(&roots_in(&c, -5.0, 5.0), &[0.5]);
+    }
+
+    #[test]
+    fn ignores_zero_leading_coefficients(){
+        // x^2-2 with room for a quartic
+        let c = [-2.0, 0.0, 1.0, 0.0, 0.0];
+        assert_roots(&roots_in(&c, -5.0, 5.0), &[-(2.0f64).sqrt(), 2.0f64.sqrt()]);
+        assert_roots(&roots_in(&[4.0, -2.0, 0.0], -5.0, 5.0), &[2.0]);
+        assert_roots(&roots_in(&[1.0, 0.0, 0.0], -5.0, 5.0), &[]);
+        assert_roots(&roots_in(&[0.0, 0.0], -5.0, 5.0), &[]);
+    }
+
+    #[test]
+    fn finds_roots_at_the_ends_of_the_range(){
+        let c = from_roots(&[-1.0, 1.0]);
+        assert_roots(&roots_in(&c, -1.0, 1.0), &[-1.0, 1.0]);
+    }
+
+    #[test]
+    fn no_real_roots(){
+        // x^4+1
+        assert_roots(&roots_in(&[1.0, 0.0, 0.0, 0.0, 1.0], -10.0, 10.0), &[]);
+    }
+}
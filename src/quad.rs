@@ -7,9 +7,13 @@ use crate::ray::Ray as Ray;
 use crate::interval::Interval as Interval;
 use crate::hittable_list::Hittable_List as Hittable_List;
 
+use crate::sampler;
 use std::sync::Arc;
 
-pub struct Quad{
+// a flat shape spanned by u and v from Q. a hit point Q + a*u + b*v is
+// inside when the shape says (a, b) is, so the plane intersection, area and
+// sampling are shared by every shape
+pub struct Planar<S: PlanarShape>{
     Q: Vec3,
     u: Vec3,
     v: Vec3,
@@ -17,10 +21,48 @@ pub struct Quad{
     normal: Vec3,
     D: f64,
     area: f64,
+    shape: S,
     mat: Arc<dyn Material>,
     bbox: AABB,
 }
 
+// the region of the plane in (a, b) coordinates
+pub trait PlanarShape: Send + Sync{
+    // the texture coordinates of (a, b), or None when it is outside
+    fn interior(&self, a: f64, b: f64)->Option<(f64, f64)>;
+    // in units of the parallelogram spanned by u and v
+    fn area(&self)->f64;
+    // uniform point (a, b) from two canonical samples
+    fn sample(&self, r1: f64, r2: f64)->(f64, f64);
+    fn bounding_box(&self, q: Vec3, u: Vec3, v: Vec3)->AABB;
+}
+
+pub type Quad = Planar<Parallelogram>;
+
+// 0 <= a, b <= 1
+pub struct Parallelogram;
+
+// a, b >= 0 and a+b <= 1, the corners are Q, Q+u and Q+v
+pub struct PlanarTriangle;
+
+// a^2 + b^2 <= 1, u and v are the semi-axes from the center Q
+pub struct Ellipse;
+
+// an ellipse with a hole, inner is the hole's size as a fraction of the
+// outer edge
+pub struct Annulus{
+    pub inner: f64,
+}
+
+// counter-clockwise corners in (a, b)
+pub struct ConvexPolygon{
+    points: Vec<(f64, f64)>,
+    // running area of the fan of triangles from the first corner
+    area_cdf: Vec<f64>,
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
 // box from RTW but Box is a reserved word
 pub struct Cube{
     pub sides: Vec<Arc<dyn Hittable>>,
@@ -28,43 +70,231 @@ pub struct Cube{
 
 impl Quad{
     pub fn new(Q: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material>)->Self{
-        let mut ret: Quad = Self{
-            Q: Q,
-            u: u,
-            v: v,
-            w: Vec3::enew(),
-            normal: Vec3::enew(),
-            D: 0.0,
-            area: 0.0,
-            mat: mat,
-            bbox: AABB::newi(Vec3::enew(),Vec3::enew()),
-        };
+        Self::from_shape(Q, u, v, Parallelogram, mat)
+    }
+}
 
-        let n = Vec3::cross(&ret.u, ret.v);
-        ret.normal = Vec3::unit_vector(&n);
-        ret.D = ret.normal.dot(ret.Q);
-        ret.w = n.clone() / n.dot(n.clone());
+impl<S: PlanarShape> Planar<S>{
+    pub fn from_shape(q: Vec3, u: Vec3, v: Vec3, shape: S, mat: Arc<dyn Material>)->Self{
+        let n = Vec3::cross(&u, v);
+        let normal = Vec3::unit_vector(&n);
+        Self{
+            Q: q,
+            u,
+            v,
+            w: n / n.dot(n),
+            normal,
+            D: normal.dot(q),
+            area: n.length()*shape.area(),
+            bbox: shape.bounding_box(q, u, v),
+            shape,
+            mat,
+        }
+    }
+    pub fn shape(&self)->&S{
+        &self.shape
+    }
+    pub fn area(&self)->f64{
+        self.area
+    }
+}
 
-        ret.area = n.length();
+impl Planar<ConvexPolygon>{
+    // the corners must lie in one plane and go round a convex shape, in
+    // either direction
+    pub fn polygon(points: &[Vec3], mat: Arc<dyn Material>)->Result<Self, String>{
+        if points.len() < 3{
+            return Err(format!("a polygon needs at least 3 corners, got {}", points.len()))
+        }
+        // newell's method, robust to a few nearly collinear corners
+        let mut n = Vec3::enew();
+        for (i, p) in points.iter().enumerate(){
+            n += Vec3::cross(p, points[(i+1)%points.len()]);
+        }
+        if n.length_squared() == 0.0{
+            return Err("polygon corners are collinear".to_string())
+        }
+        let normal = n.unit_vector();
+        let q = points[0];
+        let u = points.iter().map(|&p| p-q).find(|d| !d.near_zero())
+            .ok_or_else(|| "polygon corners all coincide".to_string())?;
+        let v = Vec3::cross(&normal, u);
 
-        ret.set_bounding_box();
-        ret
-    }
-    fn set_bounding_box(&mut self){
-        let bbox_diag1: AABB = AABB::newi(self.Q, self.Q+self.u+self.v);
-        let bbox_diag2: AABB = AABB::newi(self.Q+self.u,self.Q+self.v);
-        self.bbox = AABB::newb(bbox_diag1, bbox_diag2);
+        let size = points.iter().map(|&p| (p-q).length()).fold(0.0, f64::max);
+        if points.iter().any(|&p| normal.dot(p-q).abs() > 1e-9*size.max(1.0)){
+            return Err("polygon corners are not in one plane".to_string())
+        }
+
+        // u and v are perpendicular and the same length
+        let len2 = u.length_squared();
+        let coords: Vec<(f64, f64)> = points.iter().map(|&p| ((p-q).dot(u)/len2, (p-q).dot(v)/len2)).collect();
+        let shape = ConvexPolygon::new(coords)?;
+        Ok(Self::from_shape(q, u, v, shape, mat))
     }
-    fn is_interior(a: f64, b: f64, rec: &mut Hit_record)->bool{
-        let interval = Interval::new(0.0,1.0);
+}
 
+impl PlanarShape for Parallelogram{
+    fn interior(&self, a: f64, b: f64)->Option<(f64, f64)>{
+        let interval = Interval::new(0.0,1.0);
         if !interval.contains(a) || !interval.contains(b){
-            return false 
+            return None
         }
+        Some((a, b))
+    }
+    fn area(&self)->f64{
+        1.0
+    }
+    fn sample(&self, r1: f64, r2: f64)->(f64, f64){
+        (r1, r2)
+    }
+    fn bounding_box(&self, q: Vec3, u: Vec3, v: Vec3)->AABB{
+        let bbox_diag1: AABB = AABB::newi(q, q+u+v);
+        let bbox_diag2: AABB = AABB::newi(q+u, q+v);
+        AABB::newb(bbox_diag1, bbox_diag2)
+    }
+}
 
-        rec.u = a;
-        rec.v = b;
-        true
+impl PlanarShape for PlanarTriangle{
+    fn interior(&self, a: f64, b: f64)->Option<(f64, f64)>{
+        if a < 0.0 || b < 0.0 || a+b > 1.0{
+            return None
+        }
+        Some((a, b))
+    }
+    fn area(&self)->f64{
+        0.5
+    }
+    fn sample(&self, r1: f64, r2: f64)->(f64, f64){
+        let su0 = r1.sqrt();
+        (r2*su0, su0-r2*su0)
+    }
+    fn bounding_box(&self, q: Vec3, u: Vec3, v: Vec3)->AABB{
+        AABB::newb(AABB::newi(q, q+u), AABB::newi(q, q+v))
+    }
+}
+
+// exact box of the curve q + cos(t) u + sin(t) v
+fn ellipse_bbox(q: Vec3, u: Vec3, v: Vec3)->AABB{
+    let mut e = Vec3::enew();
+    for i in 0..3{
+        e[i] = (u[i]*u[i]+v[i]*v[i]).sqrt();
+    }
+    AABB::newi(q-e, q+e)
+}
+
+// u goes around the center and v outwards
+fn polar_uv(a: f64, b: f64)->(f64, f64){
+    ((b.atan2(a)+std::f64::consts::PI)/(2.0*std::f64::consts::PI), (a*a+b*b).sqrt())
+}
+
+impl PlanarShape for Ellipse{
+    fn interior(&self, a: f64, b: f64)->Option<(f64, f64)>{
+        if a*a+b*b > 1.0{
+            return None
+        }
+        Some(polar_uv(a, b))
+    }
+    fn area(&self)->f64{
+        std::f64::consts::PI
+    }
+    fn sample(&self, r1: f64, r2: f64)->(f64, f64){
+        let rho = r1.sqrt();
+        let phi = 2.0*std::f64::consts::PI*r2;
+        (rho*phi.cos(), rho*phi.sin())
+    }
+    fn bounding_box(&self, q: Vec3, u: Vec3, v: Vec3)->AABB{
+        ellipse_bbox(q, u, v)
+    }
+}
+
+impl PlanarShape for Annulus{
+    fn interior(&self, a: f64, b: f64)->Option<(f64, f64)>{
+        let rho2 = a*a+b*b;
+        if rho2 > 1.0 || rho2 < self.inner*self.inner{
+            return None
+        }
+        let (u, rho) = polar_uv(a, b);
+        Some((u, (rho-self.inner)/(1.0-self.inner)))
+    }
+    fn area(&self)->f64{
+        std::f64::consts::PI*(1.0-self.inner*self.inner)
+    }
+    fn sample(&self, r1: f64, r2: f64)->(f64, f64){
+        let inner2 = self.inner*self.inner;
+        let rho = (inner2+r1*(1.0-inner2)).sqrt();
+        let phi = 2.0*std::f64::consts::PI*r2;
+        (rho*phi.cos(), rho*phi.sin())
+    }
+    fn bounding_box(&self, q: Vec3, u: Vec3, v: Vec3)->AABB{
+        ellipse_bbox(q, u, v)
+    }
+}
+
+impl ConvexPolygon{
+    pub fn new(mut points: Vec<(f64, f64)>)->Result<Self, String>{
+        let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| (a.0-o.0)*(b.1-o.1)-(a.1-o.1)*(b.0-o.0);
+        let n = points.len();
+        if n < 3{
+            return Err(format!("a polygon needs at least 3 corners, got {}", n))
+        }
+        let twice_area: f64 = (1..n-1).map(|i| cross(points[0], points[i], points[i+1])).sum();
+        if twice_area == 0.0{
+            return Err("polygon has no area".to_string())
+        }
+        if twice_area < 0.0{
+            points.reverse();
+        }
+        if (0..n).any(|i| cross(points[i], points[(i+1)%n], points[(i+2)%n]) < 0.0){
+            return Err("polygon is not convex".to_string())
+        }
+
+        let mut area_cdf = Vec::with_capacity(n-2);
+        let mut area = 0.0;
+        for i in 1..n-1{
+            area += 0.5*cross(points[0], points[i], points[i+1]);
+            area_cdf.push(area);
+        }
+        let min = points.iter().fold((f64::INFINITY, f64::INFINITY), |m, p| (m.0.min(p.0), m.1.min(p.1)));
+        let max = points.iter().fold((f64::NEG_INFINITY, f64::NEG_INFINITY), |m, p| (m.0.max(p.0), m.1.max(p.1)));
+        Ok(Self{points, area_cdf, min, max})
+    }
+    pub fn points(&self)->&Vec<(f64, f64)>{
+        &self.points
+    }
+}
+
+impl PlanarShape for ConvexPolygon{
+    fn interior(&self, a: f64, b: f64)->Option<(f64, f64)>{
+        let n = self.points.len();
+        for i in 0..n{
+            let (p, q) = (self.points[i], self.points[(i+1)%n]);
+            if (q.0-p.0)*(b-p.1)-(q.1-p.1)*(a-p.0) < 0.0{
+                return None
+            }
+        }
+        // stretched over the polygon's bounds
+        Some(((a-self.min.0)/(self.max.0-self.min.0), (b-self.min.1)/(self.max.1-self.min.1)))
+    }
+    fn area(&self)->f64{
+        self.area_cdf[self.area_cdf.len()-1]
+    }
+    // picks a triangle of the fan by area, then reuses the first number within it
+    fn sample(&self, r1: f64, r2: f64)->(f64, f64){
+        let target = r1*self.area();
+        let i = self.area_cdf.partition_point(|&a| a < target).min(self.area_cdf.len()-1);
+        let lo = if i == 0 { 0.0 } else { self.area_cdf[i-1] };
+        let r1 = ((target-lo)/(self.area_cdf[i]-lo)).clamp(0.0, 1.0);
+        let (p0, p1, p2) = (self.points[0], self.points[i+1], self.points[i+2]);
+        let su0 = r1.sqrt();
+        let (b0, b1) = (1.0-su0, r2*su0);
+        let b2 = 1.0-b0-b1;
+        (b0*p0.0+b1*p1.0+b2*p2.0, b0*p0.1+b1*p1.1+b2*p2.1)
+    }
+    fn bounding_box(&self, q: Vec3, u: Vec3, v: Vec3)->AABB{
+        self.points.iter().fold(AABB::empty(), |bbox, &(a, b)|{
+            let p = q+a*u+b*v;
+            AABB::newb(bbox, AABB::newi(p, p))
+        })
     }
 }
 
@@ -90,7 +320,7 @@ impl Cube{
     }
 }
 
-impl Hittable for Quad{
+impl<S: PlanarShape> Hittable for Planar<S>{
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
         let denom = self.normal.dot(r.direction());
 
@@ -108,10 +338,12 @@ impl Hittable for Quad{
         let alpha = self.w.dot(Vec3::cross(&p_vec, self.v));
         let beta = self.w.dot(Vec3::cross(&self.u, p_vec));
 
-        if !Self::is_interior(alpha, beta, rec){
+        let Some((u, v)) = self.shape.interior(alpha, beta) else{
             return false
-        }
+        };
 
+        rec.u = u;
+        rec.v = v;
         rec.setT(t);
         rec.setP(intersection);
         rec.mat = self.mat.clone();
@@ -133,8 +365,9 @@ impl Hittable for Quad{
         distance_squared/(cosine*self.area)
     }
     fn random(&self, origin: Vec3)->Vec3{
-        let p = self.Q + (Vec3::random_double()*self.u)+(Vec3::random_double()*self.v);
+        let (r1, r2) = sampler::get_2d();
+        let (a, b) = self.shape.sample(r1, r2);
+        let p = self.Q + (a*self.u)+(b*self.v);
         p-origin
     }
 }
-
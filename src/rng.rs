@@ -0,0 +1,46 @@
+// PCG32 (XSH-RR), small and fast enough to rebuild for every camera sample
+#[derive(Clone, Copy, Debug)]
+pub struct Pcg32{
+    state: u64,
+    inc: u64,
+}
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+impl Pcg32{
+    pub fn new(seed: u64, stream: u64)->Self{
+        let mut rng = Pcg32{ state: 0, inc: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+    pub fn next_u32(&mut self)->u32{
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+    // uniform in [0,1), 53 bits from two outputs
+    pub fn next_f64(&mut self)->f64{
+        let hi = (self.next_u32() >> 5) as u64;
+        let lo = (self.next_u32() >> 6) as u64;
+        ((hi << 26) | lo) as f64 * (1.0/(1u64 << 53) as f64)
+    }
+    pub fn between(&mut self, min: f64, max: f64)->f64{
+        min + (max-min)*self.next_f64()
+    }
+    // uniform integer in [0,n), n > 0
+    pub fn below(&mut self, n: usize)->usize{
+        ((self.next_u32() as u64 * n as u64) >> 32) as usize
+    }
+}
+
+// splitmix64 finalizer, spreads neighbouring pixel and sample indices apart
+pub fn mix(mut x: u64)->u64{
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
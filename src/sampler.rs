@@ -0,0 +1,473 @@
+use crate::rng::Pcg32 as Pcg32;
+use crate::rng::mix as mix;
+
+use std::cell::RefCell;
+use std::sync::OnceLock;
+
+// dimension layout of a path: pixel (2), lens (2), time (1), then a fixed
+// block per bounce so the same decision always lands on the same dimension.
+// draws past the end of a block, like a medium sampled for every volume the
+// ray passes, get independent samples rather than the next block's values
+pub const CAMERA_DIMENSIONS: u32 = 5;
+pub const BOUNCE_DIMENSIONS: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SamplerKind{
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+pub trait Sampler{
+    // resets the dimension, called before every camera sample
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+    fn set_dimension(&mut self, dim: u32);
+    fn get_1d(&mut self)->f64;
+    fn get_2d(&mut self)->(f64, f64);
+}
+
+impl SamplerKind{
+    pub const NAMES: &'static str = "independent, stratified, halton, sobol, blue_noise";
+
+    pub fn from_name(name: &str)->Option<Self>{
+        match name.replace('-', "_").as_str(){
+            "independent"=>Some(SamplerKind::Independent),
+            "stratified"=>Some(SamplerKind::Stratified),
+            "halton"=>Some(SamplerKind::Halton),
+            "sobol"=>Some(SamplerKind::Sobol),
+            "blue_noise" | "bluenoise"=>Some(SamplerKind::BlueNoise),
+            _=>None,
+        }
+    }
+    pub fn create(&self, samples_per_pixel: u32, seed: u64)->Box<dyn Sampler>{
+        let state = SampleState::new(samples_per_pixel, seed);
+        match self{
+            SamplerKind::Independent=>Box::new(IndependentSampler{ state }),
+            SamplerKind::Stratified=>Box::new(StratifiedSampler{ state }),
+            SamplerKind::Halton=>Box::new(HaltonSampler{ state }),
+            SamplerKind::Sobol=>Box::new(SobolSampler{ state }),
+            SamplerKind::BlueNoise=>Box::new(BlueNoiseSampler{ state }),
+        }
+    }
+}
+
+// bookkeeping shared by all samplers, rng covers jitter and any dimensions
+// a sampler has no table for
+struct SampleState{
+    spp: u32,
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dim: u32,
+    // end of the current block of dimensions
+    limit: u32,
+    rng: Pcg32,
+}
+
+impl SampleState{
+    fn new(spp: u32, seed: u64)->Self{
+        Self{
+            spp: spp.max(1),
+            seed,
+            pixel: 0,
+            index: 0,
+            dim: 0,
+            limit: CAMERA_DIMENSIONS,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+    fn start(&mut self, x: u32, y: u32, index: u32){
+        self.pixel = ((y as u64) << 32) | x as u64;
+        self.index = index;
+        self.dim = 0;
+        self.limit = CAMERA_DIMENSIONS;
+        self.rng = Pcg32::new(mix(self.seed ^ mix(self.pixel ^ mix(index as u64))), self.pixel);
+    }
+    // per pixel and dimension, the same for every sample of the pixel
+    fn pixel_hash(&self, dim: u32)->u64{
+        mix(self.seed ^ mix(self.pixel ^ mix(0x5A4D_0000_0000 | dim as u64)))
+    }
+    // per dimension only, shared by all pixels
+    fn global_hash(&self, dim: u32)->u64{
+        mix(self.seed ^ mix(0x5A4D_0000_0000 | dim as u64))
+    }
+    fn set_dimension(&mut self, dim: u32){
+        self.dim = dim;
+        self.limit = if dim < CAMERA_DIMENSIONS{
+            CAMERA_DIMENSIONS
+        }else{
+            dim-(dim-CAMERA_DIMENSIONS)%BOUNCE_DIMENSIONS+BOUNCE_DIMENSIONS
+        };
+    }
+    // None once the block is used up
+    fn next_dim(&mut self, count: u32)->Option<u32>{
+        let dim = self.dim;
+        self.dim += count;
+        (self.dim <= self.limit).then_some(dim)
+    }
+}
+
+pub struct IndependentSampler{
+    state: SampleState,
+}
+
+impl Sampler for IndependentSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32){
+        self.state.start(x, y, index)
+    }
+    fn set_dimension(&mut self, dim: u32){
+        self.state.set_dimension(dim)
+    }
+    fn get_1d(&mut self)->f64{
+        self.state.rng.next_f64()
+    }
+    fn get_2d(&mut self)->(f64, f64){
+        (self.state.rng.next_f64(), self.state.rng.next_f64())
+    }
+}
+
+// jittered strata, shuffled independently per pixel and dimension
+pub struct StratifiedSampler{
+    state: SampleState,
+}
+
+impl Sampler for StratifiedSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32){
+        self.state.start(x, y, index)
+    }
+    fn set_dimension(&mut self, dim: u32){
+        self.state.set_dimension(dim)
+    }
+    fn get_1d(&mut self)->f64{
+        let s = &mut self.state;
+        let Some(dim) = s.next_dim(1) else{
+            return s.rng.next_f64()
+        };
+        let stratum = permutation_element(s.index % s.spp, s.spp, s.pixel_hash(dim) as u32);
+        (stratum as f64 + s.rng.next_f64())/s.spp as f64
+    }
+    fn get_2d(&mut self)->(f64, f64){
+        let s = &mut self.state;
+        let Some(dim) = s.next_dim(2) else{
+            return (s.rng.next_f64(), s.rng.next_f64())
+        };
+        let nx = s.spp.isqrt();
+        let ny = s.spp.div_ceil(nx);
+        let stratum = permutation_element(s.index % (nx*ny), nx*ny, s.pixel_hash(dim) as u32);
+        (((stratum % nx) as f64 + s.rng.next_f64())/nx as f64, ((stratum / nx) as f64 + s.rng.next_f64())/ny as f64)
+    }
+}
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+// Halton points with Owen scrambled digits per pixel, dimensions past the
+// prime table fall back to independent samples
+pub struct HaltonSampler{
+    state: SampleState,
+}
+
+impl Sampler for HaltonSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32){
+        self.state.start(x, y, index)
+    }
+    fn set_dimension(&mut self, dim: u32){
+        self.state.set_dimension(dim)
+    }
+    fn get_1d(&mut self)->f64{
+        let s = &mut self.state;
+        let dim = match s.next_dim(1){
+            Some(dim) if (dim as usize) < PRIMES.len()=>dim,
+            _=>return s.rng.next_f64(),
+        };
+        owen_radical_inverse(PRIMES[dim as usize], s.index, s.pixel_hash(dim))
+    }
+    fn get_2d(&mut self)->(f64, f64){
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// (0,2) Sobol pairs with hash based Owen scrambling and a shuffled index per
+// pixel and dimension pair, after Burley 2020
+pub struct SobolSampler{
+    state: SampleState,
+}
+
+impl Sampler for SobolSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32){
+        self.state.start(x, y, index)
+    }
+    fn set_dimension(&mut self, dim: u32){
+        self.state.set_dimension(dim)
+    }
+    fn get_1d(&mut self)->f64{
+        let s = &mut self.state;
+        let Some(dim) = s.next_dim(1) else{
+            return s.rng.next_f64()
+        };
+        to_unit(owen_sobol_1d(s.index, s.pixel_hash(dim)))
+    }
+    fn get_2d(&mut self)->(f64, f64){
+        let s = &mut self.state;
+        let Some(dim) = s.next_dim(2) else{
+            return (s.rng.next_f64(), s.rng.next_f64())
+        };
+        let (x, y) = owen_sobol_2d(s.index, s.pixel_hash(dim));
+        (to_unit(x), to_unit(y))
+    }
+}
+
+// one Sobol sequence shared by every pixel, shifted per pixel by a blue noise
+// mask so the remaining error is spread as high frequency noise
+pub struct BlueNoiseSampler{
+    state: SampleState,
+}
+
+impl BlueNoiseSampler{
+    fn mask(&self, dim: u32)->f64{
+        let table = blue_noise();
+        let offset = self.state.global_hash(dim);
+        let x = (self.state.pixel as u32 as usize + (offset as u32) as usize) % BLUE_NOISE_SIZE;
+        let y = ((self.state.pixel >> 32) as usize + (offset >> 32) as usize) % BLUE_NOISE_SIZE;
+        table[y*BLUE_NOISE_SIZE+x] as f64
+    }
+}
+
+impl Sampler for BlueNoiseSampler{
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32){
+        self.state.start(x, y, index)
+    }
+    fn set_dimension(&mut self, dim: u32){
+        self.state.set_dimension(dim)
+    }
+    fn get_1d(&mut self)->f64{
+        let Some(dim) = self.state.next_dim(1) else{
+            return self.state.rng.next_f64()
+        };
+        let v = to_unit(owen_sobol_1d(self.state.index, self.state.global_hash(dim)));
+        wrap(v + self.mask(dim))
+    }
+    fn get_2d(&mut self)->(f64, f64){
+        let Some(dim) = self.state.next_dim(2) else{
+            return (self.state.rng.next_f64(), self.state.rng.next_f64())
+        };
+        let (x, y) = owen_sobol_2d(self.state.index, self.state.global_hash(dim));
+        (wrap(to_unit(x) + self.mask(dim)), wrap(to_unit(y) + self.mask(dim+1)))
+    }
+}
+
+fn to_unit(v: u32)->f64{
+    v as f64 * (1.0/4294967296.0)
+}
+
+fn wrap(v: f64)->f64{
+    if v >= 1.0 { v-1.0 } else { v }
+}
+
+// each digit is permuted by a hash of the digits before it, which keeps the
+// points apart even for large bases and few samples per pixel
+fn owen_radical_inverse(base: u32, mut index: u32, hash: u64)->f64{
+    let inv_base = 1.0/base as f64;
+    let mut inv_base_n = 1.0;
+    let mut result = 0.0;
+    // only used to key the digit permutations, may wrap for large bases
+    let mut prefix: u64 = 0;
+    while 1.0-(base-1) as f64*inv_base_n < 1.0{
+        let next = index/base;
+        let digit = permutation_element(index - next*base, base, mix(hash ^ prefix) as u32);
+        prefix = prefix.wrapping_mul(base as u64).wrapping_add(digit as u64);
+        inv_base_n *= inv_base;
+        result += digit as f64*inv_base_n;
+        index = next;
+    }
+    result.min(1.0-f64::EPSILON)
+}
+
+// Kensler's hashed permutation of [0,n), cycle walking to stay in range
+fn permutation_element(mut i: u32, n: u32, p: u32)->u32{
+    let mut w = n-1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop{
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n{
+            break
+        }
+    }
+    (i.wrapping_add(p)) % n
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32)->u32{
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32)->u32{
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// second Sobol dimension, the first one is the bit reversed index
+fn sobol_dim1(mut index: u32)->u32{
+    let mut result = 0;
+    let mut v: u32 = 1 << 31;
+    while index != 0{
+        if index & 1 != 0{
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn owen_sobol_1d(index: u32, hash: u64)->u32{
+    let shuffled = nested_uniform_scramble(index, hash as u32);
+    nested_uniform_scramble(shuffled.reverse_bits(), (hash >> 32) as u32)
+}
+
+fn owen_sobol_2d(index: u32, hash: u64)->(u32, u32){
+    let shuffled = nested_uniform_scramble(index, hash as u32);
+    let seed_y = mix(hash) as u32;
+    (nested_uniform_scramble(shuffled.reverse_bits(), (hash >> 32) as u32),
+        nested_uniform_scramble(sobol_dim1(shuffled), seed_y))
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+fn blue_noise()->&'static [f32]{
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    TABLE.get_or_init(void_and_cluster)
+}
+
+// Ulichney's void-and-cluster ranking on a toroidal grid, built once on first use
+fn void_and_cluster()->Vec<f32>{
+    let size = BLUE_NOISE_SIZE;
+    let n = size*size;
+    let sigma: f64 = 1.5;
+
+    let mut kernel = vec![0.0; n];
+    for dy in 0..size{
+        for dx in 0..size{
+            let fx = dx.min(size-dx) as f64;
+            let fy = dy.min(size-dy) as f64;
+            kernel[dy*size+dx] = (-(fx*fx+fy*fy)/(2.0*sigma*sigma)).exp();
+        }
+    }
+
+    let mut energy = vec![0.0; n];
+    let mut on = vec![false; n];
+    let toggle = |energy: &mut Vec<f64>, on: &mut Vec<bool>, i: usize, set: bool|{
+        on[i] = set;
+        let sign = if set { 1.0 } else { -1.0 };
+        let (ix, iy) = (i % size, i / size);
+        for y in 0..size{
+            let ky = (y + size - iy) % size;
+            for x in 0..size{
+                let kx = (x + size - ix) % size;
+                energy[y*size+x] += sign*kernel[ky*size+kx];
+            }
+        }
+    };
+    let tightest_cluster = |energy: &Vec<f64>, on: &Vec<bool>|{
+        (0..n).filter(|&i| on[i]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |energy: &Vec<f64>, on: &Vec<bool>|{
+        (0..n).filter(|&i| !on[i]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    let mut rng = Pcg32::new(0x426C75654E6F6973, 0);
+    let initial = n/10;
+    let mut placed = 0;
+    while placed < initial{
+        let i = rng.below(n);
+        if !on[i]{
+            toggle(&mut energy, &mut on, i, true);
+            placed += 1;
+        }
+    }
+
+    // spread the initial points until moving the tightest cluster doesn't help
+    for _ in 0..n{
+        let cluster = tightest_cluster(&energy, &on);
+        toggle(&mut energy, &mut on, cluster, false);
+        let void = largest_void(&energy, &on);
+        toggle(&mut energy, &mut on, void, true);
+        if void == cluster{
+            break
+        }
+    }
+
+    let mut rank = vec![0; n];
+    let (prototype, prototype_energy) = (on.clone(), energy.clone());
+    for r in (0..initial).rev(){
+        let cluster = tightest_cluster(&energy, &on);
+        toggle(&mut energy, &mut on, cluster, false);
+        rank[cluster] = r;
+    }
+    on = prototype;
+    energy = prototype_energy;
+    for r in initial..n{
+        let void = largest_void(&energy, &on);
+        toggle(&mut energy, &mut on, void, true);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|&r| (r as f32 + 0.5)/n as f32).collect()
+}
+
+thread_local!{
+    static CURRENT: RefCell<Box<dyn Sampler>> = RefCell::new(SamplerKind::Independent.create(1, 0));
+}
+
+// every random decision made while tracing goes through the sampler installed
+// on the current thread, the camera installs one per row of pixels
+pub fn install(sampler: Box<dyn Sampler>){
+    CURRENT.with(|c| *c.borrow_mut() = sampler);
+}
+
+pub fn start_pixel_sample(x: u32, y: u32, index: u32){
+    CURRENT.with(|c| c.borrow_mut().start_pixel_sample(x, y, index));
+}
+
+pub fn start_bounce(bounce: u32){
+    CURRENT.with(|c| c.borrow_mut().set_dimension(CAMERA_DIMENSIONS + bounce*BOUNCE_DIMENSIONS));
+}
+
+pub fn get_1d()->f64{
+    CURRENT.with(|c| c.borrow_mut().get_1d())
+}
+
+pub fn get_2d()->(f64, f64){
+    CURRENT.with(|c| c.borrow_mut().get_2d())
+}
+
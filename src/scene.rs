@@ -0,0 +1,1261 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Transform as Transform;
+use crate::hittable_list::Hittable_List as Hittable_List;
+use crate::vec3::Vec3 as Vec3;
+use crate::camera::Camera as Camera;
+use crate::camera::AdaptiveSampling as AdaptiveSampling;
+use crate::camera::Shutter as Shutter;
+use crate::camera::ShutterCurve as ShutterCurve;
+use crate::material::Material as Material;
+use crate::material::Lambertian as Lambertian;
+use crate::material::Metal as Metal;
+use crate::material::Dielectric as Dielectric;
+use crate::material::Diffuse_Light as Diffuse_Light;
+use crate::material::Isotropic as Isotropic;
+use crate::material::EmptyMat as EmptyMat;
+use crate::hair::Hair as Hair;
+use crate::texture::Texture as Texture;
+use crate::texture::Solid_Color as Solid_Color;
+use crate::texture::Checker_Texture as Checker_Texture;
+use crate::texture::Image_Texture as Image_Texture;
+use crate::texture::Noise_Texture as Noise_Texture;
+use crate::image_tex::texture_path as texture_path;
+use crate::sphere::Sphere as Sphere;
+use crate::quad::Quad as Quad;
+use crate::quad::Cube as Cube;
+use crate::quad::Planar as Planar;
+use crate::quad::Ellipse as Ellipse;
+use crate::quad::Annulus as Annulus;
+use crate::triangle::Triangle as Triangle;
+use crate::disk::Disk as Disk;
+use crate::cylinder::Cylinder as Cylinder;
+use crate::cylinder::Cone as Cone;
+use crate::torus::Torus as Torus;
+use crate::plane::InfinitePlane as InfinitePlane;
+use crate::csg::Csg as Csg;
+use crate::csg::CsgOp as CsgOp;
+use crate::sdf::Sdf as Sdf;
+use crate::sdf::SdfShape as SdfShape;
+use crate::heightfield::Heightfield as Heightfield;
+use crate::curve::Curve as Curve;
+use crate::curve::CurveBasis as CurveBasis;
+use crate::curve::CurveMode as CurveMode;
+use crate::metaballs::Metaballs as Metaballs;
+use crate::metaballs::MetaKernel as MetaKernel;
+use crate::metaballs::Ball as Ball;
+use crate::voxel::VoxelGrid as VoxelGrid;
+use crate::image_tex::RtwImage as RtwImage;
+use crate::perlin::Perlin as Perlin;
+use crate::aabb::AABB as AABB;
+use crate::constant_medium::ConstantMedium as ConstantMedium;
+use crate::obj::ObjModel as ObjModel;
+use crate::instance::Instance as Instance;
+use crate::matrix::Mat4 as Mat4;
+use crate::matrix::Quat as Quat;
+use crate::motion::AnimatedTransform as AnimatedTransform;
+use crate::motion::Keyframe as Keyframe;
+use crate::bvh::BVH as BVH;
+use crate::bvh::BvhOptions as BvhOptions;
+use crate::bvh::BvhStats as BvhStats;
+use crate::bvh::SplitMethod as SplitMethod;
+use crate::color::Display as Display;
+use crate::color::ToneMap as ToneMap;
+use crate::sampler::SamplerKind as SamplerKind;
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// a scene file is a JSON object, see scenes/cornell.json for an example
+pub struct Scene{
+    pub camera: Camera,
+    pub world: Hittable_List,
+    pub lights: Hittable_List,
+}
+
+#[derive(Debug)]
+pub struct SceneError{
+    pub location: String,
+    pub message: String,
+}
+
+type Object = Map<String, Value>;
+
+struct Loader<'a>{
+    dir: PathBuf,
+    texture_defs: Option<&'a Object>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    resolving: Vec<String>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    empty: Arc<dyn Material>,
+    bvh: BvhOptions,
+    geometry_defs: Option<&'a Object>,
+    geometry: HashMap<String, Arc<dyn Hittable>>,
+}
+
+impl SceneError{
+    fn new(location: &str, message: String)->Self{
+        Self{
+            location: location.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for SceneError{
+    fn fmt(&self, f: &mut fmt::Formatter)->fmt::Result{
+        if self.location.is_empty(){
+            return write!(f, "{}", self.message)
+        }
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl std::error::Error for SceneError{}
+
+impl Scene{
+    pub fn load(path: &str)->Result<Self, SceneError>{
+        Self::load_with(path, None)
+    }
+    // bvh, when given, replaces the scene's own "bvh" settings
+    pub fn load_with(path: &str, bvh: Option<&BvhOptions>)->Result<Self, SceneError>{
+        let source = fs::read_to_string(path)
+            .map_err(|e| SceneError::new(path, format!("cannot read scene file: {}", e)))?;
+        let dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        Self::parse_with(&source, &dir, bvh).map_err(|e| SceneError{
+            location: if e.location.is_empty(){ path.to_string() }else{ format!("{}: {}", path, e.location) },
+            message: e.message,
+        })
+    }
+    pub fn parse(source: &str, dir: &Path)->Result<Self, SceneError>{
+        Self::parse_with(source, dir, None)
+    }
+    pub fn parse_with(source: &str, dir: &Path, bvh: Option<&BvhOptions>)->Result<Self, SceneError>{
+        let root: Value = serde_json::from_str(source)
+            .map_err(|e| SceneError::new("", format!("invalid JSON: {}", e)))?;
+        let root = as_object(&root, "")?;
+        check_keys(root, "", &["camera", "textures", "materials", "geometry", "objects", "lights", "bvh"])?;
+
+        // parsed first, meshes loaded from obj files use it too
+        let (use_bvh, scene_bvh) = match root.get("bvh"){
+            Some(v)=>parse_bvh(v, "bvh")?,
+            None=>(false, BvhOptions::default()),
+        };
+        let use_bvh = use_bvh || bvh.is_some();
+
+        let mut loader = Loader{
+            dir: dir.to_path_buf(),
+            texture_defs: None,
+            textures: HashMap::new(),
+            resolving: Vec::new(),
+            materials: HashMap::new(),
+            empty: Arc::new(EmptyMat::new()),
+            bvh: bvh.copied().unwrap_or(scene_bvh),
+            geometry_defs: None,
+            geometry: HashMap::new(),
+        };
+
+        if let Some(textures) = root.get("textures"){
+            let textures = as_object(textures, "textures")?;
+            loader.texture_defs = Some(textures);
+            for name in textures.keys(){
+                loader.texture(name, "textures")?;
+            }
+        }
+
+        if let Some(materials) = root.get("materials"){
+            for (name, def) in as_object(materials, "materials")?{
+                let mat = loader.material(def, &format!("materials.{}", name))?;
+                loader.materials.insert(name.clone(), mat);
+            }
+        }
+
+        // shared geometry is built once here and placed by "instance" objects
+        if let Some(geometry) = root.get("geometry"){
+            let geometry = as_object(geometry, "geometry")?;
+            loader.geometry_defs = Some(geometry);
+            for name in geometry.keys(){
+                loader.geometry(name, "geometry")?;
+            }
+        }
+
+        let camera = match root.get("camera"){
+            Some(c)=>parse_camera(c, "camera")?,
+            None=>parse_camera(&Value::Object(Map::new()), "camera")?,
+        };
+
+        let mut objects: Vec<Arc<dyn Hittable>> = Vec::new();
+        for (i, def) in as_array(root.get("objects").unwrap_or(&Value::Array(Vec::new())), "objects")?.iter().enumerate(){
+            loader.object(def, &format!("objects[{}]", i), true, &mut objects)?;
+        }
+
+        let mut light_objects: Vec<Arc<dyn Hittable>> = Vec::new();
+        for (i, def) in as_array(root.get("lights").unwrap_or(&Value::Array(Vec::new())), "lights")?.iter().enumerate(){
+            loader.object(def, &format!("lights[{}]", i), false, &mut light_objects)?;
+        }
+        let mut lights = Hittable_List::new();
+        for light in light_objects{
+            lights.push(light);
+        }
+
+        // instances always get a top level BVH over their bounds, the
+        // scene's bvh settings only choose how it is built
+        let use_bvh = use_bvh || !loader.geometry.is_empty();
+        let mut world = Hittable_List::new();
+        if use_bvh && !objects.is_empty(){
+            let bvh = BVH::with_options(objects, &loader.bvh);
+            eprintln!("BVH: {}", bvh.stats());
+            world.push(Arc::new(bvh));
+        }else{
+            for obj in objects{
+                world.push(obj);
+            }
+        }
+
+        Ok(Scene{camera, world, lights})
+    }
+}
+
+impl Loader<'_>{
+    fn texture(&mut self, name: &str, location: &str)->Result<Arc<dyn Texture>, SceneError>{
+        if let Some(tex) = self.textures.get(name){
+            return Ok(tex.clone())
+        }
+        let def = match self.texture_defs.and_then(|defs| defs.get(name)){
+            Some(def)=>def,
+            None=>return Err(SceneError::new(location, format!("unknown texture '{}'", name))),
+        };
+        if self.resolving.iter().any(|n| n == name){
+            return Err(SceneError::new(location, format!("texture '{}' refers to itself", name)))
+        }
+
+        self.resolving.push(name.to_string());
+        let location = format!("textures.{}", name);
+        let obj = as_object(def, &location)?;
+        let kind = get_str(obj, "type", &location)?;
+
+        let tex: Arc<dyn Texture> = match kind{
+            "solid"=>{
+                check_keys(obj, &location, &["type", "color"])?;
+                Arc::new(Solid_Color::new(get_vec3(obj, "color", &location)?))
+            }
+            "checker"=>{
+                check_keys(obj, &location, &["type", "scale", "even", "odd"])?;
+                let scale = get_f64(obj, "scale", &location)?;
+                let even = self.color_or_texture(obj, "even", &location)?;
+                let odd = self.color_or_texture(obj, "odd", &location)?;
+                Arc::new(Checker_Texture::new(scale, even, odd))
+            }
+            "image"=>{
+                check_keys(obj, &location, &["type", "file"])?;
+                let file = get_str(obj, "file", &location)?;
+                Arc::new(Image_Texture::new(&texture_path(&self.dir, file)))
+            }
+            "noise"=>{
+                check_keys(obj, &location, &["type", "scale", "seed"])?;
+                Arc::new(Noise_Texture::new(get_f64(obj, "scale", &location)?, opt_u64(obj, "seed", &location, 0)?))
+            }
+            _=>return Err(unknown_type(&location, kind, &["solid", "checker", "image", "noise"])),
+        };
+        self.resolving.pop();
+
+        self.textures.insert(name.to_string(), tex.clone());
+        Ok(tex)
+    }
+    // a color array or the name of a texture
+    fn color_or_texture(&mut self, obj: &Object, key: &str, location: &str)->Result<Arc<dyn Texture>, SceneError>{
+        let field = format!("{}.{}", location, key);
+        match obj.get(key){
+            Some(Value::String(name))=>self.texture(name, &field),
+            Some(v)=>Ok(Arc::new(Solid_Color::new(as_vec3(v, &field)?))),
+            None=>Err(SceneError::new(location, format!("missing key '{}'", key))),
+        }
+    }
+    fn material(&mut self, def: &Value, location: &str)->Result<Arc<dyn Material>, SceneError>{
+        let obj = as_object(def, location)?;
+        let kind = get_str(obj, "type", location)?;
+
+        let mat: Arc<dyn Material> = match kind{
+            "lambertian"=>{
+                check_keys(obj, location, &["type", "albedo"])?;
+                Arc::new(Lambertian::newt(self.color_or_texture(obj, "albedo", location)?))
+            }
+            "metal"=>{
+                check_keys(obj, location, &["type", "albedo", "fuzz"])?;
+                Arc::new(Metal::new(get_vec3(obj, "albedo", location)?, opt_f64(obj, "fuzz", location, 0.0)?))
+            }
+            "dielectric"=>{
+                check_keys(obj, location, &["type", "refraction_index"])?;
+                Arc::new(Dielectric::new(get_f64(obj, "refraction_index", location)?))
+            }
+            "diffuse_light"=>{
+                check_keys(obj, location, &["type", "emit"])?;
+                Arc::new(Diffuse_Light::new(self.color_or_texture(obj, "emit", location)?))
+            }
+            "isotropic"=>{
+                check_keys(obj, location, &["type", "albedo"])?;
+                Arc::new(Isotropic::new(self.color_or_texture(obj, "albedo", location)?))
+            }
+            "hair"=>{
+                check_keys(obj, location, &["type", "sigma_a", "color", "eumelanin", "pheomelanin", "beta_m", "beta_n", "alpha", "eta"])?;
+                let beta_m = opt_f64(obj, "beta_m", location, Hair::DEFAULT_BETA_M)?;
+                let beta_n = opt_f64(obj, "beta_n", location, Hair::DEFAULT_BETA_N)?;
+                for (key, beta) in [("beta_m", beta_m), ("beta_n", beta_n)]{
+                    if !(0.0..=1.0).contains(&beta){
+                        return Err(SceneError::new(&format!("{}.{}", location, key), "expected a number from 0 to 1".to_string()))
+                    }
+                }
+                let melanin = obj.contains_key("eumelanin") || obj.contains_key("pheomelanin");
+                let sigma_a = match (obj.contains_key("sigma_a"), obj.contains_key("color"), melanin){
+                    (true, false, false)=>get_vec3(obj, "sigma_a", location)?,
+                    (false, true, false)=>Hair::sigma_a_from_color(get_vec3(obj, "color", location)?, beta_n),
+                    (false, false, _)=>Hair::sigma_a_from_melanin(opt_f64(obj, "eumelanin", location, 1.3)?,
+                        opt_f64(obj, "pheomelanin", location, 0.0)?),
+                    _=>return Err(SceneError::new(location, "expected only one of sigma_a, color or melanin".to_string())),
+                };
+                if (0..3).any(|i| sigma_a[i] < 0.0){
+                    return Err(SceneError::new(location, "absorption can't be negative".to_string()))
+                }
+                Arc::new(Hair::new(sigma_a, beta_m, beta_n, opt_f64(obj, "alpha", location, Hair::DEFAULT_ALPHA)?,
+                    opt_f64(obj, "eta", location, Hair::DEFAULT_ETA)?))
+            }
+            _=>return Err(unknown_type(location, kind, &["lambertian", "metal", "dielectric", "diffuse_light", "isotropic", "hair"])),
+        };
+        Ok(mat)
+    }
+    fn material_ref(&self, obj: &Object, location: &str, required: bool)->Result<Arc<dyn Material>, SceneError>{
+        match obj.get("material"){
+            Some(Value::String(name))=>match self.materials.get(name){
+                Some(mat)=>Ok(mat.clone()),
+                None=>Err(SceneError::new(&format!("{}.material", location), format!("unknown material '{}'", name))),
+            },
+            Some(_)=>Err(SceneError::new(&format!("{}.material", location), "expected a material name".to_string())),
+            None if required=>Err(SceneError::new(location, "missing key 'material'".to_string())),
+            None=>Ok(self.empty.clone()),
+        }
+    }
+    // one object or a list of them, a list gets its own BVH
+    fn geometry(&mut self, name: &str, location: &str)->Result<Arc<dyn Hittable>, SceneError>{
+        if let Some(geometry) = self.geometry.get(name){
+            return Ok(geometry.clone())
+        }
+        let def = match self.geometry_defs.and_then(|defs| defs.get(name)){
+            Some(def)=>def,
+            None=>return Err(SceneError::new(location, format!("unknown geometry '{}'", name))),
+        };
+        if self.resolving.iter().any(|n| n == name){
+            return Err(SceneError::new(location, format!("geometry '{}' refers to itself", name)))
+        }
+
+        self.resolving.push(name.to_string());
+        let location = format!("geometry.{}", name);
+        let mut parts = Vec::new();
+        match def{
+            Value::Array(defs)=>{
+                for (i, def) in defs.iter().enumerate(){
+                    self.object(def, &format!("{}[{}]", location, i), true, &mut parts)?;
+                }
+            }
+            _=>self.object(def, &location, true, &mut parts)?,
+        }
+        let geometry: Arc<dyn Hittable> = match parts.len(){
+            0=>return Err(SceneError::new(&location, "expected at least one object".to_string())),
+            1=>parts.remove(0),
+            _=>{
+                let bvh = BVH::with_options(parts, &self.bvh);
+                eprintln!("BVH for geometry '{}': {}", name, bvh.stats());
+                Arc::new(bvh)
+            }
+        };
+        self.resolving.pop();
+
+        self.geometry.insert(name.to_string(), geometry.clone());
+        Ok(geometry)
+    }
+    // shapes in the lights list don't need a material, they are only sampled
+    fn object(&mut self, def: &Value, location: &str, needs_material: bool, out: &mut Vec<Arc<dyn Hittable>>)->Result<(), SceneError>{
+        let obj = as_object(def, location)?;
+        let kind = get_str(obj, "type", location)?;
+
+        let shape: Arc<dyn Hittable> = match kind{
+            "sphere"=>{
+                check_keys(obj, location, &["type", "center", "center2", "radius", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let center = get_vec3(obj, "center", location)?;
+                let radius = get_f64(obj, "radius", location)?;
+                match obj.get("center2"){
+                    Some(v)=>Arc::new(Sphere::newt(center, as_vec3(v, &format!("{}.center2", location))?, radius, mat)),
+                    None=>Arc::new(Sphere::new(center, radius, mat)),
+                }
+            }
+            "quad"=>{
+                check_keys(obj, location, &["type", "q", "u", "v", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                Arc::new(Quad::new(get_vec3(obj, "q", location)?, get_vec3(obj, "u", location)?, get_vec3(obj, "v", location)?, mat))
+            }
+            "ellipse" | "annulus"=>{
+                let keys: &[&str] = if kind == "annulus"{
+                    &["type", "center", "u", "v", "inner", "material", "transforms", "motion"]
+                }else{
+                    &["type", "center", "u", "v", "material", "transforms", "motion"]
+                };
+                check_keys(obj, location, keys)?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let center = get_vec3(obj, "center", location)?;
+                let (u, v) = (get_vec3(obj, "u", location)?, get_vec3(obj, "v", location)?);
+                if u.cross(v).near_zero(){
+                    return Err(SceneError::new(location, "u and v must not be parallel".to_string()))
+                }
+                if kind == "ellipse"{
+                    Arc::new(Planar::from_shape(center, u, v, Ellipse, mat))
+                }else{
+                    let inner = get_f64(obj, "inner", location)?;
+                    if !(0.0..1.0).contains(&inner){
+                        return Err(SceneError::new(&format!("{}.inner", location), "expected a number from 0 up to 1".to_string()))
+                    }
+                    Arc::new(Planar::from_shape(center, u, v, Annulus{ inner }, mat))
+                }
+            }
+            "polygon"=>{
+                check_keys(obj, location, &["type", "points", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let here = format!("{}.points", location);
+                let points = as_array(field(obj, "points", location)?, &here)?.iter().enumerate()
+                    .map(|(i, p)| as_vec3(p, &format!("{}[{}]", here, i)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Arc::new(Planar::polygon(&points, mat).map_err(|e| SceneError::new(&here, e))?)
+            }
+            "triangle"=>{
+                check_keys(obj, location, &["type", "a", "b", "c", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                Arc::new(Triangle::new(get_vec3(obj, "a", location)?, get_vec3(obj, "b", location)?, get_vec3(obj, "c", location)?, mat))
+            }
+            "cube"=>{
+                check_keys(obj, location, &["type", "a", "b", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                Cube::new(get_vec3(obj, "a", location)?, get_vec3(obj, "b", location)?, mat)
+            }
+            "constant_medium"=>{
+                check_keys(obj, location, &["type", "boundary", "density", "albedo", "transforms", "motion"])?;
+                let boundary_def = obj.get("boundary")
+                    .ok_or_else(|| SceneError::new(location, "missing key 'boundary'".to_string()))?;
+                let mut boundary = Vec::new();
+                self.object(boundary_def, &format!("{}.boundary", location), false, &mut boundary)?;
+                let density = get_f64(obj, "density", location)?;
+                let albedo = self.color_or_texture(obj, "albedo", location)?;
+                Arc::new(ConstantMedium::new(boundary.remove(0), density, albedo))
+            }
+            "obj"=>{
+                check_keys(obj, location, &["type", "file", "transforms", "motion"])?;
+                let file = get_str(obj, "file", location)?;
+                let path = self.dir.join(file);
+                let model = ObjModel::load_with(&path.display().to_string(), &self.bvh)
+                    .map_err(|e| SceneError::new(location, e.to_string()))?;
+                if let Some(stats) = model.meshes.iter().filter_map(|m| m.bvh()).map(BVH::stats).reduce(BvhStats::merge){
+                    eprintln!("BVH for '{}': {}", file, stats);
+                }
+                let mut parts = model.objects();
+                if parts.len() == 1{
+                    parts.remove(0)
+                }else if parts.is_empty(){
+                    return Err(SceneError::new(location, format!("'{}' contains no faces", file)))
+                }else{
+                    Arc::new(BVH::with_options(parts, &self.bvh))
+                }
+            }
+            "csg"=>{
+                check_keys(obj, location, &["type", "operation", "left", "right", "material", "transforms", "motion"])?;
+                let name = get_str(obj, "operation", location)?;
+                let op = CsgOp::from_name(name).ok_or_else(|| SceneError::new(&format!("{}.operation", location),
+                    format!("unknown operation '{}' (expected one of: {})", name, CsgOp::NAMES)))?;
+                // with a material of its own the sides don't need one
+                let material = if needs_material && obj.contains_key("material"){
+                    Some(self.material_ref(obj, location, true)?)
+                }else{
+                    None
+                };
+                let mut sides = Vec::new();
+                for key in ["left", "right"]{
+                    let def = field(obj, key, location)?;
+                    self.object(def, &format!("{}.{}", location, key), needs_material && material.is_none(), &mut sides)?;
+                }
+                let right = sides.pop().unwrap();
+                let left = sides.pop().unwrap();
+                match material{
+                    Some(mat)=>Arc::new(Csg::newm(op, left, right, mat)),
+                    None=>Arc::new(Csg::new(op, left, right)),
+                }
+            }
+            "sdf"=>{
+                check_keys(obj, location, &["type", "shape", "bound", "max_steps", "epsilon", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let sdf = parse_sdf(field(obj, "shape", location)?, &format!("{}.shape", location))?;
+                let here = format!("{}.bound", location);
+                let bound = as_object(field(obj, "bound", location)?, &here)?;
+                check_keys(bound, &here, &["min", "max"])?;
+                let (min, max) = (get_vec3(bound, "min", &here)?, get_vec3(bound, "max", &here)?);
+                if (0..3).any(|i| min[i] >= max[i]){
+                    return Err(SceneError::new(&here, "min must be below max on every axis".to_string()))
+                }
+                let mut shape = SdfShape::new(sdf, AABB::newi(min, max), mat);
+                shape.set_max_steps(opt_u32(obj, "max_steps", location, SdfShape::DEFAULT_MAX_STEPS)?);
+                if obj.contains_key("epsilon"){
+                    shape.set_epsilon(get_positive(obj, "epsilon", location)?);
+                }
+                Arc::new(shape)
+            }
+            "heightfield"=>{
+                check_keys(obj, location, &["type", "corner", "size", "image", "noise", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let corner = get_vec3(obj, "corner", location)?;
+                let size = get_vec3(obj, "size", location)?;
+                if size.x() <= 0.0 || size.z() <= 0.0{
+                    return Err(SceneError::new(&format!("{}.size", location), "expected a positive width and depth".to_string()))
+                }
+                match (obj.get("image"), obj.get("noise")){
+                    (Some(_), None)=>{
+                        let file = get_str(obj, "image", location)?;
+                        let image = RtwImage::from_file(&texture_path(&self.dir, file));
+                        if image.width() < 2 || image.height() < 2{
+                            return Err(SceneError::new(&format!("{}.image", location), format!("could not load '{}' as an image of at least 2x2 pixels", file)))
+                        }
+                        Arc::new(Heightfield::from_image(&image, corner, size, mat))
+                    }
+                    (None, Some(noise))=>{
+                        let here = format!("{}.noise", location);
+                        let noise = as_object(noise, &here)?;
+                        check_keys(noise, &here, &["samples", "frequency", "octaves", "seed"])?;
+                        let samples = format!("{}.samples", here);
+                        let counts = as_array(field(noise, "samples", &here)?, &samples)?;
+                        let counts = counts.iter().map(|v| as_f64(v, &samples)).collect::<Result<Vec<f64>, SceneError>>()?;
+                        let &[nx, nz] = counts.as_slice() else{
+                            return Err(SceneError::new(&samples, "expected [x, z] sample counts".to_string()))
+                        };
+                        if [nx, nz].iter().any(|&n| n.fract() != 0.0 || !(2.0..=16384.0).contains(&n)){
+                            return Err(SceneError::new(&samples, "expected whole numbers from 2 to 16384".to_string()))
+                        }
+                        let octaves = opt_u32(noise, "octaves", &here, 7)?;
+                        if !(1..=16).contains(&octaves){
+                            return Err(SceneError::new(&format!("{}.octaves", here), "expected 1 to 16".to_string()))
+                        }
+                        let perlin = Perlin::newseed(opt_u64(noise, "seed", &here, 0)?);
+                        Arc::new(Heightfield::from_noise(&perlin, get_positive(noise, "frequency", &here)?, octaves as i32,
+                            (nx as usize, nz as usize), corner, size, mat))
+                    }
+                    _=>return Err(SceneError::new(location, "expected either an image or noise".to_string())),
+                }
+            }
+            "curves"=>{
+                check_keys(obj, location, &["type", "strands", "basis", "mode", "width", "normal", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let name = opt_str(obj, "basis", location, "bspline")?;
+                let basis = CurveBasis::from_name(name).ok_or_else(|| SceneError::new(&format!("{}.basis", location),
+                    format!("unknown basis '{}' (expected one of: {})", name, CurveBasis::NAMES)))?;
+                let name = opt_str(obj, "mode", location, "tube")?;
+                let mode = CurveMode::from_name(name).ok_or_else(|| SceneError::new(&format!("{}.mode", location),
+                    format!("unknown mode '{}' (expected one of: {})", name, CurveMode::NAMES)))?;
+                let here = format!("{}.width", location);
+                let width = match field(obj, "width", location)?{
+                    Value::Array(w) if w.len() == 2=>[as_f64(&w[0], &here)?, as_f64(&w[1], &here)?],
+                    w=>{
+                        let w = as_f64(w, &here)?;
+                        [w, w]
+                    }
+                };
+                if width.iter().any(|&w| w < 0.0) || width.iter().all(|&w| w == 0.0){
+                    return Err(SceneError::new(&here, "expected a width, or [start, end] widths, that isn't negative or zero".to_string()))
+                }
+                let normal = match obj.get("normal"){
+                    Some(_) if mode == CurveMode::Ribbon=>Some(get_direction(obj, "normal", location)?),
+                    Some(_)=>return Err(SceneError::new(&format!("{}.normal", location), "only a ribbon can have a normal".to_string())),
+                    None=>None,
+                };
+                let here = format!("{}.strands", location);
+                let mut pieces = Vec::new();
+                for (i, strand) in as_array(field(obj, "strands", location)?, &here)?.iter().enumerate(){
+                    let at = format!("{}[{}]", here, i);
+                    let points = as_array(strand, &at)?.iter().enumerate()
+                        .map(|(k, p)| as_vec3(p, &format!("{}[{}]", at, k))).collect::<Result<Vec<Vec3>, SceneError>>()?;
+                    if !basis.valid_count(points.len()){
+                        let expected = match basis{
+                            CurveBasis::Bezier=>"4, 7, 10 or more points, three per segment plus one",
+                            CurveBasis::BSpline=>"at least 4 points",
+                        };
+                        return Err(SceneError::new(&at, format!("expected {}", expected)))
+                    }
+                    pieces.extend(Curve::strand(&points, basis, width, mode, normal, mat.clone()));
+                }
+                match pieces.len(){
+                    0=>return Err(SceneError::new(&here, "expected at least one strand".to_string())),
+                    1=>pieces.remove(0),
+                    _=>Arc::new(BVH::with_options(pieces, &self.bvh)),
+                }
+            }
+            "metaballs"=>{
+                check_keys(obj, location, &["type", "balls", "threshold", "kernel", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let name = opt_str(obj, "kernel", location, "wyvill")?;
+                let kernel = MetaKernel::from_name(name).ok_or_else(|| SceneError::new(&format!("{}.kernel", location),
+                    format!("unknown kernel '{}' (expected one of: {})", name, MetaKernel::NAMES)))?;
+                let threshold = get_positive(obj, "threshold", location)?;
+                let here = format!("{}.balls", location);
+                let mut balls = Vec::new();
+                for (i, def) in as_array(field(obj, "balls", location)?, &here)?.iter().enumerate(){
+                    let at = format!("{}[{}]", here, i);
+                    let ball = as_object(def, &at)?;
+                    check_keys(ball, &at, &["center", "center2", "radius", "weight"])?;
+                    let center = get_vec3(ball, "center", &at)?;
+                    let radius = get_positive(ball, "radius", &at)?;
+                    let weight = opt_f64(ball, "weight", &at, 1.0)?;
+                    balls.push(match ball.get("center2"){
+                        Some(v)=>Ball::newt(center, as_vec3(v, &format!("{}.center2", at))?, radius, weight),
+                        None=>Ball::new(center, radius, weight),
+                    });
+                }
+                if balls.is_empty(){
+                    return Err(SceneError::new(&here, "expected at least one ball".to_string()))
+                }
+                Arc::new(Metaballs::new(balls, kernel, threshold, mat))
+            }
+            "voxels"=>{
+                check_keys(obj, location, &["type", "origin", "size", "dims", "palette", "sparse", "data", "boxes", "cells", "transforms", "motion"])?;
+                let here = format!("{}.dims", location);
+                let dims = as_indices(field(obj, "dims", location)?, &here, 3)?;
+                if dims.contains(&0) || dims.iter().try_fold(1usize, |a, &n| a.checked_mul(n)).is_none_or(|n| n > 1 << 30){
+                    return Err(SceneError::new(&here, "expected three sizes above 0 with at most 2^30 cells in all".to_string()))
+                }
+                let dims = [dims[0], dims[1], dims[2]];
+                let here = format!("{}.palette", location);
+                let mut palette = Vec::new();
+                for (i, name) in as_array(field(obj, "palette", location)?, &here)?.iter().enumerate(){
+                    let at = format!("{}[{}]", here, i);
+                    let name = name.as_str().ok_or_else(|| SceneError::new(&at, "expected a material name".to_string()))?;
+                    palette.push(self.materials.get(name).cloned()
+                        .ok_or_else(|| SceneError::new(&at, format!("unknown material '{}'", name)))?);
+                }
+                let materials = palette.len();
+                if materials == 0 || materials >= u16::MAX as usize{
+                    return Err(SceneError::new(&here, format!("expected 1 to {} material names", u16::MAX-1)))
+                }
+                let (origin, size) = (get_vec3(obj, "origin", location)?, get_positive(obj, "size", location)?);
+                let mut grid = if opt_bool(obj, "sparse", location, false)?{
+                    VoxelGrid::sparse(origin, size, dims, palette)
+                }else{
+                    VoxelGrid::new(origin, size, dims, palette)
+                };
+                let value = |v: &Value, at: &str|->Result<u16, SceneError>{
+                    match v.as_u64(){
+                        Some(n) if n as usize <= materials=>Ok(n as u16),
+                        _=>Err(SceneError::new(at, format!("expected 0 for empty or a palette number from 1 to {}", materials))),
+                    }
+                };
+                let in_grid = |cell: &[usize], at: &str|->Result<[usize; 3], SceneError>{
+                    if (0..3).any(|a| cell[a] >= dims[a]){
+                        return Err(SceneError::new(at, "expected a cell inside dims".to_string()))
+                    }
+                    Ok([cell[0], cell[1], cell[2]])
+                };
+                // any mix of the three, applied in this order
+                if let Some(data) = obj.get("data"){
+                    let here = format!("{}.data", location);
+                    let data = as_array(data, &here)?;
+                    if data.len() != dims[0]*dims[1]*dims[2]{
+                        return Err(SceneError::new(&here, "expected one value per cell, x fastest then y then z".to_string()))
+                    }
+                    for (n, v) in data.iter().enumerate(){
+                        let v = value(v, &format!("{}[{}]", here, n))?;
+                        if v != 0{
+                            grid.set([n%dims[0], n/dims[0]%dims[1], n/(dims[0]*dims[1])], v);
+                        }
+                    }
+                }
+                if let Some(boxes) = obj.get("boxes"){
+                    let here = format!("{}.boxes", location);
+                    for (i, def) in as_array(boxes, &here)?.iter().enumerate(){
+                        let at = format!("{}[{}]", here, i);
+                        let b = as_object(def, &at)?;
+                        check_keys(b, &at, &["min", "max", "value"])?;
+                        let (min_at, max_at) = (format!("{}.min", at), format!("{}.max", at));
+                        let min = in_grid(&as_indices(field(b, "min", &at)?, &min_at, 3)?, &min_at)?;
+                        let max = in_grid(&as_indices(field(b, "max", &at)?, &max_at, 3)?, &max_at)?;
+                        if (0..3).any(|a| min[a] > max[a]){
+                            return Err(SceneError::new(&at, "min must not be above max on any axis".to_string()))
+                        }
+                        grid.fill(min, max, value(field(b, "value", &at)?, &format!("{}.value", at))?);
+                    }
+                }
+                if let Some(cells) = obj.get("cells"){
+                    let here = format!("{}.cells", location);
+                    for (i, def) in as_array(cells, &here)?.iter().enumerate(){
+                        let at = format!("{}[{}]", here, i);
+                        let cell = as_indices(def, &at, 4)?;
+                        if cell[3] > materials{
+                            return Err(SceneError::new(&at, format!("expected [x, y, z, value] with a value from 0 to {}", materials)))
+                        }
+                        grid.set(in_grid(&cell, &at)?, cell[3] as u16);
+                    }
+                }
+                if grid.is_empty(){
+                    return Err(SceneError::new(location, "expected at least one filled cell".to_string()))
+                }
+                Arc::new(grid)
+            }
+            "instance"=>{
+                check_keys(obj, location, &["type", "geometry", "material", "transforms", "motion"])?;
+                let geometry = self.geometry(get_str(obj, "geometry", location)?, &format!("{}.geometry", location))?;
+                let transform = parse_transforms(obj, location)?;
+                let instance = if needs_material && obj.contains_key("material"){
+                    Instance::newm(geometry, transform, self.material_ref(obj, location, true)?)
+                }else{
+                    Instance::new(geometry, transform)
+                };
+                // the transforms are already part of the instance
+                out.push(apply_motion(Arc::new(instance), obj, location)?);
+                return Ok(())
+            }
+            "disk"=>{
+                check_keys(obj, location, &["type", "center", "normal", "radius", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                Arc::new(Disk::new(get_vec3(obj, "center", location)?, get_direction(obj, "normal", location)?,
+                    get_positive(obj, "radius", location)?, mat))
+            }
+            "cylinder" | "cone"=>{
+                check_keys(obj, location, &["type", "base", "axis", "radius", "capped", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let base = get_vec3(obj, "base", location)?;
+                let axis = get_direction(obj, "axis", location)?;
+                let radius = get_positive(obj, "radius", location)?;
+                let capped = opt_bool(obj, "capped", location, true)?;
+                if kind == "cylinder"{
+                    Arc::new(Cylinder::new(base, axis, radius, capped, mat))
+                }else{
+                    Arc::new(Cone::new(base, axis, radius, capped, mat))
+                }
+            }
+            "torus"=>{
+                check_keys(obj, location, &["type", "center", "axis", "major_radius", "minor_radius", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                Arc::new(Torus::new(get_vec3(obj, "center", location)?, get_direction(obj, "axis", location)?,
+                    get_positive(obj, "major_radius", location)?, get_positive(obj, "minor_radius", location)?, mat))
+            }
+            "plane"=>{
+                check_keys(obj, location, &["type", "point", "normal", "tile", "material", "transforms", "motion"])?;
+                let mat = self.material_ref(obj, location, needs_material)?;
+                let tile = opt_f64(obj, "tile", location, 1.0)?;
+                if tile <= 0.0{
+                    return Err(SceneError::new(&format!("{}.tile", location), "expected a positive number".to_string()))
+                }
+                Arc::new(InfinitePlane::newu(get_vec3(obj, "point", location)?, get_direction(obj, "normal", location)?, tile, mat))
+            }
+            _=>return Err(unknown_type(location, kind, &["sphere", "quad", "triangle", "cube", "ellipse", "annulus", "polygon", "disk", "cylinder", "cone", "torus", "plane",
+                "csg", "sdf", "heightfield", "curves", "metaballs", "voxels", "constant_medium", "obj", "instance"])),
+        };
+
+        let shape = apply_transforms(shape, obj, location)?;
+        out.push(apply_motion(shape, obj, location)?);
+        Ok(())
+    }
+}
+
+// transforms apply in the order they are listed and become a single node
+fn apply_transforms(shape: Arc<dyn Hittable>, obj: &Object, location: &str)->Result<Arc<dyn Hittable>, SceneError>{
+    if !obj.contains_key("transforms"){
+        return Ok(shape)
+    }
+    Ok(Arc::new(Transform::new(shape, parse_transforms(obj, location)?)))
+}
+
+// keyframes are applied after the object's own transforms
+fn apply_motion(shape: Arc<dyn Hittable>, obj: &Object, location: &str)->Result<Arc<dyn Hittable>, SceneError>{
+    let motion = match obj.get("motion"){
+        Some(m)=>m,
+        None=>return Ok(shape),
+    };
+    let field = format!("{}.motion", location);
+    let mut keys: Vec<Keyframe> = Vec::new();
+    for (i, k) in as_array(motion, &field)?.iter().enumerate(){
+        let location = format!("{}[{}]", field, i);
+        let k = as_object(k, &location)?;
+        check_keys(k, &location, &["time", "translate", "rotate", "scale"])?;
+        let time = get_f64(k, "time", &location)?;
+        if keys.last().is_some_and(|prev| time <= prev.time){
+            return Err(SceneError::new(&format!("{}.time", location), "keyframe times must increase".to_string()))
+        }
+        let rotate = match k.get("rotate"){
+            Some(r)=>{
+                let here = format!("{}.rotate", location);
+                let r = as_object(r, &here)?;
+                check_keys(r, &here, &["axis", "angle"])?;
+                let axis = get_vec3(r, "axis", &here)?;
+                if axis.length_squared() == 0.0{
+                    return Err(SceneError::new(&here, "axis must not be zero".to_string()))
+                }
+                Quat::from_axis_angle(axis, get_f64(r, "angle", &here)?)
+            }
+            None=>Quat::identity(),
+        };
+        let scale = match k.get("scale"){
+            Some(v)=>match v.as_f64(){
+                Some(s)=>Vec3::new(s, s, s),
+                None=>as_vec3(v, &format!("{}.scale", location))?,
+            },
+            None=>Vec3::new(1.0, 1.0, 1.0),
+        };
+        if scale.x()*scale.y()*scale.z() == 0.0{
+            return Err(SceneError::new(&format!("{}.scale", location), "scale must not be zero".to_string()))
+        }
+        keys.push(Keyframe::new(time, opt_vec3(k, "translate", &location, Vec3::enew())?, rotate, scale));
+    }
+    if keys.is_empty(){
+        return Err(SceneError::new(&field, "expected at least one keyframe".to_string()))
+    }
+    Ok(Arc::new(AnimatedTransform::new(shape, keys)))
+}
+
+const TRANSFORMS: [&str; 8] = ["translate", "rotate_x", "rotate_y", "rotate_z", "rotate", "scale", "look_at", "matrix"];
+
+fn parse_transforms(obj: &Object, location: &str)->Result<Mat4, SceneError>{
+    let mut matrix = Mat4::identity();
+    let transforms = match obj.get("transforms"){
+        Some(t)=>t,
+        None=>return Ok(matrix),
+    };
+    let field = format!("{}.transforms", location);
+    for (i, t) in as_array(transforms, &field)?.iter().enumerate(){
+        let location = format!("{}[{}]", field, i);
+        let t = as_object(t, &location)?;
+        check_keys(t, &location, &TRANSFORMS)?;
+        let (key, v) = match t.iter().next(){
+            Some(kv) if t.len() == 1=>kv,
+            _=>return Err(SceneError::new(&location, format!("expected exactly one of {}", TRANSFORMS.join(", ")))),
+        };
+        let here = format!("{}.{}", location, key);
+        let step = match key.as_str(){
+            "translate"=>Mat4::translate(as_vec3(v, &here)?),
+            "rotate_x"=>Mat4::rotate_x(as_f64(v, &here)?),
+            "rotate_y"=>Mat4::rotate_y(as_f64(v, &here)?),
+            "rotate_z"=>Mat4::rotate_z(as_f64(v, &here)?),
+            "rotate"=>{
+                let r = as_object(v, &here)?;
+                check_keys(r, &here, &["axis", "angle"])?;
+                let axis = get_vec3(r, "axis", &here)?;
+                if axis.length_squared() == 0.0{
+                    return Err(SceneError::new(&here, "axis must not be zero".to_string()))
+                }
+                Mat4::rotate(axis, get_f64(r, "angle", &here)?)
+            }
+            // a single number scales uniformly
+            "scale"=>{
+                let s = match v.as_f64(){
+                    Some(s)=>Vec3::new(s, s, s),
+                    None=>as_vec3(v, &here)?,
+                };
+                Mat4::scale(s)
+            }
+            "look_at"=>{
+                let l = as_object(v, &here)?;
+                check_keys(l, &here, &["from", "to", "up"])?;
+                let from = opt_vec3(l, "from", &here, Vec3::enew())?;
+                let to = get_vec3(l, "to", &here)?;
+                let up = opt_vec3(l, "up", &here, Vec3::new(0.0,1.0,0.0))?;
+                Mat4::look_at(from, to, up)
+                    .ok_or_else(|| SceneError::new(&here, "'to' must differ from 'from' and not lie along 'up'".to_string()))?
+            }
+            // rows, the last one may be left out
+            _=>{
+                let rows = as_array(v, &here)?;
+                if rows.len() != 3 && rows.len() != 4{
+                    return Err(SceneError::new(&here, "expected 3 or 4 rows of 4 numbers".to_string()))
+                }
+                let mut cells = [[0.0; 4]; 4];
+                cells[3] = [0.0, 0.0, 0.0, 1.0];
+                for (r, row) in rows.iter().enumerate(){
+                    let row_loc = format!("{}[{}]", here, r);
+                    let row = as_array(row, &row_loc)?;
+                    if row.len() != 4{
+                        return Err(SceneError::new(&row_loc, "expected 4 numbers".to_string()))
+                    }
+                    for (c, x) in row.iter().enumerate(){
+                        cells[r][c] = as_f64(x, &format!("{}[{}]", row_loc, c))?;
+                    }
+                }
+                let m = Mat4::new(cells);
+                if !m.is_affine(){
+                    return Err(SceneError::new(&here, "the last row must be [0, 0, 0, 1]".to_string()))
+                }
+                m
+            }
+        };
+        matrix = step*matrix;
+    }
+    if matrix.inverse().is_none(){
+        return Err(SceneError::new(&field, "the transforms collapse the object, a scale or matrix is singular".to_string()))
+    }
+    Ok(matrix)
+}
+
+fn parse_camera(def: &Value, location: &str)->Result<Camera, SceneError>{
+    let obj = as_object(def, location)?;
+    check_keys(obj, location, &["aspect_ratio", "image_width", "samples_per_pixel", "max_depth", "vfov",
+        "lookfrom", "lookat", "vup", "defocus_angle", "focus_dist", "background", "exposure", "tone_map", "white_point", "seed", "sampler", "adaptive", "shutter"])?;
+
+    let aspect_ratio = opt_f64(obj, "aspect_ratio", location, 1.0)?;
+    if !aspect_ratio.is_finite() || aspect_ratio <= 0.0{
+        return Err(SceneError::new(&format!("{}.aspect_ratio", location), "expected a positive number".to_string()))
+    }
+    let mut cam = Camera::new(
+        aspect_ratio,
+        opt_u32(obj, "image_width", location, 100)?,
+        opt_u32(obj, "samples_per_pixel", location, 10)?,
+        opt_u32(obj, "max_depth", location, 10)?,
+        opt_f64(obj, "vfov", location, 90.0)?,
+        opt_vec3(obj, "lookfrom", location, Vec3::enew())?,
+        opt_vec3(obj, "lookat", location, Vec3::new(0.0,0.0,-1.0))?,
+        opt_vec3(obj, "vup", location, Vec3::new(0.0,1.0,0.0))?,
+        opt_f64(obj, "defocus_angle", location, 0.0)?,
+        opt_f64(obj, "focus_dist", location, 10.0)?,
+        opt_vec3(obj, "background", location, Vec3::enew())?,
+    );
+
+    let white = opt_f64(obj, "white_point", location, 1.0)?;
+    if white <= 0.0{
+        return Err(SceneError::new(&format!("{}.white_point", location), "expected a positive number".to_string()))
+    }
+    let tone_map = match obj.get("tone_map"){
+        Some(v)=>{
+            let name = v.as_str()
+                .ok_or_else(|| SceneError::new(&format!("{}.tone_map", location), "expected a string".to_string()))?;
+            ToneMap::from_name(name, white).ok_or_else(|| SceneError::new(&format!("{}.tone_map", location),
+                format!("unknown tone map '{}' (expected one of: {})", name, ToneMap::NAMES)))?
+        }
+        None=>ToneMap::Clamp,
+    };
+    if obj.contains_key("white_point") && !matches!(tone_map, ToneMap::ReinhardExtended{..}){
+        return Err(SceneError::new(location, "white_point only applies to the reinhard_extended tone map".to_string()))
+    }
+    cam.set_display(Display{
+        exposure: opt_f64(obj, "exposure", location, 0.0)?,
+        tone_map,
+    });
+    cam.set_seed(opt_u64(obj, "seed", location, 0)?);
+    if let Some(v) = obj.get("sampler"){
+        let name = v.as_str()
+            .ok_or_else(|| SceneError::new(&format!("{}.sampler", location), "expected a string".to_string()))?;
+        cam.set_sampler(SamplerKind::from_name(name).ok_or_else(|| SceneError::new(&format!("{}.sampler", location),
+            format!("unknown sampler '{}' (expected one of: {})", name, SamplerKind::NAMES)))?);
+    }
+    if let Some(v) = obj.get("adaptive"){
+        let location = &format!("{}.adaptive", location);
+        let a = as_object(v, location)?;
+        check_keys(a, location, &["threshold", "min_spp", "max_spp"])?;
+        let max_spp = opt_u32(a, "max_spp", location, cam.samples_per_pixel())?;
+        let adaptive = AdaptiveSampling{
+            min_spp: opt_u32(a, "min_spp", location, AdaptiveSampling::DEFAULT_MIN_SPP.min(max_spp))?,
+            max_spp,
+            threshold: get_f64(a, "threshold", location)?,
+        };
+        adaptive.check().map_err(|e| SceneError::new(location, e))?;
+        cam.set_adaptive(Some(adaptive));
+    }
+    if let Some(v) = obj.get("shutter"){
+        cam.set_shutter(parse_shutter(v, &format!("{}.shutter", location))?);
+    }
+
+    Ok(cam)
+}
+
+// either "close" or "angle" in degrees of a one unit frame, and "ramp" only
+// with the trapezoid curve
+fn parse_shutter(def: &Value, location: &str)->Result<Shutter, SceneError>{
+    let obj = as_object(def, location)?;
+    check_keys(obj, location, &["open", "close", "angle", "curve", "ramp", "rolling"])?;
+    let open = opt_f64(obj, "open", location, 0.0)?;
+    let mut shutter = match obj.get("angle"){
+        Some(_)=>{
+            if obj.contains_key("close"){
+                return Err(SceneError::new(location, "give either close or angle, not both".to_string()))
+            }
+            Shutter::from_angle(get_f64(obj, "angle", location)?, open)
+        }
+        None=>Shutter{
+            open,
+            close: opt_f64(obj, "close", location, open+1.0)?,
+            ..Shutter::default()
+        },
+    };
+    let ramp = opt_f64(obj, "ramp", location, ShutterCurve::DEFAULT_RAMP)?;
+    if let Some(v) = obj.get("curve"){
+        let name = v.as_str()
+            .ok_or_else(|| SceneError::new(&format!("{}.curve", location), "expected a string".to_string()))?;
+        shutter.curve = ShutterCurve::from_name(name, ramp).ok_or_else(|| SceneError::new(&format!("{}.curve", location),
+            format!("unknown shutter curve '{}' (expected one of: {})", name, ShutterCurve::NAMES)))?;
+    }
+    if obj.contains_key("ramp") && !matches!(shutter.curve, ShutterCurve::Trapezoid{..}){
+        return Err(SceneError::new(location, "ramp only applies to the trapezoid curve".to_string()))
+    }
+    shutter.rolling = opt_f64(obj, "rolling", location, 0.0)?;
+    shutter.check().map_err(|e| SceneError::new(location, e))?;
+    Ok(shutter)
+}
+
+// an expression node is an object with a type. combinations take a list of
+// "shapes", the other operators wrap a single "shape"
+fn parse_sdf(def: &Value, location: &str)->Result<Sdf, SceneError>{
+    let obj = as_object(def, location)?;
+    let kind = get_str(obj, "type", location)?;
+    let inner = ||->Result<Sdf, SceneError>{
+        parse_sdf(field(obj, "shape", location)?, &format!("{}.shape", location))
+    };
+    let sdf = match kind{
+        "sphere"=>{
+            check_keys(obj, location, &["type", "radius"])?;
+            Sdf::sphere(get_positive(obj, "radius", location)?)
+        }
+        "box"=>{
+            check_keys(obj, location, &["type", "size", "radius"])?;
+            let size = get_vec3(obj, "size", location)?;
+            let radius = opt_f64(obj, "radius", location, 0.0)?;
+            if (0..3).any(|i| size[i] <= 0.0) || radius < 0.0 || (0..3).any(|i| 2.0*radius > size[i]){
+                return Err(SceneError::new(location, "expected a positive size with radius at most half of it".to_string()))
+            }
+            Sdf::round_box(size, radius)
+        }
+        "torus"=>{
+            check_keys(obj, location, &["type", "major_radius", "minor_radius"])?;
+            Sdf::torus(get_positive(obj, "major_radius", location)?, get_positive(obj, "minor_radius", location)?)
+        }
+        "cylinder"=>{
+            check_keys(obj, location, &["type", "radius", "height"])?;
+            Sdf::cylinder(get_positive(obj, "radius", location)?, get_positive(obj, "height", location)?)
+        }
+        "mandelbulb"=>{
+            check_keys(obj, location, &["type", "power", "iterations"])?;
+            Sdf::mandelbulb(opt_f64(obj, "power", location, 8.0)?, opt_u32(obj, "iterations", location, 12)?)
+        }
+        "union" | "intersection" | "difference"=>{
+            check_keys(obj, location, &["type", "shapes", "smooth"])?;
+            let k = opt_f64(obj, "smooth", location, 0.0)?;
+            if k < 0.0{
+                return Err(SceneError::new(&format!("{}.smooth", location), "expected a non-negative number".to_string()))
+            }
+            let here = format!("{}.shapes", location);
+            let defs = as_array(field(obj, "shapes", location)?, &here)?;
+            if defs.len() < 2{
+                return Err(SceneError::new(&here, "expected at least two shapes".to_string()))
+            }
+            let mut shapes = defs.iter().enumerate().map(|(i, d)| parse_sdf(d, &format!("{}[{}]", here, i)));
+            let mut sdf = shapes.next().unwrap()?;
+            // a difference takes every later shape away from the first
+            for shape in shapes{
+                let shape = shape?;
+                sdf = match (kind, k > 0.0){
+                    ("union", false)=>sdf.union(shape),
+                    ("union", true)=>sdf.smooth_union(shape, k),
+                    ("intersection", false)=>sdf.intersection(shape),
+                    ("intersection", true)=>sdf.smooth_intersection(shape, k),
+                    (_, false)=>sdf.difference(shape),
+                    (_, true)=>sdf.smooth_difference(shape, k),
+                };
+            }
+            sdf
+        }
+        "translate"=>{
+            check_keys(obj, location, &["type", "shape", "offset"])?;
+            inner()?.translate(get_vec3(obj, "offset", location)?)
+        }
+        "rotate"=>{
+            check_keys(obj, location, &["type", "shape", "axis", "angle"])?;
+            inner()?.rotate(get_direction(obj, "axis", location)?, get_f64(obj, "angle", location)?)
+        }
+        "scale"=>{
+            check_keys(obj, location, &["type", "shape", "factor"])?;
+            inner()?.scale(get_positive(obj, "factor", location)?)
+        }
+        "round"=>{
+            check_keys(obj, location, &["type", "shape", "radius"])?;
+            inner()?.round(get_positive(obj, "radius", location)?)
+        }
+        "twist"=>{
+            check_keys(obj, location, &["type", "shape", "rate"])?;
+            inner()?.twist(get_f64(obj, "rate", location)?)
+        }
+        "repeat"=>{
+            check_keys(obj, location, &["type", "shape", "period"])?;
+            let period = get_vec3(obj, "period", location)?;
+            if (0..3).any(|i| period[i] < 0.0){
+                return Err(SceneError::new(&format!("{}.period", location), "expected non-negative spacings".to_string()))
+            }
+            inner()?.repeat(period)
+        }
+        "displace"=>{
+            check_keys(obj, location, &["type", "shape", "amount", "frequency", "octaves", "seed"])?;
+            let octaves = opt_u32(obj, "octaves", location, 1)?;
+            if !(1..=16).contains(&octaves){
+                return Err(SceneError::new(&format!("{}.octaves", location), "expected 1 to 16".to_string()))
+            }
+            inner()?.displace(get_f64(obj, "amount", location)?, get_positive(obj, "frequency", location)?,
+                octaves as i32, opt_u64(obj, "seed", location, 0)?)
+        }
+        _=>return Err(unknown_type(location, kind, &["sphere", "box", "torus", "cylinder", "mandelbulb", "union", "intersection",
+            "difference", "translate", "rotate", "scale", "round", "twist", "repeat", "displace"])),
+    };
+    Ok(sdf)
+}
+
+// "bvh": true, false, or an object with build settings which implies true
+fn parse_bvh(def: &Value, location: &str)->Result<(bool, BvhOptions), SceneError>{
+    if let Some(enabled) = def.as_bool(){
+        return Ok((enabled, BvhOptions::default()))
+    }
+    let obj = def.as_object()
+        .ok_or_else(|| SceneError::new(location, "expected true, false or an object".to_string()))?;
+    check_keys(obj, location, &["split", "max_leaf_size", "traversal_cost", "intersection_cost", "bins"])?;
+
+    let defaults = BvhOptions::default();
+    let split = match obj.get("split"){
+        Some(v)=>{
+            let name = v.as_str()
+                .ok_or_else(|| SceneError::new(&format!("{}.split", location), "expected a string".to_string()))?;
+            SplitMethod::from_name(name).ok_or_else(|| SceneError::new(&format!("{}.split", location),
+                format!("unknown split method '{}' (expected one of: {})", name, SplitMethod::NAMES)))?
+        }
+        None=>defaults.split,
+    };
+    let options = BvhOptions{
+        split,
+        max_leaf_size: opt_u32(obj, "max_leaf_size", location, defaults.max_leaf_size as u32)? as usize,
+        traversal_cost: opt_f64(obj, "traversal_cost", location, defaults.traversal_cost)?,
+        intersection_cost: opt_f64(obj, "intersection_cost", location, defaults.intersection_cost)?,
+        bins: opt_u32(obj, "bins", location, defaults.bins as u32)? as usize,
+    };
+    options.check().map_err(|e| SceneError::new(location, e))?;
+    Ok((true, options))
+}
+
+fn unknown_type(location: &str, kind: &str, expected: &[&str])->SceneError{
+    SceneError::new(&format!("{}.type", location), format!("unknown type '{}' (expected one of: {})", kind, expected.join(", ")))
+}
+
+fn check_keys(obj: &Object, location: &str, allowed: &[&str])->Result<(), SceneError>{
+    for key in obj.keys(){
+        if !allowed.contains(&key.as_str()){
+            return Err(SceneError::new(location, format!("unknown key '{}' (expected one of: {})", key, allowed.join(", "))))
+        }
+    }
+    Ok(())
+}
+
+fn as_object<'a>(v: &'a Value, location: &str)->Result<&'a Object, SceneError>{
+    v.as_object().ok_or_else(|| SceneError::new(location, "expected an object".to_string()))
+}
+
+fn as_array<'a>(v: &'a Value, location: &str)->Result<&'a Vec<Value>, SceneError>{
+    v.as_array().ok_or_else(|| SceneError::new(location, "expected an array".to_string()))
+}
+
+fn as_vec3(v: &Value, location: &str)->Result<Vec3, SceneError>{
+    let err = || SceneError::new(location, "expected an array of 3 numbers".to_string());
+    let arr = v.as_array().ok_or_else(err)?;
+    if arr.len() != 3{
+        return Err(err())
+    }
+    let mut ret = Vec3::enew();
+    for (i, c) in arr.iter().enumerate(){
+        ret[i] = c.as_f64().ok_or_else(err)?;
+    }
+    Ok(ret)
+}
+
+fn field<'a>(obj: &'a Object, key: &str, location: &str)->Result<&'a Value, SceneError>{
+    obj.get(key).ok_or_else(|| SceneError::new(location, format!("missing key '{}'", key)))
+}
+
+fn get_str<'a>(obj: &'a Object, key: &str, location: &str)->Result<&'a str, SceneError>{
+    field(obj, key, location)?.as_str()
+        .ok_or_else(|| SceneError::new(&format!("{}.{}", location, key), "expected a string".to_string()))
+}
+
+fn as_f64(v: &Value, location: &str)->Result<f64, SceneError>{
+    v.as_f64().ok_or_else(|| SceneError::new(location, "expected a number".to_string()))
+}
+
+fn get_f64(obj: &Object, key: &str, location: &str)->Result<f64, SceneError>{
+    field(obj, key, location)?.as_f64()
+        .ok_or_else(|| SceneError::new(&format!("{}.{}", location, key), "expected a number".to_string()))
+}
+
+fn get_positive(obj: &Object, key: &str, location: &str)->Result<f64, SceneError>{
+    let v = get_f64(obj, key, location)?;
+    if v <= 0.0{
+        return Err(SceneError::new(&format!("{}.{}", location, key), "expected a positive number".to_string()))
+    }
+    Ok(v)
+}
+
+// any length but zero
+fn get_direction(obj: &Object, key: &str, location: &str)->Result<Vec3, SceneError>{
+    let v = get_vec3(obj, key, location)?;
+    if v.length_squared() == 0.0{
+        return Err(SceneError::new(&format!("{}.{}", location, key), "expected a non-zero vector".to_string()))
+    }
+    Ok(v)
+}
+
+fn get_vec3(obj: &Object, key: &str, location: &str)->Result<Vec3, SceneError>{
+    as_vec3(field(obj, key, location)?, &format!("{}.{}", location, key))
+}
+
+// exactly n non-negative integers
+fn as_indices(v: &Value, location: &str, n: usize)->Result<Vec<usize>, SceneError>{
+    let err = || SceneError::new(location, format!("expected an array of {} non-negative integers", n));
+    let arr = v.as_array().filter(|arr| arr.len() == n).ok_or_else(err)?;
+    arr.iter().map(|x| x.as_u64().and_then(|x| usize::try_from(x).ok()).ok_or_else(err)).collect()
+}
+
+fn opt_f64(obj: &Object, key: &str, location: &str, default: f64)->Result<f64, SceneError>{
+    if obj.contains_key(key){
+        return get_f64(obj, key, location)
+    }
+    Ok(default)
+}
+
+fn opt_u32(obj: &Object, key: &str, location: &str, default: u32)->Result<u32, SceneError>{
+    match obj.get(key){
+        Some(v)=>v.as_u64().and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(|| SceneError::new(&format!("{}.{}", location, key), "expected a non-negative integer".to_string())),
+        None=>Ok(default),
+    }
+}
+
+fn opt_u64(obj: &Object, key: &str, location: &str, default: u64)->Result<u64, SceneError>{
+    match obj.get(key){
+        Some(v)=>v.as_u64()
+            .ok_or_else(|| SceneError::new(&format!("{}.{}", location, key), "expected a non-negative integer".to_string())),
+        None=>Ok(default),
+    }
+}
+
+fn opt_bool(obj: &Object, key: &str, location: &str, default: bool)->Result<bool, SceneError>{
+    match obj.get(key){
+        Some(v)=>v.as_bool()
+            .ok_or_else(|| SceneError::new(&format!("{}.{}", location, key), "expected true or false".to_string())),
+        None=>Ok(default),
+    }
+}
+
+fn opt_str<'a>(obj: &'a Object, key: &str, location: &str, default: &'a str)->Result<&'a str, SceneError>{
+    if obj.contains_key(key){
+        return get_str(obj, key, location)
+    }
+    Ok(default)
+}
+
+fn opt_vec3(obj: &Object, key: &str, location: &str, default: Vec3)->Result<Vec3, SceneError>{
+    if obj.contains_key(key){
+        return get_vec3(obj, key, location)
+    }
+    Ok(default)
+}
@@ -0,0 +1,315 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::sphere::Sphere as Sphere;
+use crate::vec3::Vec3 as Vec3;
+use crate::matrix::Mat4 as Mat4;
+use crate::perlin::Perlin as Perlin;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+
+use std::sync::Arc;
+
+// a signed distance expression, negative inside. children are evaluated at
+// points mapped into their own space, so the operators nest freely
+pub enum Sdf{
+    Sphere{ radius: f64 },
+    // half the size along each axis, with the edges rounded off by radius
+    Box{ half: Vec3, radius: f64 },
+    // around the y axis
+    Torus{ major: f64, minor: f64 },
+    // along the y axis, centered on the origin
+    Cylinder{ radius: f64, half_height: f64 },
+    // the mandelbulb fractal, usually of power 8. its distance is an
+    // estimate rather than exact
+    Mandelbulb{ power: f64, iterations: u32 },
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Difference(Box<Sdf>, Box<Sdf>),
+    // blends the surfaces over a distance of about k
+    SmoothUnion{ a: Box<Sdf>, b: Box<Sdf>, k: f64 },
+    SmoothIntersection{ a: Box<Sdf>, b: Box<Sdf>, k: f64 },
+    SmoothDifference{ a: Box<Sdf>, b: Box<Sdf>, k: f64 },
+    Translate{ inner: Box<Sdf>, offset: Vec3 },
+    // the inverse rotation, which is what points need
+    Rotate{ inner: Box<Sdf>, inverse: Mat4 },
+    Scale{ inner: Box<Sdf>, factor: f64 },
+    Round{ inner: Box<Sdf>, radius: f64 },
+    // turns by rate radians per unit along y
+    Twist{ inner: Box<Sdf>, rate: f64 },
+    // copies the inner shape in every cell of a grid with this spacing, a
+    // zero spacing leaves that axis alone. the shape should fit in one cell
+    Repeat{ inner: Box<Sdf>, period: Vec3 },
+    // pushes the surface out by amount times turbulence at frequency
+    Displace{ inner: Box<Sdf>, noise: Perlin, amount: f64, frequency: f64, octaves: i32 },
+}
+
+// a bound on the slope of Perlin::noise, per octave
+const NOISE_SLOPE: f64 = 2.0;
+
+impl Sdf{
+    pub fn sphere(radius: f64)->Self{
+        Sdf::Sphere{ radius }
+    }
+    pub fn round_box(size: Vec3, radius: f64)->Self{
+        Sdf::Box{ half: 0.5*size, radius }
+    }
+    pub fn torus(major: f64, minor: f64)->Self{
+        Sdf::Torus{ major, minor }
+    }
+    pub fn cylinder(radius: f64, height: f64)->Self{
+        Sdf::Cylinder{ radius, half_height: 0.5*height }
+    }
+    pub fn mandelbulb(power: f64, iterations: u32)->Self{
+        Sdf::Mandelbulb{ power, iterations }
+    }
+    pub fn union(self, other: Sdf)->Self{
+        Sdf::Union(Box::new(self), Box::new(other))
+    }
+    pub fn intersection(self, other: Sdf)->Self{
+        Sdf::Intersection(Box::new(self), Box::new(other))
+    }
+    pub fn difference(self, other: Sdf)->Self{
+        Sdf::Difference(Box::new(self), Box::new(other))
+    }
+    pub fn smooth_union(self, other: Sdf, k: f64)->Self{
+        Sdf::SmoothUnion{ a: Box::new(self), b: Box::new(other), k }
+    }
+    pub fn smooth_intersection(self, other: Sdf, k: f64)->Self{
+        Sdf::SmoothIntersection{ a: Box::new(self), b: Box::new(other), k }
+    }
+    pub fn smooth_difference(self, other: Sdf, k: f64)->Self{
+        Sdf::SmoothDifference{ a: Box::new(self), b: Box::new(other), k }
+    }
+    pub fn translate(self, offset: Vec3)->Self{
+        Sdf::Translate{ inner: Box::new(self), offset }
+    }
+    // degrees, the same sense as Mat4::rotate
+    pub fn rotate(self, axis: Vec3, angle: f64)->Self{
+        Sdf::Rotate{ inner: Box::new(self), inverse: Mat4::rotate(axis, -angle) }
+    }
+    pub fn scale(self, factor: f64)->Self{
+        Sdf::Scale{ inner: Box::new(self), factor }
+    }
+    pub fn round(self, radius: f64)->Self{
+        Sdf::Round{ inner: Box::new(self), radius }
+    }
+    // degrees per unit along y
+    pub fn twist(self, rate: f64)->Self{
+        Sdf::Twist{ inner: Box::new(self), rate: rate.to_radians() }
+    }
+    pub fn repeat(self, period: Vec3)->Self{
+        Sdf::Repeat{ inner: Box::new(self), period }
+    }
+    pub fn displace(self, amount: f64, frequency: f64, octaves: i32, seed: u64)->Self{
+        Sdf::Displace{ inner: Box::new(self), noise: Perlin::newseed(seed), amount, frequency, octaves }
+    }
+
+    pub fn distance(&self, p: Vec3)->f64{
+        match self{
+            Sdf::Sphere{radius}=>p.length()-radius,
+            Sdf::Box{half, radius}=>{
+                let mut q = Vec3::enew();
+                for i in 0..3{
+                    q[i] = p[i].abs()-(half[i]-radius);
+                }
+                let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+                outside+q.x().max(q.y()).max(q.z()).min(0.0)-radius
+            }
+            Sdf::Torus{major, minor}=>{
+                let ring = (p.x()*p.x()+p.z()*p.z()).sqrt()-major;
+                (ring*ring+p.y()*p.y()).sqrt()-minor
+            }
+            Sdf::Cylinder{radius, half_height}=>{
+                let dx = (p.x()*p.x()+p.z()*p.z()).sqrt()-radius;
+                let dy = p.y().abs()-half_height;
+                dx.max(dy).min(0.0)+(dx.max(0.0)*dx.max(0.0)+dy.max(0.0)*dy.max(0.0)).sqrt()
+            }
+            Sdf::Mandelbulb{power, iterations}=>Self::mandelbulb_distance(p, *power, *iterations),
+            Sdf::Union(a, b)=>a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b)=>a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b)=>a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion{a, b, k}=>smooth_min(a.distance(p), b.distance(p), *k),
+            Sdf::SmoothIntersection{a, b, k}=>-smooth_min(-a.distance(p), -b.distance(p), *k),
+            Sdf::SmoothDifference{a, b, k}=>-smooth_min(-a.distance(p), b.distance(p), *k),
+            Sdf::Translate{inner, offset}=>inner.distance(p-*offset),
+            Sdf::Rotate{inner, inverse}=>inner.distance(inverse.vector(p)),
+            Sdf::Scale{inner, factor}=>factor*inner.distance(p/ *factor),
+            Sdf::Round{inner, radius}=>inner.distance(p)-radius,
+            Sdf::Twist{inner, rate}=>{
+                let (sin, cos) = (rate*p.y()).sin_cos();
+                inner.distance(Vec3::new(cos*p.x()-sin*p.z(), p.y(), sin*p.x()+cos*p.z()))
+            }
+            Sdf::Repeat{inner, period}=>{
+                let mut q = p;
+                for i in 0..3{
+                    if period[i] > 0.0{
+                        q[i] = p[i]-period[i]*(p[i]/period[i]).round();
+                    }
+                }
+                inner.distance(q)
+            }
+            Sdf::Displace{inner, noise, amount, frequency, octaves}=>{
+                inner.distance(p)-amount*noise.turb(*frequency*p, *octaves)
+            }
+        }
+    }
+    // how much faster than the true distance the expression can change,
+    // within radius of the origin. sphere tracing divides its steps by this
+    pub fn lipschitz(&self, radius: f64)->f64{
+        match self{
+            Sdf::Sphere{..} | Sdf::Box{..} | Sdf::Torus{..} | Sdf::Cylinder{..} | Sdf::Mandelbulb{..}=>1.0,
+            Sdf::Union(a, b) | Sdf::Intersection(a, b) | Sdf::Difference(a, b)=>a.lipschitz(radius).max(b.lipschitz(radius)),
+            Sdf::SmoothUnion{a, b, ..} | Sdf::SmoothIntersection{a, b, ..} | Sdf::SmoothDifference{a, b, ..}=>{
+                a.lipschitz(radius).max(b.lipschitz(radius))
+            }
+            Sdf::Translate{inner, offset}=>inner.lipschitz(radius+offset.length()),
+            Sdf::Rotate{inner, ..} | Sdf::Round{inner, ..}=>inner.lipschitz(radius),
+            Sdf::Scale{inner, factor}=>inner.lipschitz(radius/factor),
+            // a point at distance r from the axis is swept sideways by rate*r
+            // for every unit it moves along y
+            Sdf::Twist{inner, rate}=>inner.lipschitz(radius)*(1.0+(rate*radius).powi(2)).sqrt(),
+            // the inner shape only ever sees points within half a cell
+            Sdf::Repeat{inner, period}=>{
+                let cell = (0..3).map(|i| if period[i] > 0.0 { (0.5*period[i]).powi(2) } else { radius*radius }).sum::<f64>().sqrt();
+                inner.lipschitz(radius.min(cell))
+            }
+            Sdf::Displace{inner, amount, frequency, octaves, ..}=>{
+                inner.lipschitz(radius)+amount.abs()*frequency*NOISE_SLOPE*(*octaves).max(1) as f64
+            }
+        }
+    }
+    fn mandelbulb_distance(p: Vec3, power: f64, iterations: u32)->f64{
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut r = z.length();
+        for _ in 0..iterations{
+            if r > 2.0{
+                break
+            }
+            let theta = (z.z()/r).clamp(-1.0, 1.0).acos()*power;
+            let phi = z.y().atan2(z.x())*power;
+            dr = r.powf(power-1.0)*power*dr+1.0;
+            let zr = r.powf(power);
+            z = zr*Vec3::new(theta.sin()*phi.cos(), theta.sin()*phi.sin(), theta.cos())+p;
+            r = z.length();
+        }
+        if r == 0.0{
+            return 0.0
+        }
+        0.5*r.ln()*r/dr
+    }
+}
+
+// polynomial smooth minimum, the same as min once a and b are k apart
+fn smooth_min(a: f64, b: f64, k: f64)->f64{
+    if k <= 0.0{
+        return a.min(b)
+    }
+    let h = (0.5+0.5*(b-a)/k).clamp(0.0, 1.0);
+    b+(a-b)*h-k*h*(1.0-h)
+}
+
+// an sdf drawn by sphere tracing inside a box, which has to hold the whole
+// surface
+pub struct SdfShape{
+    sdf: Sdf,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+    // steps are the distance divided by the expression's lipschitz bound
+    step_scale: f64,
+    epsilon: f64,
+    max_steps: u32,
+}
+
+impl SdfShape{
+    pub const DEFAULT_MAX_STEPS: u32 = 512;
+
+    pub fn new(sdf: Sdf, bound: AABB, mat: Arc<dyn Material>)->Self{
+        let corners = [bound.x(), bound.y(), bound.z()].map(|ax| ax.min.abs().max(ax.max.abs()));
+        let radius = Vec3::new(corners[0], corners[1], corners[2]).length();
+        let diagonal = Vec3::new(bound.x().size(), bound.y().size(), bound.z().size()).length();
+        Self{
+            step_scale: 1.0/sdf.lipschitz(radius).max(1.0),
+            sdf,
+            mat,
+            bbox: bound,
+            epsilon: 1e-5*diagonal,
+            max_steps: Self::DEFAULT_MAX_STEPS,
+        }
+    }
+    pub fn set_max_steps(&mut self, max_steps: u32){
+        self.max_steps = max_steps
+    }
+    pub fn set_epsilon(&mut self, epsilon: f64){
+        self.epsilon = epsilon
+    }
+    pub fn sdf(&self)->&Sdf{
+        &self.sdf
+    }
+    // central differences on a tetrahedron, four evaluations instead of six
+    fn normal(&self, p: Vec3)->Vec3{
+        let h = self.epsilon;
+        let k = [Vec3::new(1.0,-1.0,-1.0), Vec3::new(-1.0,-1.0,1.0), Vec3::new(-1.0,1.0,-1.0), Vec3::new(1.0,1.0,1.0)];
+        let mut n = Vec3::enew();
+        for k in k{
+            n += k*self.sdf.distance(p+h*k);
+        }
+        if n.near_zero(){
+            return Vec3::new(0.0,1.0,0.0)
+        }
+        n.unit_vector()
+    }
+}
+
+impl Hittable for SdfShape{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let mut span = ray_t;
+        if !self.bbox.hit(*r, &mut span){
+            return false
+        }
+        let len = r.direction().length();
+        let d = r.direction()/len;
+        // march in units of distance along the ray
+        let (t_end, mut t) = (span.max().min(f64::MAX)*len, span.min()*len);
+        let at = |t: f64| r.origin()+t*d;
+
+        // a ray leaving the surface starts within epsilon of it, step off
+        // before deciding which side it is on
+        let mut dist = self.sdf.distance(at(t));
+        let mut escape = 0;
+        while dist.abs() < self.epsilon && escape < 8{
+            t += 2.0*self.epsilon;
+            dist = self.sdf.distance(at(t));
+            escape += 1;
+        }
+        let side = if dist < 0.0 { -1.0 } else { 1.0 };
+
+        for _ in 0..self.max_steps{
+            if t > t_end{
+                return false
+            }
+            let step = side*dist;
+            if step < self.epsilon{
+                let t_hit = t/len;
+                if !ray_t.surrounds(t_hit){
+                    return false
+                }
+                let p = at(t);
+                rec.setT(t_hit);
+                rec.setP(p);
+                rec.mat = self.mat.clone();
+                rec.set_face_normal(r, &self.normal(p));
+                Sphere::get_sphere_uv((p-self.bbox.centroid()).unit_vector(), &mut rec.u, &mut rec.v);
+                return true
+            }
+            t += step*self.step_scale;
+            dist = self.sdf.distance(at(t));
+        }
+        false
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+}
@@ -1,12 +1,14 @@
 use crate::vec3::Vec3 as Vec3;
 use crate::hittable::Hit_record as Hit_record;
 use crate::hittable::Hittable as Hittable;
+use crate::hittable::Span as Span;
 use crate::ray::Ray as Ray;
 use crate::interval::Interval as Interval;
 use crate::material::Material as Material;
 use crate::aabb::AABB as AABB;
 use crate::onb::ONB as ONB;
 
+use crate::sampler;
 use std::sync::Arc;
 
 pub struct Sphere{
@@ -48,9 +50,16 @@ impl Sphere{
     pub fn bounding_box(&self)->AABB{
         self.bbox
     }
+    fn set_record(&self, r: &Ray, t: f64, current_center: Vec3, rec: &mut Hit_record){
+        rec.setT(t);
+        rec.setP(r.at(rec.t()));
+        let outward_normal: Vec3 = (rec.p() - current_center) / self.radius;
+        rec.set_face_normal(r, &outward_normal);
+        Self::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
+        rec.mat = self.mat.clone();
+    }
     fn random_to_sphere(radius: f64, distance_squared: f64)->Vec3{
-        let r1 = Vec3::random_double();
-        let r2 = Vec3::random_double();
+        let (r1, r2) = sampler::get_2d();
         let z = 1.0+r2*((1.0-radius*radius/distance_squared).sqrt()-1.0);
         let phi = 2.0*std::f64::consts::PI*r1;
         let x = phi.cos()*(1.0-z*z).sqrt();
@@ -83,12 +92,7 @@ impl Hittable for Sphere{
         }
 
        
-        rec.setT(root);
-        rec.setP(r.at(rec.t()));
-        let outward_normal: Vec3 = (rec.p() - current_center) / self.radius;
-        rec.set_face_normal(r, &outward_normal);
-        Self::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
-        rec.mat = self.mat.clone();
+        self.set_record(r, root, current_center, rec);
         
         return true
     }
@@ -97,7 +101,7 @@ impl Hittable for Sphere{
     }
     fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
         let mut rec = Hit_record::new(self.mat.clone());
-        if self.hit(&Ray::new(origin, direction),Interval::new(0.001,std::f64::INFINITY), &mut rec){
+        if !self.hit(&Ray::new(origin, direction),Interval::new(0.001,std::f64::INFINITY), &mut rec){
             return 0.0
         }
 
@@ -113,5 +117,24 @@ impl Hittable for Sphere{
         let uvw = ONB::new(direction);
         uvw.transform(Self::random_to_sphere(self.radius, distance_squared))
     }
+    // both roots at once instead of walking the hits
+    fn intervals(&self, r: &Ray, spans: &mut Vec<Span>){
+        let current_center: Vec3 = self.center.at(r.time());
+        let oc: Vec3 = current_center-r.origin();
+        let a: f64 = r.direction().length_squared();
+        let h: f64 = Vec3::dot(&r.direction(), oc);
+        let c: f64 = oc.length_squared()-self.radius*self.radius;
+
+        let discriminant: f64 = h*h-a*c;
+        if discriminant <= 0.0{
+            return
+        }
+        let sqrtd: f64 = discriminant.sqrt();
+        let mut enter = Hit_record::new(self.mat.clone());
+        let mut exit = Hit_record::new(self.mat.clone());
+        self.set_record(r, (h-sqrtd)/a, current_center, &mut enter);
+        self.set_record(r, (h+sqrtd)/a, current_center, &mut exit);
+        spans.push(Span{enter, exit});
+    }
 }
 
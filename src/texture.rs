@@ -68,9 +68,9 @@ impl Image_Texture{
 }
 
 impl Noise_Texture{
-    pub fn new(scale: f64)->Self{
+    pub fn new(scale: f64, seed: u64)->Self{
         Noise_Texture{
-            noise: Perlin::new(),
+            noise: Perlin::newseed(seed),
             scale: scale,
         }
     }
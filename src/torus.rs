@@ -0,0 +1,124 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::hittable::area_pdf_value as area_pdf_value;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::onb::ONB as ONB;
+
+use crate::poly;
+use crate::sampler;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// a tube of radius minor swept around a circle of radius major, which lies
+// in the plane through center facing along axis
+pub struct Torus{
+    center: Vec3,
+    major: f64,
+    minor: f64,
+    frame: ONB,
+    mat: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+impl Torus{
+    pub fn new(center: Vec3, axis: Vec3, major: f64, minor: f64, mat: Arc<dyn Material>)->Self{
+        let frame = ONB::new(axis);
+        let (major, minor) = (major.max(0.0), minor.max(0.0));
+        let n = frame.w();
+        let mut e = Vec3::enew();
+        for i in 0..3{
+            e[i] = major*(1.0-n[i]*n[i]).max(0.0).sqrt()+minor;
+        }
+        Self{
+            center,
+            major,
+            minor,
+            frame,
+            mat,
+            bbox: AABB::newi(center-e, center+e),
+        }
+    }
+    // u goes around the axis and v around the tube, starting on the outside
+    pub fn get_torus_uv(local: Vec3, major: f64, u: &mut f64, v: &mut f64){
+        let rho = (local.x()*local.x()+local.y()*local.y()).sqrt();
+        *u = (local.y().atan2(local.x())+PI)/(2.0*PI);
+        *v = (local.z().atan2(rho-major)+PI)/(2.0*PI);
+    }
+    // sampling takes both angles uniformly, which crowds points on the inside
+    // of the ring. this is the density per unit area that gives
+    fn area_density(&self, p: Vec3)->f64{
+        let local = self.frame.local(p-self.center);
+        let rho = (local.x()*local.x()+local.y()*local.y()).sqrt();
+        1.0/(4.0*PI*PI*self.minor*rho.max(1e-12))
+    }
+}
+
+impl Hittable for Torus{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let len = r.direction().length();
+        let d = self.frame.local(r.direction())/len;
+        let o = self.frame.local(r.origin()-self.center);
+
+        // clip to the bounding sphere and solve from where the ray enters it,
+        // which keeps the quartic's coefficients small
+        let bound = self.major+self.minor;
+        let b = o.dot(d);
+        let discriminant = b*b-(o.length_squared()-bound*bound);
+        if discriminant <= 0.0{
+            return false
+        }
+        let sqrtd = discriminant.sqrt();
+        let t_enter = (-b-sqrtd).max(ray_t.min()*len);
+        let t_exit = (-b+sqrtd).min(ray_t.max()*len);
+        if t_enter >= t_exit{
+            return false
+        }
+        let o = o+t_enter*d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + t d
+        let r2 = self.major*self.major;
+        let f = o.dot(d);
+        let g = o.length_squared()+r2-self.minor*self.minor;
+        let c = [
+            g*g-4.0*r2*(o.x()*o.x()+o.y()*o.y()),
+            4.0*f*g-8.0*r2*(o.x()*d.x()+o.y()*d.y()),
+            4.0*f*f+2.0*g-4.0*r2*(d.x()*d.x()+d.y()*d.y()),
+            4.0*f,
+            1.0,
+        ];
+        let Some(t) = poly::roots_in(&c, 0.0, t_exit-t_enter).into_iter()
+            .map(|t| (t+t_enter)/len)
+            .find(|&t| ray_t.surrounds(t)) else{
+            return false
+        };
+
+        let local = self.frame.local(r.at(t)-self.center);
+        let rho = (local.x()*local.x()+local.y()*local.y()).sqrt().max(1e-12);
+        // away from the nearest point on the ring
+        let normal = Vec3::new(local.x()*(1.0-self.major/rho), local.y()*(1.0-self.major/rho), local.z()).unit_vector();
+
+        rec.setT(t);
+        rec.setP(r.at(t));
+        rec.mat = self.mat.clone();
+        rec.set_face_normal(r, &self.frame.transform(normal));
+        Self::get_torus_uv(local, self.major, &mut rec.u, &mut rec.v);
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        area_pdf_value(self, origin, direction, self.mat.clone(), |p| self.area_density(p))
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        let (r1, r2) = sampler::get_2d();
+        let (phi, theta) = (2.0*PI*r1, 2.0*PI*r2);
+        let rho = self.major+self.minor*theta.cos();
+        let local = Vec3::new(rho*phi.cos(), rho*phi.sin(), self.minor*theta.sin());
+        self.center+self.frame.transform(local)-origin
+    }
+}
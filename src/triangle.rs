@@ -0,0 +1,249 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::bvh::BVH as BVH;
+use crate::bvh::BvhOptions as BvhOptions;
+
+use crate::sampler;
+use std::sync::Arc;
+
+// shared vertex data, normals and uvs are indexed the same way as positions
+pub struct MeshData{
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64,f64)>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+pub struct Triangle{
+    mesh: Arc<MeshData>,
+    face: usize,
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    area: f64,
+    bbox: AABB,
+}
+
+pub struct TriangleMesh{
+    mesh: Arc<MeshData>,
+    triangles: Vec<Arc<dyn Hittable>>,
+    area_cdf: Vec<f64>,
+    area: f64,
+    mat: Arc<dyn Material>,
+    bvh: Option<BVH>,
+    bbox: AABB,
+}
+
+impl MeshData{
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64,f64)>, indices: Vec<[usize; 3]>)->Self{
+        assert!(normals.is_empty() || normals.len() == positions.len(), "mesh needs one normal per vertex");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(), "mesh needs one uv per vertex");
+        assert!(indices.iter().flatten().all(|&i| i < positions.len()), "mesh index out of range");
+        Self{positions, normals, uvs, indices}
+    }
+    fn vertices(&self, face: usize)->(Vec3,Vec3,Vec3){
+        let [i0,i1,i2] = self.indices[face];
+        (self.positions[i0],self.positions[i1],self.positions[i2])
+    }
+    // uniform point on a face from two canonical samples
+    fn sample_point(&self, face: usize, r1: f64, r2: f64)->Vec3{
+        let (p0,p1,p2) = self.vertices(face);
+        let su0 = r1.sqrt();
+        let b0 = 1.0-su0;
+        let b1 = r2*su0;
+        b0*p0+b1*p1+(1.0-b0-b1)*p2
+    }
+}
+
+impl Triangle{
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, mat: Arc<dyn Material>)->Self{
+        let mesh = Arc::new(MeshData::new(vec![a,b,c], Vec::new(), Vec::new(), vec![[0,1,2]]));
+        Self::from_mesh(mesh, 0, mat)
+    }
+    pub fn from_mesh(mesh: Arc<MeshData>, face: usize, mat: Arc<dyn Material>)->Self{
+        let [i0,i1,i2] = mesh.indices[face];
+        let (p0,p1,p2) = (mesh.positions[i0],mesh.positions[i1],mesh.positions[i2]);
+
+        let n = Vec3::cross(&(p1-p0), p2-p0);
+        let area = 0.5*n.length();
+        let normal = if area > 0.0{
+            n / n.length()
+        }else{
+            Vec3::enew()
+        };
+
+        let bbox = AABB::newb(AABB::newi(p0,p1),AABB::newi(p0,p2));
+        Self{mesh, face, mat, normal, area, bbox}
+    }
+    pub fn area(&self)->f64{
+        self.area
+    }
+    fn vertices(&self)->(Vec3,Vec3,Vec3){
+        self.mesh.vertices(self.face)
+    }
+    fn uv(&self, b0: f64, b1: f64, b2: f64)->(f64,f64){
+        if self.mesh.uvs.is_empty(){
+            return (b1+b2, b2)
+        }
+        let [i0,i1,i2] = self.mesh.indices[self.face];
+        let (uv0,uv1,uv2) = (self.mesh.uvs[i0],self.mesh.uvs[i1],self.mesh.uvs[i2]);
+        (b0*uv0.0+b1*uv1.0+b2*uv2.0, b0*uv0.1+b1*uv1.1+b2*uv2.1)
+    }
+    fn shading_normal(&self, b0: f64, b1: f64, b2: f64)->Option<Vec3>{
+        if self.mesh.normals.is_empty(){
+            return None
+        }
+        let [i0,i1,i2] = self.mesh.indices[self.face];
+        let n = b0*self.mesh.normals[i0]+b1*self.mesh.normals[i1]+b2*self.mesh.normals[i2];
+        if n.near_zero(){
+            return None
+        }
+        Some(n.unit_vector())
+    }
+}
+
+impl TriangleMesh{
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64,f64)>, indices: Vec<[usize; 3]>, mat: Arc<dyn Material>)->Self{
+        Self::from_data(Arc::new(MeshData::new(positions, normals, uvs, indices)), mat)
+    }
+    pub fn from_data(mesh: Arc<MeshData>, mat: Arc<dyn Material>)->Self{
+        Self::from_data_with(mesh, mat, &BvhOptions::default())
+    }
+    pub fn from_data_with(mesh: Arc<MeshData>, mat: Arc<dyn Material>, bvh_options: &BvhOptions)->Self{
+        let mut triangles: Vec<Arc<dyn Hittable>> = Vec::with_capacity(mesh.indices.len());
+        let mut area_cdf = Vec::with_capacity(mesh.indices.len());
+        let mut area = 0.0;
+        let mut bbox = AABB::empty();
+
+        for face in 0..mesh.indices.len(){
+            let tri = Triangle::from_mesh(mesh.clone(), face, mat.clone());
+            area += tri.area();
+            area_cdf.push(area);
+            bbox = AABB::newb(bbox, tri.bounding_box());
+            triangles.push(Arc::new(tri));
+        }
+
+        let bvh = if triangles.is_empty(){
+            None
+        }else{
+            Some(BVH::with_options(triangles.clone(), bvh_options))
+        };
+
+        Self{mesh, triangles, area_cdf, area, mat, bvh, bbox}
+    }
+    pub fn mesh(&self)->&Arc<MeshData>{
+        &self.mesh
+    }
+    pub fn triangles(&self)->&Vec<Arc<dyn Hittable>>{
+        &self.triangles
+    }
+    pub fn area(&self)->f64{
+        self.area
+    }
+    pub fn bvh(&self)->Option<&BVH>{
+        self.bvh.as_ref()
+    }
+}
+
+impl Hittable for Triangle{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        // Moller-Trumbore
+        let (p0,p1,p2) = self.vertices();
+        let e1 = p1-p0;
+        let e2 = p2-p0;
+        let pvec = Vec3::cross(&r.direction(), e2);
+        let det = e1.dot(pvec);
+
+        if det.abs() < 1e-12{
+            return false
+        }
+
+        let inv_det = 1.0/det;
+        let tvec = r.origin()-p0;
+        let b1 = tvec.dot(pvec)*inv_det;
+        if !(0.0..=1.0).contains(&b1){
+            return false
+        }
+
+        let qvec = Vec3::cross(&tvec, e1);
+        let b2 = r.direction().dot(qvec)*inv_det;
+        if b2 < 0.0 || b1+b2 > 1.0{
+            return false
+        }
+
+        let t = e2.dot(qvec)*inv_det;
+        if !ray_t.surrounds(t){
+            return false
+        }
+
+        let b0 = 1.0-b1-b2;
+        rec.setT(t);
+        rec.setP(b0*p0+b1*p1+b2*p2);
+        rec.set_face_normal(r, &self.normal);
+
+        if let Some(ns) = self.shading_normal(b0, b1, b2){
+            // keep the interpolated normal on the same side as the geometry
+            let ns = if ns.dot(self.normal) < 0.0{ -ns }else{ ns };
+            rec.normal = if rec.front_face{ ns }else{ -ns };
+        }
+
+        (rec.u, rec.v) = self.uv(b0, b1, b2);
+        rec.mat = self.mat.clone();
+
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        let mut rec = Hit_record::new(self.mat.clone());
+        if !self.hit(&Ray::new(origin,direction),Interval::new(0.001,f64::INFINITY), &mut rec){
+            return 0.0
+        }
+
+        let distance_squared = rec.t()*rec.t()*direction.length_squared();
+        let cosine = f64::abs(direction.dot(self.normal)) / direction.length();
+        distance_squared/(cosine*self.area)
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        let (r1, r2) = sampler::get_2d();
+        self.mesh.sample_point(self.face, r1, r2)-origin
+    }
+}
+
+impl Hittable for TriangleMesh{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        match &self.bvh{
+            Some(bvh)=>bvh.hit(r, ray_t, rec),
+            None=>false,
+        }
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3)->f64{
+        let mut rec = Hit_record::new(self.mat.clone());
+        if self.area <= 0.0 || !self.hit(&Ray::new(origin,direction),Interval::new(0.001,f64::INFINITY), &mut rec){
+            return 0.0
+        }
+
+        let distance_squared = rec.t()*rec.t()*direction.length_squared();
+        let cosine = f64::abs(direction.dot(rec.normal())) / direction.length();
+        distance_squared/(cosine*self.area)
+    }
+    fn random(&self, origin: Vec3)->Vec3{
+        if self.triangles.is_empty(){
+            return Vec3::new(1.0,0.0,0.0)
+        }
+
+        // pick a face proportional to its area, then a uniform point on it
+        let target = sampler::get_1d()*self.area;
+        let face = self.area_cdf.partition_point(|&a| a <= target).min(self.area_cdf.len()-1);
+        let (r1, r2) = sampler::get_2d();
+        self.mesh.sample_point(face, r1, r2)-origin
+    }
+}
@@ -1,5 +1,5 @@
 use std::ops::{Add, Sub, Neg, Mul, Div, AddAssign, DivAssign, MulAssign, Index, IndexMut};
-use rand::Rng; 
+use crate::sampler;
 
 use crate::aabb::AABB as AABB;
 
@@ -46,11 +46,18 @@ impl Vec3 {
     pub fn dot(&self, other: Vec3)->f64{
         self.e[0]*other.e[0]+self.e[1]*other.e[1]+self.e[2]*other.e[2]
     }
+    // component-wise minimum and maximum
+    pub fn min(&self, other: Vec3)->Vec3{
+        Vec3::new(self.e[0].min(other.e[0]), self.e[1].min(other.e[1]), self.e[2].min(other.e[2]))
+    }
+    pub fn max(&self, other: Vec3)->Vec3{
+        Vec3::new(self.e[0].max(other.e[0]), self.e[1].max(other.e[1]), self.e[2].max(other.e[2]))
+    }
     pub fn random_double()->f64{
-        rand::thread_rng().gen_range(0.0..1.0)
+        sampler::get_1d()
     }
     pub fn random_between(min: f64, max: f64)->f64{
-        rand::thread_rng().gen_range(min..max)
+        min + (max-min)*sampler::get_1d()
     }
     pub fn random()->Vec3{
         Vec3::new(Self::random_double(),Self::random_double(),Self::random_double())
@@ -62,13 +69,11 @@ impl Vec3 {
         *self / self.length()
     }
     pub fn random_unit_vector()->Vec3{
-        loop {
-            let p: Vec3 = Self::random_vars(-1.0,1.0);
-            let lensq: f64 = p.length_squared();
-            if 1e-160 < lensq && lensq <= 1.0{
-                return p / lensq.sqrt()
-            }
-        }
+        let (r1, r2) = sampler::get_2d();
+        let z = 1.0-2.0*r1;
+        let r = (1.0-z*z).max(0.0).sqrt();
+        let phi = 2.0*std::f64::consts::PI*r2;
+        Vec3::new(r*phi.cos(), r*phi.sin(), z)
     }
     pub fn random_on_hemisphere(normal: &Vec3)->Vec3{
         let on_unit_sphere: Vec3 = Self::random_unit_vector();
@@ -91,17 +96,23 @@ impl Vec3 {
         let r_out_parallel: Vec3 = -((1.0-r_out_perp.length_squared()).abs().sqrt() * n);
         r_out_perp+r_out_parallel
     }
+    // concentric mapping keeps the sampler's stratification on the disk
     pub fn random_in_unit_disk()->Vec3{
-        loop{
-            let p: Vec3 = Vec3::new(Self::random_between(-1.0,1.0), Self::random_between(-1.0,1.0),0.0);
-            if p.length_squared() < 1.0{
-                return p
-            }
+        let (r1, r2) = sampler::get_2d();
+        let (a, b) = (2.0*r1-1.0, 2.0*r2-1.0);
+        if a == 0.0 && b == 0.0{
+            return Vec3::enew()
         }
+        let quarter_pi = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs(){
+            (a, quarter_pi*(b/a))
+        }else{
+            (b, 2.0*quarter_pi-quarter_pi*(a/b))
+        };
+        Vec3::new(r*theta.cos(), r*theta.sin(), 0.0)
     }
     pub fn random_cosine_direction()->Vec3{
-        let r1 = Self::random_double();
-        let r2 = Self::random_double();
+        let (r1, r2) = sampler::get_2d();
 
         let phi = 2.0*std::f64::consts::PI*r1;
         let x = phi.cos()*r2.sqrt();
@@ -0,0 +1,238 @@
+use crate::hittable::Hittable as Hittable;
+use crate::hittable::Hit_record as Hit_record;
+use crate::vec3::Vec3 as Vec3;
+use crate::material::Material as Material;
+use crate::aabb::AABB as AABB;
+use crate::ray::Ray as Ray;
+use crate::interval::Interval as Interval;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// voxels per side of a brick in a sparse grid
+const BRICK: usize = 8;
+
+// palette indices of the cells, 0 for empty
+enum Storage{
+    Dense(Vec<u16>),
+    // only bricks with something in them are stored
+    Sparse(HashMap<[usize; 3], Box<[u16; BRICK*BRICK*BRICK]>>),
+}
+
+// a box of dims cubes of side size from origin, each empty or filled with
+// one of the palette's materials. rays step from cell to cell, and a surface
+// is wherever the material changes, so faces between two cells of the same
+// material are never seen and glass blocks refract as one piece
+pub struct VoxelGrid{
+    origin: Vec3,
+    size: f64,
+    dims: [usize; 3],
+    storage: Storage,
+    palette: Vec<Arc<dyn Material>>,
+    // the filled cells, lo inclusive and hi exclusive
+    lo: [usize; 3],
+    hi: [usize; 3],
+    bbox: AABB,
+}
+
+impl VoxelGrid{
+    // palette index n is palette[n-1]
+    pub fn new(origin: Vec3, size: f64, dims: [usize; 3], palette: Vec<Arc<dyn Material>>)->Self{
+        Self::with_storage(origin, size, dims, palette, Storage::Dense(vec![0; dims[0]*dims[1]*dims[2]]))
+    }
+    // the same, keeping only the 8x8x8 bricks that have something in them
+    pub fn sparse(origin: Vec3, size: f64, dims: [usize; 3], palette: Vec<Arc<dyn Material>>)->Self{
+        Self::with_storage(origin, size, dims, palette, Storage::Sparse(HashMap::new()))
+    }
+    fn with_storage(origin: Vec3, size: f64, dims: [usize; 3], palette: Vec<Arc<dyn Material>>, storage: Storage)->Self{
+        assert!(size > 0.0, "voxel size must be positive");
+        assert!(palette.len() < u16::MAX as usize, "too many voxel materials");
+        Self{origin, size, dims, storage, palette, lo: dims, hi: [0; 3], bbox: AABB::empty()}
+    }
+    pub fn dims(&self)->[usize; 3]{
+        self.dims
+    }
+    // true until a cell is filled
+    pub fn is_empty(&self)->bool{
+        (0..3).any(|a| self.lo[a] >= self.hi[a])
+    }
+    pub fn get(&self, cell: [usize; 3])->u16{
+        if (0..3).any(|a| cell[a] >= self.dims[a]){
+            return 0
+        }
+        match &self.storage{
+            Storage::Dense(cells)=>cells[self.index(cell)],
+            Storage::Sparse(bricks)=>{
+                let (brick, offset) = Self::brick_of(cell);
+                bricks.get(&brick).map_or(0, |b| b[offset])
+            }
+        }
+    }
+    // cells outside the grid are ignored
+    pub fn set(&mut self, cell: [usize; 3], value: u16){
+        assert!((value as usize) <= self.palette.len(), "voxel material out of range");
+        if (0..3).any(|a| cell[a] >= self.dims[a]){
+            return
+        }
+        match &mut self.storage{
+            Storage::Dense(cells)=>{
+                let i = cell[0]+self.dims[0]*(cell[1]+self.dims[1]*cell[2]);
+                cells[i] = value;
+            }
+            Storage::Sparse(bricks)=>{
+                let (brick, offset) = Self::brick_of(cell);
+                if value != 0{
+                    bricks.entry(brick).or_insert_with(|| Box::new([0; BRICK*BRICK*BRICK]))[offset] = value;
+                }else if let Some(b) = bricks.get_mut(&brick){
+                    b[offset] = 0;
+                }
+            }
+        }
+        // clearing cells leaves the bounds as they were, which is still safe
+        if value != 0{
+            for (a, &c) in cell.iter().enumerate(){
+                self.lo[a] = self.lo[a].min(c);
+                self.hi[a] = self.hi[a].max(c+1);
+            }
+            let corner = |c: [usize; 3]| self.origin+self.size*Vec3::new(c[0] as f64, c[1] as f64, c[2] as f64);
+            self.bbox = AABB::newi(corner(self.lo), corner(self.hi));
+        }
+    }
+    // fills the cells from lo to hi, both inclusive
+    pub fn fill(&mut self, lo: [usize; 3], hi: [usize; 3], value: u16){
+        for k in lo[2]..=hi[2].min(self.dims[2].saturating_sub(1)){
+            for j in lo[1]..=hi[1].min(self.dims[1].saturating_sub(1)){
+                for i in lo[0]..=hi[0].min(self.dims[0].saturating_sub(1)){
+                    self.set([i, j, k], value);
+                }
+            }
+        }
+    }
+    fn index(&self, cell: [usize; 3])->usize{
+        cell[0]+self.dims[0]*(cell[1]+self.dims[1]*cell[2])
+    }
+    fn brick_of(cell: [usize; 3])->([usize; 3], usize){
+        let brick = cell.map(|c| c/BRICK);
+        let [i, j, k] = cell.map(|c| c%BRICK);
+        (brick, i+BRICK*(j+BRICK*k))
+    }
+    fn material(&self, value: u16)->Arc<dyn Material>{
+        self.palette[value as usize-1].clone()
+    }
+    // texture coordinates across one face of a cell, as seen from outside
+    // with y up on the sides and -z up on the top
+    fn face_uv(local: Vec3, axis: usize, outward: f64)->(f64, f64){
+        let f = |x: f64| x.clamp(0.0, 1.0);
+        match (axis, outward > 0.0){
+            (0, true)=>(1.0-f(local.z()), f(local.y())),
+            (0, false)=>(f(local.z()), f(local.y())),
+            (1, true)=>(f(local.x()), 1.0-f(local.z())),
+            (1, false)=>(f(local.x()), f(local.z())),
+            (_, true)=>(f(local.x()), f(local.y())),
+            (_, false)=>(1.0-f(local.x()), f(local.y())),
+        }
+    }
+}
+
+impl Hittable for VoxelGrid{
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut Hit_record)->bool{
+        let mut span = ray_t;
+        if !self.bbox.hit(*r, &mut span){
+            return false
+        }
+        // in cell units, t means the same as for r
+        let o = (r.origin()-self.origin)/self.size;
+        let d = r.direction()/self.size;
+        let entered = span.min() > ray_t.min();
+
+        // Amanatides and Woo: the t of the next boundary on each axis, and
+        // the t between boundaries
+        let start = o+span.min()*d;
+        let mut cell = [0usize; 3];
+        let mut step = [0i64; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        // the axis of the box face the ray came in through
+        let mut axis = 0;
+        let mut latest = f64::NEG_INFINITY;
+        for a in 0..3{
+            cell[a] = (start[a].floor().max(self.lo[a] as f64) as usize).min(self.hi[a]-1);
+            if d[a] > 0.0{
+                step[a] = 1;
+                next[a] = ((cell[a]+1) as f64-o[a])/d[a];
+                delta[a] = 1.0/d[a];
+            }else if d[a] < 0.0{
+                step[a] = -1;
+                next[a] = (cell[a] as f64-o[a])/d[a];
+                delta[a] = -1.0/d[a];
+            }
+            if d[a] != 0.0{
+                let face = if d[a] > 0.0 { self.lo[a] } else { self.hi[a] };
+                let t = (face as f64-o[a])/d[a];
+                if t > latest{
+                    latest = t;
+                    axis = a;
+                }
+            }
+        }
+
+        let mut current = self.get(cell);
+        let mut t = span.min();
+        let mut value = current;
+        // the cell on the other side of the face that was hit
+        let mut from = cell;
+        // a filled cell right where the ray comes into the box is hit on the
+        // box's face
+        let at_entry = entered && current != 0;
+        if !at_entry{
+            loop{
+                axis = if next[0] < next[1] && next[0] < next[2] { 0 } else if next[1] < next[2] { 1 } else { 2 };
+                t = next[axis];
+                if t > ray_t.max(){
+                    return false
+                }
+                next[axis] += delta[axis];
+                from = cell;
+                let c = cell[axis] as i64+step[axis];
+                // past the filled bounds everything is empty, which also ends
+                // the walk
+                let outside = c < self.lo[axis] as i64 || c >= self.hi[axis] as i64;
+                if !outside{
+                    cell[axis] = c as usize;
+                }
+                value = if outside { 0 } else { self.get(cell) };
+                if value != current && ray_t.surrounds(t){
+                    break
+                }
+                if outside{
+                    return false
+                }
+                current = value;
+            }
+        }
+
+        // the face between the two cells, facing out of the one being
+        // entered or, leaving into empty space, out of the one left behind
+        let (solid, face_cell, outward) = if value != 0{
+            (value, cell, -(step[axis] as f64))
+        }else{
+            (current, from, step[axis] as f64)
+        };
+        let mut normal = Vec3::enew();
+        normal[axis] = outward;
+
+        let p = r.at(t);
+        let local = o+t*d-Vec3::new(face_cell[0] as f64, face_cell[1] as f64, face_cell[2] as f64);
+
+        rec.setT(t);
+        rec.setP(p);
+        rec.mat = self.material(solid);
+        rec.set_face_normal(r, &normal);
+        (rec.u, rec.v) = Self::face_uv(local, axis, outward);
+        true
+    }
+    fn bounding_box(&self)->AABB{
+        self.bbox
+    }
+}
+
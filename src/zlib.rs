@@ -0,0 +1,328 @@
+// minimal zlib stream writer: LZ77 with hash chains and the fixed deflate
+// huffman codes, enough for PNG and EXR output without an extra dependency
+
+const WINDOW_SIZE: usize = 32768;
+const HASH_BITS: usize = 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASE: [u16; 29] = [3,4,5,6,7,8,9,10,11,13,15,17,19,23,27,31,35,43,51,59,67,83,99,115,131,163,195,227,258];
+const LENGTH_EXTRA: [u8; 29] = [0,0,0,0,0,0,0,0,1,1,1,1,2,2,2,2,3,3,3,3,4,4,4,4,5,5,5,5,0];
+const DIST_BASE: [u16; 30] = [1,2,3,4,5,7,9,13,17,25,33,49,65,97,129,193,257,385,513,769,1025,1537,2049,3073,4097,6145,8193,12289,16385,24577];
+const DIST_EXTRA: [u8; 30] = [0,0,0,0,1,1,2,2,3,3,4,4,5,5,6,6,7,7,8,8,9,9,10,10,11,11,12,12,13,13];
+
+struct BitWriter{
+    out: Vec<u8>,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl BitWriter{
+    fn new(out: Vec<u8>)->Self{
+        Self{out, bit_buf: 0, bit_count: 0}
+    }
+    // deflate packs bits starting from the least significant one
+    fn write_bits(&mut self, bits: u32, count: u32){
+        self.bit_buf |= bits << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8{
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+    // huffman codes are stored most significant bit first
+    fn write_code(&mut self, code: u32, len: u32){
+        let mut reversed = 0;
+        for i in 0..len{
+            reversed |= ((code >> i) & 1) << (len-1-i);
+        }
+        self.write_bits(reversed, len);
+    }
+    fn finish(mut self)->Vec<u8>{
+        if self.bit_count > 0{
+            self.out.push(self.bit_buf as u8);
+        }
+        self.out
+    }
+}
+
+fn write_literal(w: &mut BitWriter, symbol: u32){
+    match symbol{
+        0..=143=>w.write_code(0x30+symbol, 8),
+        144..=255=>w.write_code(0x190+symbol-144, 9),
+        256..=279=>w.write_code(symbol-256, 7),
+        _=>w.write_code(0xC0+symbol-280, 8),
+    }
+}
+
+fn write_match(w: &mut BitWriter, length: usize, distance: usize){
+    let li = LENGTH_BASE.iter().rposition(|&b| b as usize <= length).unwrap();
+    write_literal(w, 257+li as u32);
+    w.write_bits((length-LENGTH_BASE[li] as usize) as u32, LENGTH_EXTRA[li] as u32);
+
+    let di = DIST_BASE.iter().rposition(|&b| b as usize <= distance).unwrap();
+    w.write_code(di as u32, 5);
+    w.write_bits((distance-DIST_BASE[di] as usize) as u32, DIST_EXTRA[di] as u32);
+}
+
+fn hash(data: &[u8], i: usize)->usize{
+    let v = (data[i] as u32) << 16 | (data[i+1] as u32) << 8 | data[i+2] as u32;
+    (v.wrapping_mul(2654435761) >> (32-HASH_BITS)) as usize
+}
+
+pub fn adler32(data: &[u8])->u32{
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for chunk in data.chunks(5552){
+        for &byte in chunk{
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// raw deflate data as a single fixed-huffman block
+pub fn deflate(data: &[u8], out: Vec<u8>)->Vec<u8>{
+    let mut w = BitWriter::new(out);
+    w.write_bits(1, 1);
+    w.write_bits(1, 2);
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let insert = |head: &mut Vec<usize>, prev: &mut Vec<usize>, i: usize|{
+        if i+MIN_MATCH <= data.len(){
+            let h = hash(data, i);
+            prev[i % WINDOW_SIZE] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len(){
+        let mut best_len = 0;
+        let mut best_dist = 0;
+
+        if i+MIN_MATCH <= data.len(){
+            let max_len = MAX_MATCH.min(data.len()-i);
+            let mut candidate = head[hash(data, i)];
+            let mut chain = 0;
+            while candidate != usize::MAX && i-candidate <= WINDOW_SIZE && chain < MAX_CHAIN{
+                let mut len = 0;
+                while len < max_len && data[candidate+len] == data[i+len]{
+                    len += 1;
+                }
+                if len > best_len{
+                    best_len = len;
+                    best_dist = i-candidate;
+                    if len == max_len{
+                        break
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                if next == usize::MAX || next >= candidate{
+                    break
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH{
+            write_match(&mut w, best_len, best_dist);
+            for j in i..i+best_len{
+                insert(&mut head, &mut prev, j);
+            }
+            i += best_len;
+        }else{
+            write_literal(&mut w, data[i] as u32);
+            insert(&mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+
+    write_literal(&mut w, 256);
+    w.finish()
+}
+
+// zlib wrapper (RFC 1950) around the deflate stream
+pub fn compress(data: &[u8])->Vec<u8>{
+    let mut out = deflate(data, vec![0x78, 0x9C]);
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+pub mod tests{
+    use super::*;
+    use crate::rng::Pcg32 as Pcg32;
+
+    struct BitReader<'a>{
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_>{
+        fn bit(&mut self)->u32{
+            let bit = (self.data[self.pos/8] >> (self.pos%8)) & 1;
+            self.pos += 1;
+            bit as u32
+        }
+        fn bits(&mut self, count: u32)->u32{
+            (0..count).fold(0, |v, i| v | self.bit() << i)
+        }
+    }
+
+    // canonical huffman decoding one bit at a time, as in zlib's puff
+    struct Huffman{
+        counts: Vec<u16>,
+        symbols: Vec<u16>,
+    }
+
+    impl Huffman{
+        fn new(lengths: &[u8])->Self{
+            let mut counts = vec![0; 16];
+            for &l in lengths{
+                counts[l as usize] += 1;
+            }
+            counts[0] = 0;
+            let mut offsets = [0; 16];
+            for l in 1..15{
+                offsets[l+1] = offsets[l]+counts[l];
+            }
+            let mut symbols = vec![0; lengths.len()];
+            for (s, &l) in lengths.iter().enumerate(){
+                if l != 0{
+                    symbols[offsets[l as usize] as usize] = s as u16;
+                    offsets[l as usize] += 1;
+                }
+            }
+            Self{counts, symbols}
+        }
+        fn decode(&self, r: &mut BitReader)->u16{
+            let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+            for len in 1..16{
+                code |= r.bit() as i32;
+                let count = self.counts[len] as i32;
+                if code-first < count{
+                    return self.symbols[(index+code-first) as usize]
+                }
+                index += count;
+                first = (first+count) << 1;
+                code <<= 1;
+            }
+            panic!("invalid huffman code")
+        }
+    }
+
+    // stored and fixed huffman blocks, all the writer produces
+    pub fn inflate(data: &[u8])->Vec<u8>{
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        let literals = Huffman::new(&lengths);
+        let distances = Huffman::new(&[5; 30]);
+
+        let mut r = BitReader{data, pos: 0};
+        let mut out = Vec::new();
+        loop{
+            let last = r.bit();
+            match r.bits(2){
+                0=>{
+                    r.pos = r.pos.div_ceil(8)*8;
+                    let len = r.bits(16) as usize;
+                    assert_eq!(r.bits(16) as usize, !len & 0xFFFF);
+                    let start = r.pos/8;
+                    out.extend_from_slice(&data[start..start+len]);
+                    r.pos += len*8;
+                }
+                1=>loop{
+                    let symbol = literals.decode(&mut r) as usize;
+                    if symbol < 256{
+                        out.push(symbol as u8);
+                        continue
+                    }
+                    if symbol == 256{
+                        break
+                    }
+                    let li = symbol-257;
+                    let length = LENGTH_BASE[li] as usize+r.bits(LENGTH_EXTRA[li] as u32) as usize;
+                    let di = distances.decode(&mut r) as usize;
+                    let distance = DIST_BASE[di] as usize+r.bits(DIST_EXTRA[di] as u32) as usize;
+                    assert!(distance <= out.len(), "distance past the start of the output");
+                    for _ in 0..length{
+                        out.push(out[out.len()-distance]);
+                    }
+                }
+                kind=>panic!("unexpected block type {}", kind),
+            }
+            if last == 1{
+                return out
+            }
+        }
+    }
+
+    // checks the zlib header and adler32 trailer around the deflate data
+    pub fn decompress(data: &[u8])->Vec<u8>{
+        assert_eq!(data[0] & 0x0F, 8, "not deflate");
+        assert_eq!((data[0] as u16*256+data[1] as u16) % 31, 0, "bad header check");
+        let out = inflate(&data[2..data.len()-4]);
+        assert_eq!(adler32(&out).to_be_bytes(), data[data.len()-4..]);
+        out
+    }
+
+    fn round_trip(data: &[u8]){
+        assert_eq!(decompress(&compress(data)), data);
+    }
+
+    #[test]
+    fn round_trips_empty_and_short_input(){
+        round_trip(&[]);
+        round_trip(&[0]);
+        round_trip(b"ab");
+        round_trip(b"abcabcabcabc");
+    }
+
+    #[test]
+    fn round_trips_random_bytes(){
+        let mut rng = Pcg32::new(1, 0);
+        let data: Vec<u8> = (0..100_000).map(|_| rng.next_u32() as u8).collect();
+        round_trip(&data);
+    }
+
+    #[test]
+    fn round_trips_long_runs_and_far_matches(){
+        let mut rng = Pcg32::new(2, 0);
+        let mut data = vec![7u8; 5000];
+        // a block repeated past the window, and short repeats from a small alphabet
+        let block: Vec<u8> = (0..40_000).map(|_| rng.next_u32() as u8).collect();
+        data.extend_from_slice(&block);
+        data.extend_from_slice(&block);
+        data.extend((0..50_000).map(|_| b"xyz"[rng.below(3)]));
+        let packed = compress(&data);
+        assert!(packed.len() < data.len(), "repetitive data should shrink");
+        assert_eq!(decompress(&packed), data);
+    }
+
+    #[test]
+    fn adler32_matches_known_values(){
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        // long enough for the sums to be reduced
+        assert_eq!(adler32(&vec![255u8; 100_000]), adler32_slow(&vec![255u8; 100_000]));
+    }
+
+    fn adler32_slow(data: &[u8])->u32{
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in data{
+            a = (a+byte as u64) % 65521;
+            b = (b+a) % 65521;
+        }
+        ((b << 16) | a) as u32
+    }
+}